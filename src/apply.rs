@@ -0,0 +1,807 @@
+use std::collections::BTreeSet;
+
+use serde_json::{Map, Value};
+
+use crate::health::HealthCheck;
+use crate::init_file::{
+    apply_cluster_settings, get_optional_json_string, parse_init_config_json, parse_load_balancers,
+    parse_minikube_mounts, parse_port_forwards, parse_socat_tunnels, LoadBalancerConfig,
+    MinikubeMountConfig, PortForwardConfig, SocatTunnelConfig,
+};
+use crate::load_balancer::{
+    create_load_balancer, delete_load_balancer, list_load_balancers, listed_load_balancer_config,
+    load_balancer_config, load_balancer_id,
+};
+use crate::minikube_mount::{
+    create_minikube_mount, delete_minikube_mount_entry, fetch_minikube_mount_processes,
+    MINIKUBE_MOUNT_KIND,
+};
+use crate::minikube_tunnel::set_bind_address;
+use crate::port_forward::{create_port_forward, delete_port_forward_entry, PORT_FORWARD_KIND};
+use crate::registry::fetch_registered_processes;
+use crate::socat_tunnel::{
+    create_socat_tunnel, delete_socat_tunnel_entry, set_default_connect_host, TunnelEngine,
+    SOCAT_TUNNEL_KIND,
+};
+use crate::CommandResultType::*;
+use crate::{
+    flush_output, print_results, CommandExecutionResult, Error, OptionFunc, Resource, Session,
+};
+
+pub fn build_apply_init_file_option(
+    init_file_path: &str,
+) -> Result<(String, OptionFunc, bool), Error> {
+    let init_file_path = init_file_path.to_string();
+
+    Ok((
+        String::from("Apply initialization file"),
+        Box::new(move |session| apply_init_file(session, &init_file_path)),
+        false,
+    ))
+}
+
+/// What applying the initialization file does to a resource.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PlanAction {
+    /// The resource is configured but does not exist.
+    Create,
+    /// The resource exists as configured.
+    Keep,
+    /// The resource exists but is no longer configured.
+    Delete,
+    /// The resource exists with a configuration other than the configured one.
+    Recreate,
+}
+
+impl PlanAction {
+    fn name(&self) -> &'static str {
+        match self {
+            PlanAction::Create => "create",
+            PlanAction::Keep => "keep",
+            PlanAction::Delete => "delete",
+            PlanAction::Recreate => "recreate",
+        }
+    }
+}
+
+/// A step of the plan that makes the resources match the initialization file.
+struct PlanStep {
+    action: PlanAction,
+    kind: &'static str,
+    /// Identifies the resource among those of its kind, eg, the listening port of a socat tunnel.
+    id: String,
+    /// The configuration of the resource, as configured, or as it exists when it is deleted.
+    config: String,
+    /// The configuration of the resource as it exists, when it is recreated.
+    previous_config: Option<String>,
+    delete: Option<OptionFunc>,
+    create: Option<OptionFunc>,
+}
+
+impl PlanStep {
+    fn describe(&self) -> Resource {
+        let mut description = format!(
+            "{} {} {}",
+            self.action.name(),
+            kind_name(self.kind),
+            self.id
+        );
+        if !self.config.is_empty() {
+            description.push_str(&format!(" ({}", self.config));
+            if let Some(previous_config) = &self.previous_config {
+                description.push_str(&format!(", was {previous_config}"));
+            }
+            description.push(')');
+        }
+
+        let resource = Resource::new(self.kind, description)
+            .field("action", self.action.name())
+            .field("id", self.id.as_str())
+            .field("config", self.config.as_str());
+
+        match &self.previous_config {
+            Some(previous_config) => resource.field("previousConfig", previous_config.as_str()),
+            None => resource,
+        }
+    }
+}
+
+/// A resource, either as configured in the initialization file, along with how to create it, or as
+/// it exists, along with how to delete it.
+struct PlannedResource {
+    id: String,
+    config: String,
+    func: OptionFunc,
+}
+
+/// Makes the resources match the initialization file, by creating the configured resources that do
+/// not exist, deleting the existing resources that are no longer configured and recreating those
+/// whose configuration has changed, after printing the plan of what is about to be done.
+/// Only the kinds of resources with a section in the initialization file are managed, so that an
+/// absent section leaves its resources alone, whereas an empty one deletes them.
+pub fn apply_init_file(session: &Session, init_file_path: &str) -> CommandExecutionResult {
+    let init_config = parse_init_config_json(init_file_path)?;
+
+    apply_cluster_settings(session, &init_config)?;
+
+    apply_init_config(session, init_file_path, &init_config, None, &mut |result| {
+        print_results(session, result, true, true)
+    })
+}
+
+/// Applies a parsed initialization file and hands the plan, followed by the result of each of its
+/// steps, to `report`, as they come.
+/// When the previous version of the file is given, only the resources that it configures are
+/// deleted, so that the resources created otherwise, eg, from the menu, are left alone.
+pub fn apply_init_config(
+    session: &Session,
+    init_file_path: &str,
+    init_config: &Map<String, Value>,
+    previous_init_config: Option<&Map<String, Value>>,
+    report: &mut dyn FnMut(CommandExecutionResult),
+) -> CommandExecutionResult {
+    // The connect host of the socat tunnels is compared once the default one has been set.
+    if let Some(default_socat_connect_host) =
+        get_optional_json_string(init_config, "defaultSocatConnectHost")?
+        && default_socat_connect_host != session.default_connect_host()
+    {
+        report(Ok(PrintableResults(
+            None,
+            vec![set_default_connect_host(
+                session,
+                default_socat_connect_host,
+            )],
+        )));
+    }
+
+    let mut plan: Vec<PlanStep> = Vec::new();
+
+    plan.extend(plan_kind(
+        session,
+        "loadBalancer",
+        (init_config, previous_init_config),
+        parse_load_balancers,
+        desired_load_balancers,
+        actual_load_balancers,
+    )?);
+    plan.extend(plan_kind(
+        session,
+        SOCAT_TUNNEL_KIND,
+        (init_config, previous_init_config),
+        parse_socat_tunnels,
+        desired_socat_tunnels,
+        actual_socat_tunnels,
+    )?);
+    plan.extend(plan_kind(
+        session,
+        PORT_FORWARD_KIND,
+        (init_config, previous_init_config),
+        parse_port_forwards,
+        desired_port_forwards,
+        actual_port_forwards,
+    )?);
+    plan.extend(plan_kind(
+        session,
+        MINIKUBE_MOUNT_KIND,
+        (init_config, previous_init_config),
+        parse_minikube_mounts,
+        desired_minikube_mounts,
+        actual_minikube_mounts,
+    )?);
+
+    report(Ok(Resources(
+        Some(format!("Plan for {init_file_path}:")),
+        plan.iter().map(PlanStep::describe).collect(),
+    )));
+
+    // Deleting first frees the ports and paths of the resources that are recreated.
+    for func in plan.iter().filter_map(|x| x.delete.as_ref()) {
+        report(func(session));
+    }
+    for func in plan.iter().filter_map(|x| x.create.as_ref()) {
+        report(func(session));
+    }
+    flush_output();
+
+    if let Some(default_minikube_tunnel_bind_address) =
+        get_optional_json_string(init_config, "minikubeTunnelBindAddress")?
+    {
+        report(set_bind_address(
+            session,
+            default_minikube_tunnel_bind_address,
+        ));
+    }
+
+    let count = |action: PlanAction| plan.iter().filter(|x| x.action == action).count();
+
+    Ok(PrintableResults(
+        None,
+        vec![format!(
+            "Applied {init_file_path}: {} created, {} recreated, {} deleted, {} kept",
+            count(PlanAction::Create),
+            count(PlanAction::Recreate),
+            count(PlanAction::Delete),
+            count(PlanAction::Keep),
+        )],
+    ))
+}
+
+/// Parses a section of the initialization file, which is `None` when the section is absent.
+type ParseSection<T> = fn(&Map<String, Value>) -> Result<Option<Vec<T>>, Error>;
+
+/// Plans a kind of resources, if the initialization file, or its previous version, has a section
+/// for it.
+fn plan_kind<T>(
+    session: &Session,
+    kind: &'static str,
+    (init_config, previous_init_config): (&Map<String, Value>, Option<&Map<String, Value>>),
+    parse: ParseSection<T>,
+    desired: fn(&Session, Vec<T>) -> Vec<PlannedResource>,
+    actual: fn(&Session) -> Result<Vec<PlannedResource>, Error>,
+) -> Result<Vec<PlanStep>, Error> {
+    let configs = parse(init_config)?;
+
+    // The ids of the existing resources that may be deleted, or `None` for all of them.
+    let deletable: Option<BTreeSet<String>> = match previous_init_config {
+        Some(previous_init_config) => Some(
+            desired(session, parse(previous_init_config)?.unwrap_or_default())
+                .into_iter()
+                .map(|x| x.id)
+                .collect(),
+        ),
+        None => None,
+    };
+
+    if configs.is_none() && deletable.as_ref().is_none_or(BTreeSet::is_empty) {
+        return Ok(Vec::new());
+    }
+
+    let desired = desired(session, configs.unwrap_or_default());
+    let actual = actual(session)?
+        .into_iter()
+        .filter(|x| {
+            deletable.as_ref().is_none_or(|ids| ids.contains(&x.id))
+                || desired.iter().any(|y| y.id == x.id)
+        })
+        .collect();
+
+    Ok(diff(kind, desired, actual))
+}
+
+/// The configured load balancers, which are identified by their namespace and service name.
+/// A load balancer whose exposed resource, ports, labels or annotations change is recreated, unless
+/// its service is named after the name and ports of the exposed resource and the change renames it,
+/// in which case the service under the old name is deleted and one is created under the new name.
+fn desired_load_balancers(
+    _session: &Session,
+    load_balancers: Vec<LoadBalancerConfig>,
+) -> Vec<PlannedResource> {
+    load_balancers
+        .into_iter()
+        .map(
+            |LoadBalancerConfig {
+                 namespace,
+                 resource_type,
+                 name,
+                 ports,
+                 service_name,
+                 metadata,
+             }| PlannedResource {
+                id: format!("{namespace}/{service_name}"),
+                config: load_balancer_config(&resource_type, &name, &ports, &metadata),
+                func: Box::new(move |session| {
+                    create_load_balancer(
+                        session,
+                        &namespace,
+                        &resource_type,
+                        &name,
+                        &ports,
+                        &metadata,
+                    )
+                }),
+            },
+        )
+        .collect()
+}
+
+fn actual_load_balancers(session: &Session) -> Result<Vec<PlannedResource>, Error> {
+    Ok(list_load_balancers(session)?
+        .iter()
+        .map(|x| {
+            let (namespace, name) = load_balancer_id(x);
+
+            PlannedResource {
+                id: format!("{namespace}/{name}"),
+                config: listed_load_balancer_config(x),
+                func: Box::new(move |session| delete_load_balancer(session, &namespace, &name)),
+            }
+        })
+        .collect())
+}
+
+/// The configured socat tunnels, which are identified by their listening port and protocol.
+fn desired_socat_tunnels(
+    session: &Session,
+    socat_tunnels: Vec<SocatTunnelConfig>,
+) -> Vec<PlannedResource> {
+    socat_tunnels
+        .into_iter()
+        .map(
+            |SocatTunnelConfig {
+                 protocol,
+                 listening_port,
+                 connect_host,
+                 connect_port,
+                 health_check,
+                 engine,
+             }| {
+                let connect_host = match connect_host.as_str() {
+                    "" => session.default_connect_host(),
+                    _ => connect_host,
+                };
+                let engine = engine.unwrap_or(session.default_tunnel_engine());
+
+                PlannedResource {
+                    id: format!("{listening_port}/{protocol}"),
+                    config: socat_tunnel_config(&connect_host, connect_port, &health_check, engine),
+                    func: Box::new(move |session| {
+                        create_socat_tunnel(
+                            session,
+                            &protocol,
+                            listening_port,
+                            &connect_host,
+                            connect_port,
+                            &health_check,
+                            engine,
+                        )
+                    }),
+                }
+            },
+        )
+        .collect()
+}
+
+fn actual_socat_tunnels(session: &Session) -> Result<Vec<PlannedResource>, Error> {
+    Ok(fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?
+        .into_iter()
+        .map(|x| PlannedResource {
+            id: format!("{}/{}", x.spec_u16("listeningPort"), x.spec_str("protocol")),
+            config: socat_tunnel_config(
+                &x.spec_str("connectHost"),
+                x.spec_u16("connectPort"),
+                &HealthCheck::from_spec(&x.spec),
+                TunnelEngine::from_spec(&x),
+            ),
+            func: Box::new(move |session| {
+                Ok(Resources(
+                    None,
+                    vec![delete_socat_tunnel_entry(session, &x)],
+                ))
+            }),
+        })
+        .collect())
+}
+
+/// The configured port forwards, which are identified by their local port.
+fn desired_port_forwards(
+    _session: &Session,
+    port_forwards: Vec<PortForwardConfig>,
+) -> Vec<PlannedResource> {
+    port_forwards
+        .into_iter()
+        .map(
+            |PortForwardConfig {
+                 namespace,
+                 resource_type,
+                 name,
+                 local_port,
+                 remote_port,
+             }| PlannedResource {
+                id: local_port.to_string(),
+                config: format!("{namespace}/{resource_type}/{name}:{remote_port}"),
+                func: Box::new(move |session| {
+                    create_port_forward(
+                        session,
+                        &namespace,
+                        &resource_type,
+                        &name,
+                        local_port,
+                        remote_port,
+                    )
+                }),
+            },
+        )
+        .collect()
+}
+
+fn actual_port_forwards(session: &Session) -> Result<Vec<PlannedResource>, Error> {
+    Ok(fetch_registered_processes(session, PORT_FORWARD_KIND)?
+        .into_iter()
+        .map(|x| PlannedResource {
+            id: x.spec_u16("localPort").to_string(),
+            config: format!(
+                "{}/{}/{}:{}",
+                x.spec_str("namespace"),
+                x.spec_str("resourceType"),
+                x.spec_str("name"),
+                x.spec_u16("remotePort")
+            ),
+            func: Box::new(move |session| {
+                Ok(Resources(
+                    None,
+                    vec![delete_port_forward_entry(session, &x)],
+                ))
+            }),
+        })
+        .collect())
+}
+
+/// The configured minikube mounts, which are identified by their minikube path.
+fn desired_minikube_mounts(
+    _session: &Session,
+    minikube_mounts: Vec<MinikubeMountConfig>,
+) -> Vec<PlannedResource> {
+    minikube_mounts
+        .into_iter()
+        .map(
+            |MinikubeMountConfig {
+                 host_path,
+                 minikube_path,
+             }| PlannedResource {
+                id: minikube_path.clone(),
+                config: host_path.clone(),
+                func: Box::new(move |session| {
+                    create_minikube_mount(session, &host_path, &minikube_path)
+                }),
+            },
+        )
+        .collect()
+}
+
+/// The minikube mounts of the session's minikube profile.
+fn actual_minikube_mounts(session: &Session) -> Result<Vec<PlannedResource>, Error> {
+    Ok(fetch_minikube_mount_processes(session)?
+        .into_iter()
+        .map(|x| PlannedResource {
+            id: x.spec_str("minikubePath"),
+            config: x.spec_str("hostPath"),
+            func: Box::new(move |session| {
+                Ok(Resources(
+                    None,
+                    vec![delete_minikube_mount_entry(session, &x)],
+                ))
+            }),
+        })
+        .collect())
+}
+
+/// Pairs the configured resources of a kind with the existing ones by id.
+/// A resource that is configured more than once is only planned once, as configured first.
+fn diff(
+    kind: &'static str,
+    desired: Vec<PlannedResource>,
+    mut actual: Vec<PlannedResource>,
+) -> Vec<PlanStep> {
+    let mut plan: Vec<PlanStep> = Vec::new();
+    let mut planned: BTreeSet<String> = BTreeSet::new();
+
+    for desired in desired {
+        if !planned.insert(desired.id.clone()) {
+            continue;
+        }
+
+        let existing = actual
+            .iter()
+            .position(|x| x.id == desired.id)
+            .map(|x| actual.remove(x));
+
+        plan.push(match existing {
+            None => PlanStep {
+                action: PlanAction::Create,
+                kind,
+                id: desired.id,
+                config: desired.config,
+                previous_config: None,
+                delete: None,
+                create: Some(desired.func),
+            },
+            Some(existing) if existing.config == desired.config => PlanStep {
+                action: PlanAction::Keep,
+                kind,
+                id: desired.id,
+                config: desired.config,
+                previous_config: None,
+                delete: None,
+                create: None,
+            },
+            Some(existing) => PlanStep {
+                action: PlanAction::Recreate,
+                kind,
+                id: desired.id,
+                config: desired.config,
+                previous_config: Some(existing.config),
+                delete: Some(existing.func),
+                create: Some(desired.func),
+            },
+        });
+    }
+
+    plan.extend(actual.into_iter().map(|x| PlanStep {
+        action: PlanAction::Delete,
+        kind,
+        id: x.id,
+        config: x.config,
+        previous_config: None,
+        delete: Some(x.func),
+        create: None,
+    }));
+
+    plan
+}
+
+fn socat_tunnel_config(
+    connect_host: &str,
+    connect_port: u16,
+    health_check: &HealthCheck,
+    engine: TunnelEngine,
+) -> String {
+    let health_check = match health_check {
+        HealthCheck::Tcp => String::from("tcp check"),
+        HealthCheck::Http {
+            path,
+            expected_status,
+        } => format!("http check {path} expecting {expected_status}"),
+    };
+
+    format!(
+        "{connect_host}:{connect_port} with {}, {health_check}",
+        engine.name()
+    )
+}
+
+fn kind_name(kind: &str) -> &'static str {
+    match kind {
+        "loadBalancer" => "load balancer",
+        SOCAT_TUNNEL_KIND => "socat tunnel",
+        PORT_FORWARD_KIND => "port forward",
+        _ => "minikube mount",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::port_forward::fetch_port_forwards;
+    use crate::socat_tunnel::fetch_socat_tunnels;
+    use crate::test_support::{resource_fields, TestSession};
+    use std::fs;
+
+    const KUBECTL_GET_SVC: &str =
+        "kubectl --context minikube get svc -A -l reason=kube-minion -o json";
+
+    fn write_init_file(session: &Session, content: &str) -> String {
+        fs::create_dir_all(session.state_dir()).unwrap();
+
+        let path = session.state_dir().join("kube-minion.json");
+        fs::write(&path, content).unwrap();
+
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn apply_init_file_makes_the_resources_match_the_file() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            5432,
+            "db",
+            5432,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "db",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_port_forward(&session, "default", "services", "db", 5433, 5432).unwrap();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            r#"{"items": [{"metadata": {"namespace": "default", "name": "web-8080-80-lb",
+                                        "labels": {"kube-minion/resource-type": "services"}},
+                           "spec": {"ports": [{"port": 8080, "targetPort": 80, "protocol": "TCP"}]}},
+                          {"metadata": {"namespace": "default", "name": "old-9090-90-lb"}}]}"#,
+        );
+        let init_file_path = write_init_file(
+            &session,
+            r#"{
+                "loadBalancers": [{"name": "web", "port": 8080, "targetPort": 80},
+                                  {"name": "api", "port": 8081, "targetPort": 80}],
+                "socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5432}],
+                "portForwards": [{"name": "db", "localPort": 5433, "remotePort": 5434}]
+            }"#,
+        );
+
+        let result = apply_init_file(&session, &init_file_path);
+
+        let results = match result {
+            Ok(PrintableResults(_, results)) => results,
+            _ => panic!("Applying the initialization file has failed"),
+        };
+        assert_eq!(
+            results,
+            vec![format!(
+                "Applied {init_file_path}: 1 created, 1 recreated, 2 deleted, 2 kept"
+            )]
+        );
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl --context minikube -n default delete svc old-9090-90-lb"
+        )));
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl --context minikube -n default expose services api --type LoadBalancer \
+            --name api-8081-80-lb --port 8081 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=services"
+        )));
+        assert!(!session
+            .runner
+            .calls()
+            .iter()
+            .any(|x| x.contains("web-8080-80-lb")));
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                "kubectl --context minikube -n default port-forward services/db 5433:5434",
+                "socat -lpkube-minion-socat tcp-listen:5432,fork,reuseaddr tcp:db:5432",
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_init_file_recreates_a_named_load_balancer_whose_ports_change() {
+        let session = TestSession::new();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            r#"{"items": [{"metadata": {"namespace": "default", "name": "web-lb",
+                                        "labels": {"kube-minion/resource-type": "services"},
+                                        "annotations": {"kube-minion/resource-name": "web"}},
+                           "spec": {"ports": [{"port": 8080, "targetPort": 80, "protocol": "TCP"}]}}]}"#,
+        );
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"loadBalancers": [{"name": "web", "serviceName": "web-lb", "port": 8080,
+                                   "targetPort": 8000}]}"#,
+        );
+
+        let result = apply_init_file(&session, &init_file_path);
+
+        let results = match result {
+            Ok(PrintableResults(_, results)) => results,
+            _ => panic!("Applying the initialization file has failed"),
+        };
+        assert_eq!(
+            results,
+            vec![format!(
+                "Applied {init_file_path}: 0 created, 1 recreated, 0 deleted, 0 kept"
+            )]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl config current-context",
+                "minikube profile",
+                "kubectl --context minikube get svc -A -l reason=kube-minion -o json",
+                "kubectl --context minikube -n default delete svc web-lb",
+                "kubectl --context minikube -n default expose services web --type LoadBalancer \
+                --name web-lb --port 8080 --target-port 8000 -l \
+                reason=kube-minion,kube-minion/resource-type=services",
+                r#"kubectl --context minikube -n default patch svc web-lb --type json -p [{"op":"add","path":"/metadata/annotations","value":{"kube-minion/resource-name":"web"}}]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_init_file_recreates_a_load_balancer_whose_ports_are_renamed() {
+        let session = TestSession::new();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            r#"{"items": [{"metadata": {"namespace": "default", "name": "web-8080-80-9090-9090-lb",
+                                        "labels": {"kube-minion/resource-type": "services"}},
+                           "spec": {"ports": [
+                               {"name": "http", "port": 8080, "targetPort": 80, "protocol": "TCP"},
+                               {"name": "metrics", "port": 9090, "targetPort": 9090, "protocol": "TCP"}
+                           ]}}]}"#,
+        );
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"loadBalancers": [{"name": "web", "ports": [
+                {"name": "http", "port": 8080, "targetPort": 80},
+                {"name": "stats", "port": 9090}
+            ]}]}"#,
+        );
+
+        let result = apply_init_file(&session, &init_file_path);
+
+        let results = match result {
+            Ok(PrintableResults(_, results)) => results,
+            _ => panic!("Applying the initialization file has failed"),
+        };
+        assert_eq!(
+            results,
+            vec![format!(
+                "Applied {init_file_path}: 0 created, 1 recreated, 0 deleted, 0 kept"
+            )]
+        );
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl --context minikube -n default delete svc web-8080-80-9090-9090-lb"
+        )));
+    }
+
+    #[test]
+    fn apply_init_file_leaves_the_resources_of_absent_sections_alone() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "db",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_port_forward(&session, "default", "svc", "web", 8081, 80).unwrap();
+        let init_file_path = write_init_file(&session, r#"{"portForwards": []}"#);
+
+        apply_init_file(&session, &init_file_path).unwrap();
+
+        assert_eq!(
+            resource_fields(fetch_socat_tunnels(&session), &["listeningPort"]),
+            vec![vec!["8080"]]
+        );
+        assert!(resource_fields(fetch_port_forwards(&session), &["localPort"]).is_empty());
+        assert!(!session.runner.calls().iter().any(|x| x.contains("get svc")));
+    }
+
+    #[test]
+    fn diff_plans_each_resource_once() {
+        let resource = |id: &str, config: &str| PlannedResource {
+            id: String::from(id),
+            config: String::from(config),
+            func: Box::new(|_| Ok(PrintableResults(None, Vec::new()))),
+        };
+
+        let plan = diff(
+            PORT_FORWARD_KIND,
+            vec![
+                resource("8080", "default/svc/web:80"),
+                resource("8080", "default/svc/api:80"),
+                resource("5432", "default/svc/db:5432"),
+                resource("9090", "default/svc/admin:80"),
+            ],
+            vec![
+                resource("5432", "default/svc/db:5433"),
+                resource("9090", "default/svc/admin:80"),
+                resource("6379", "default/svc/cache:6379"),
+            ],
+        );
+
+        assert_eq!(
+            plan.iter()
+                .map(|x| x.describe().description)
+                .collect::<Vec<String>>(),
+            vec![
+                "create port forward 8080 (default/svc/web:80)",
+                "recreate port forward 5432 (default/svc/db:5432, was default/svc/db:5433)",
+                "keep port forward 9090 (default/svc/admin:80)",
+                "delete port forward 6379 (default/svc/cache:6379)",
+            ]
+        );
+    }
+}
@@ -1,28 +1,253 @@
+use std::str::FromStr;
+
 use crate::dashboard::delete_kubernetes_dashboard_load_balancer;
+use crate::init_file::clean_up_init_file;
 use crate::load_balancer::delete_all_load_balancers;
 use crate::minikube_mount::delete_all_minikube_mounts;
 use crate::minikube_tunnel::stop_minikube_tunnel;
+use crate::port_forward::delete_all_port_forwards;
 use crate::socat_tunnel::delete_all_socat_tunnels;
 use crate::CommandResultType::*;
-use crate::{merge_if_ok, CommandExecutionResult, OptionFunc};
+use crate::{merge_if_ok, CommandExecutionResult, Error, OptionFunc, Session};
+
+/// What happens to the resources created by `kube-minion` when it is terminated by SIGTERM or
+/// SIGHUP, eg, when its terminal is closed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignalPolicy {
+    /// Deletes all resources, as the clean up and exit option does.
+    CleanUp,
+    /// Leaves all resources running.
+    Detach,
+    /// Undoes the configuration of the initialization file, as the clean up initialization file
+    /// option does.
+    CleanUpInitFile,
+}
+
+impl FromStr for SignalPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cleanup" => Ok(SignalPolicy::CleanUp),
+            "detach" => Ok(SignalPolicy::Detach),
+            "cleanup-init-file" => Ok(SignalPolicy::CleanUpInitFile),
+            signal_policy => Err(format!(
+                "Unsupported signal policy {signal_policy} (expected cleanup, detach or \
+                cleanup-init-file)"
+            )),
+        }
+    }
+}
 
-pub fn build_clean_up_and_exit_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_clean_up_and_exit_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((String::from("Clean up and exit"), Box::new(clean_up), true))
 }
 
 /// Deletes resources created by `kube-minion`
 /// * All load balancers, including the Kubernetes dashboard load balancer
 /// * All socat tunnels
+/// * All port forwards
 /// * All minikube mounts
 /// * The minikube tunnel
-pub fn clean_up() -> CommandExecutionResult {
+pub fn clean_up(session: &Session) -> CommandExecutionResult {
     let mut results: Vec<String> = Vec::new();
 
-    let _ = merge_if_ok(&mut results, delete_all_load_balancers);
-    let _ = merge_if_ok(&mut results, delete_all_socat_tunnels);
-    let _ = merge_if_ok(&mut results, delete_all_minikube_mounts);
-    let _ = merge_if_ok(&mut results, delete_kubernetes_dashboard_load_balancer);
-    let _ = merge_if_ok(&mut results, stop_minikube_tunnel);
+    let _ = merge_if_ok(&mut results, || delete_all_load_balancers(session));
+    let _ = merge_if_ok(&mut results, || delete_all_socat_tunnels(session));
+    let _ = merge_if_ok(&mut results, || delete_all_port_forwards(session));
+    let _ = merge_if_ok(&mut results, || delete_all_minikube_mounts(session));
+    let _ = merge_if_ok(&mut results, || {
+        delete_kubernetes_dashboard_load_balancer(session)
+    });
+    let _ = merge_if_ok(&mut results, || stop_minikube_tunnel(session));
 
     Ok(PrintableResults(None, results))
 }
+
+/// Applies the signal policy before `kube-minion` exits because of a signal.
+pub fn clean_up_on_signal(
+    session: &Session,
+    signal_policy: SignalPolicy,
+    init_file_path: Option<&str>,
+) -> CommandExecutionResult {
+    match (signal_policy, init_file_path) {
+        (SignalPolicy::CleanUp, _) => clean_up(session),
+        (SignalPolicy::Detach, _) => Ok(PrintableResults(
+            None,
+            vec![String::from(
+                "The resources created by kube-minion have been left running",
+            )],
+        )),
+        (SignalPolicy::CleanUpInitFile, Some(init_file_path)) => {
+            clean_up_init_file(session, init_file_path.to_string())
+        }
+        (SignalPolicy::CleanUpInitFile, None) => Ok(PrintableResults(
+            None,
+            vec![String::from(
+                "No initialization file has been processed, so there is nothing to clean up",
+            )],
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthCheck;
+    use crate::load_balancer::{create_load_balancer, PortMapping, ServiceMetadata};
+    use crate::minikube_mount::create_minikube_mount;
+    use crate::minikube_tunnel::create_minikube_tunnel;
+    use crate::port_forward::{create_port_forward, fetch_port_forwards};
+    use crate::socat_tunnel::{create_socat_tunnel, TunnelEngine};
+    use crate::test_support::{resource_fields, TestSession};
+    use crate::{DryRunProcessRunner, Settings};
+    use std::sync::Arc;
+
+    #[test]
+    fn clean_up_deletes_all_resources() {
+        let session = TestSession::new();
+        create_load_balancer(
+            &session,
+            "default",
+            "svc",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_port_forward(&session, "default", "svc", "db", 5432, 5432).unwrap();
+        create_minikube_mount(&session, env!("CARGO_MANIFEST_DIR"), "/src").unwrap();
+        create_minikube_tunnel(&session).unwrap();
+        session.runner.respond_ok(
+            "kubectl get svc -A -l reason=kube-minion -o json",
+            r#"{"items": [{"metadata": {"namespace": "default", "name": "web-8080-80-lb"}}]}"#,
+        );
+        session.runner.respond_ok(
+            "kubectl -n default delete svc web-8080-80-lb",
+            "service \"web-8080-80-lb\" deleted",
+        );
+
+        let results = match clean_up(&session) {
+            Ok(PrintableResults(_, results)) => results,
+            _ => panic!("Clean up has failed"),
+        };
+
+        assert!(session.runner.running().is_empty());
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl -n default delete svc web-8080-80-lb"
+        )));
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl -n kubernetes-dashboard delete svc kubernetes-dashboard-lb"
+        )));
+        assert_eq!(
+            results,
+            vec![
+                "service \"web-8080-80-lb\" deleted",
+                "Stopped socat tunnel listening on port 8080 and connecting to localhost:80",
+                "Stopped port forward from local port 5432 to default/svc/db",
+                &format!(
+                    "Stopped minikube mount from host path {} to minikube path /src",
+                    env!("CARGO_MANIFEST_DIR")
+                ),
+                "The kubernetes dashboard load balancer has been deleted",
+                "The minikube tunnel has been stopped",
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_up_in_a_dry_run_only_prints_what_it_would_do() {
+        let session = TestSession::new();
+        create_load_balancer(
+            &session,
+            "default",
+            "svc",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        )
+        .unwrap();
+        create_port_forward(&session, "default", "svc", "db", 5432, 5432).unwrap();
+        create_minikube_tunnel(&session).unwrap();
+        session.runner.respond_ok(
+            "kubectl get svc -A -l reason=kube-minion -o json",
+            r#"{"items": [{"metadata": {"namespace": "default", "name": "web-8080-80-lb"}}]}"#,
+        );
+        let runner = Arc::new(DryRunProcessRunner::new(session.runner.clone()));
+        let dry_run_session = Session::with_runner(
+            Settings {
+                dry_run: true,
+                ..session.settings()
+            },
+            runner.clone(),
+        );
+        let calls = session.runner.calls().len();
+
+        clean_up(&dry_run_session).unwrap();
+
+        assert_eq!(
+            runner.actions(),
+            vec![
+                String::from("kubectl -n default delete svc web-8080-80-lb"),
+                String::from("kill -INT 1"),
+                String::from("kubectl -n kubernetes-dashboard delete svc kubernetes-dashboard-lb"),
+                String::from("kill -INT 2"),
+            ]
+        );
+        assert_eq!(
+            session.runner.calls()[calls..],
+            vec![
+                "kubectl get svc -A -l reason=kube-minion -o json",
+                "kubectl -n kubernetes-dashboard get svc kubernetes-dashboard-lb --no-headers",
+            ]
+        );
+        assert_eq!(session.runner.running().len(), 2);
+        assert_eq!(
+            resource_fields(fetch_port_forwards(&session), &["name"]),
+            vec![vec!["db"]]
+        );
+    }
+
+    #[test]
+    fn clean_up_on_signal_applies_the_signal_policy() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        clean_up_on_signal(&session, SignalPolicy::Detach, None).unwrap();
+        clean_up_on_signal(&session, SignalPolicy::CleanUpInitFile, None).unwrap();
+        assert_eq!(session.runner.running().len(), 1);
+
+        clean_up_on_signal(&session, SignalPolicy::CleanUp, None).unwrap();
+        assert!(session.runner.running().is_empty());
+    }
+
+    #[test]
+    fn signal_policy_is_parsed_from_its_name() {
+        assert_eq!("cleanup".parse(), Ok(SignalPolicy::CleanUp));
+        assert_eq!(" Detach ".parse(), Ok(SignalPolicy::Detach));
+        assert_eq!(
+            "cleanup-init-file".parse(),
+            Ok(SignalPolicy::CleanUpInitFile)
+        );
+        assert!("ignore".parse::<SignalPolicy>().is_err());
+    }
+}
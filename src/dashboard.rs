@@ -1,40 +1,42 @@
+use crate::health::{probe, Health, HealthCheck};
+use crate::load_balancer::ServiceMetadata;
 use crate::{
-    process_exited_with_success, start_and_wait_process, CommandExecutionResult,
-    CommandResultType::*, OptionFunc,
+    run_kubectl, CommandExecutionResult, CommandResultType::*, Error, OptionFunc, Resource, Session,
 };
+use serde_json::json;
 
-static mut DASHBOARD_PORT: u16 = 51515;
-
-pub fn set_dashboard_port(port: u16) {
-    unsafe {
-        DASHBOARD_PORT = port;
-    }
-}
-
-pub fn create_kubernetes_dashboard_load_balancer() -> CommandExecutionResult {
-    if check_kubernetes_dashboard().is_err() {
-        toggle_kubernetes_dashboard_load_balancer(false)
+pub fn create_kubernetes_dashboard_load_balancer(session: &Session) -> CommandExecutionResult {
+    if check_kubernetes_dashboard(session).is_err() {
+        toggle_kubernetes_dashboard_load_balancer(session, false)
     } else {
-        Ok(PrintableResults(
-            None,
-            vec![format!(
+        let dashboard = describe_kubernetes_dashboard_load_balancer(
+            session,
+            format!(
                 "The kubernetes dashboard load balancer can be accessed at http://127.0.0.1:{}",
-                unsafe { DASHBOARD_PORT.to_string() }
-            )],
+                session.dashboard_port()
+            ),
+            "running",
+        );
+
+        Ok(Resources(
+            None,
+            vec![probe_kubernetes_dashboard(session).annotate(dashboard)],
         ))
     }
 }
 
-pub fn delete_kubernetes_dashboard_load_balancer() -> CommandExecutionResult {
-    if check_kubernetes_dashboard().is_ok() {
-        toggle_kubernetes_dashboard_load_balancer(true)
+pub fn delete_kubernetes_dashboard_load_balancer(session: &Session) -> CommandExecutionResult {
+    if check_kubernetes_dashboard(session).is_ok() {
+        toggle_kubernetes_dashboard_load_balancer(session, true)
     } else {
         Ok(PrintableResults(None, Vec::new()))
     }
 }
 
-pub fn build_kubernetes_dashboard_option() -> Result<(String, OptionFunc, bool), String> {
-    let check_kubernetes_dashboard_result = check_kubernetes_dashboard();
+pub fn build_kubernetes_dashboard_option(
+    session: &Session,
+) -> Result<(String, OptionFunc, bool), Error> {
+    let check_kubernetes_dashboard_result = check_kubernetes_dashboard(session);
 
     let (running, next_option) = match check_kubernetes_dashboard_result {
         Ok(_) => (true, "Delete"),
@@ -43,18 +45,53 @@ pub fn build_kubernetes_dashboard_option() -> Result<(String, OptionFunc, bool),
 
     Ok((
         format!("{next_option} kubernetes dashboard load balancer"),
-        Box::new(move || toggle_kubernetes_dashboard_load_balancer(running)),
+        Box::new(move |session| toggle_kubernetes_dashboard_load_balancer(session, running)),
         false,
     ))
 }
 
-fn get_dashboard_port() -> String {
-    unsafe { DASHBOARD_PORT.to_string() }
+fn describe_kubernetes_dashboard_load_balancer(
+    session: &Session,
+    description: String,
+    state: &str,
+) -> Resource {
+    Resource::new("dashboard", description)
+        .field("namespace", "kubernetes-dashboard")
+        .field("name", "kubernetes-dashboard-lb")
+        .field("port", session.dashboard_port())
+        .field(
+            "url",
+            format!("http://127.0.0.1:{}", session.dashboard_port()),
+        )
+        .field("state", state)
+}
+
+/// Checks that the dashboard answers on its URL, which is exposed on the minikube tunnel.
+fn probe_kubernetes_dashboard(session: &Session) -> Health {
+    probe(
+        "127.0.0.1",
+        session.dashboard_port(),
+        &HealthCheck::Http {
+            path: String::from("/"),
+            expected_status: 200,
+        },
+    )
 }
 
-fn check_kubernetes_dashboard() -> CommandExecutionResult {
-    let result = start_and_wait_process(
-        "kubectl",
+/// Checks that the dashboard load balancer exists, which, with the Kubernetes API, is answered by
+/// the watch of the services, so that refreshing the menu needs no request.
+fn check_kubernetes_dashboard(session: &Session) -> CommandExecutionResult {
+    if let Some(kube_api) = session.kube_api() {
+        return match kube_api.has_service("kubernetes-dashboard", "kubernetes-dashboard-lb")? {
+            true => Ok(PrintableResults(None, Vec::new())),
+            false => Err(Error::NotFound(String::from(
+                "The kubernetes dashboard load balancer does not exist",
+            ))),
+        };
+    }
+
+    run_kubectl(
+        session,
         &[
             "-n",
             "kubernetes-dashboard",
@@ -63,81 +100,95 @@ fn check_kubernetes_dashboard() -> CommandExecutionResult {
             "kubernetes-dashboard-lb",
             "--no-headers",
         ],
-        None,
-    );
-
-    match process_exited_with_success(result) {
-        (true, _, _) => Ok(PrintableResults(None, Vec::new())),
-        (false, _, Some(error)) => Err(error),
-        (false, _, _) => Err(String::from(
+        Some(String::from(
             "Failed to check if the kubernetes dashboard load balancer exists",
         )),
-    }
+    )?;
+
+    Ok(PrintableResults(None, Vec::new()))
 }
 
-fn toggle_kubernetes_dashboard_load_balancer(running: bool) -> CommandExecutionResult {
+fn toggle_kubernetes_dashboard_load_balancer(
+    session: &Session,
+    running: bool,
+) -> CommandExecutionResult {
     if running {
-        let result = start_and_wait_process(
-            "kubectl",
-            &[
-                "-n",
-                "kubernetes-dashboard",
-                "delete",
-                "svc",
-                "kubernetes-dashboard-lb",
-            ],
-            Some(String::from(
-                "Could not delete kubernetes dashboard load balancer",
-            )),
-        );
+        match session.kube_api() {
+            Some(kube_api) => {
+                kube_api.delete_service("kubernetes-dashboard", "kubernetes-dashboard-lb")?
+            }
+            None => run_kubectl(
+                session,
+                &[
+                    "-n",
+                    "kubernetes-dashboard",
+                    "delete",
+                    "svc",
+                    "kubernetes-dashboard-lb",
+                ],
+                Some(String::from(
+                    "Could not delete kubernetes dashboard load balancer",
+                )),
+            )?,
+        };
 
-        match process_exited_with_success(result) {
-            (true, _, _) => Ok(PrintableResults(
-                None,
-                vec![String::from(
-                    "The kubernetes dashboard load balancer has been deleted",
-                )],
-            )),
-            (false, _, Some(error)) => Err(error),
-            (false, _, _) => Err(String::from(
-                "Failed to delete the kubernetes dashboard load balancer",
-            )),
-        }
+        Ok(Resources(
+            None,
+            vec![describe_kubernetes_dashboard_load_balancer(
+                session,
+                String::from("The kubernetes dashboard load balancer has been deleted"),
+                "deleted",
+            )],
+        ))
     } else {
-        let result = start_and_wait_process(
-            "kubectl",
-            &[
-                "-n",
+        match session.kube_api() {
+            Some(kube_api) => kube_api.expose(
                 "kubernetes-dashboard",
-                "expose",
                 "svc",
                 "kubernetes-dashboard",
-                "--name",
                 "kubernetes-dashboard-lb",
-                "--type",
-                "LoadBalancer",
-                "--port",
-                &get_dashboard_port(),
-                "--target-port",
-                "9090",
-                "-l",
-                "reason=kube-minion",
-            ],
-            Some(String::from("Could not proxy kubernetes dashboard")),
+                vec![json!({
+                    "port": session.dashboard_port(),
+                    "targetPort": 9090,
+                    "protocol": "TCP",
+                })],
+                &ServiceMetadata::default(),
+            )?,
+            None => run_kubectl(
+                session,
+                &[
+                    "-n",
+                    "kubernetes-dashboard",
+                    "expose",
+                    "svc",
+                    "kubernetes-dashboard",
+                    "--name",
+                    "kubernetes-dashboard-lb",
+                    "--type",
+                    "LoadBalancer",
+                    "--port",
+                    &session.dashboard_port().to_string(),
+                    "--target-port",
+                    "9090",
+                    "-l",
+                    "reason=kube-minion",
+                ],
+                Some(String::from("Could not proxy kubernetes dashboard")),
+            )?,
+        };
+
+        let dashboard = describe_kubernetes_dashboard_load_balancer(
+            session,
+            format!(
+                "The kubernetes dashboard load balancer can be accessed at http://127.0.0.1:{}",
+                session.dashboard_port()
+            ),
+            "created",
         );
 
-        match process_exited_with_success(result) {
-            (true, _, _) => Ok(PrintableResults(
-                None,
-                vec![format!(
-                    "The kubernetes dashboard load balancer can be accessed at http://127.0.0.1:{}",
-                    get_dashboard_port()
-                )],
-            )),
-            (false, _, Some(error)) => Err(error),
-            (false, _, _) => Err(String::from(
-                "Failed to create the kubernetes dashboard load balancer",
-            )),
-        }
+        Ok(Resources(
+            None,
+            vec![probe_kubernetes_dashboard(session).annotate(dashboard)],
+        ))
     }
 }
@@ -0,0 +1,126 @@
+use std::{fmt, io};
+
+/// The errors that `kube-minion` operations fail with.
+/// Each kind of error has its own name, used in JSON output, and its own process exit code, so
+/// that scripts can tell, eg, a missing `kubectl` apart from a service that does not exist.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// An external command, eg, `kubectl`, could not be found in the path.
+    MissingDependency(String),
+    /// An external command could not be started, or exited right after being started.
+    Spawn { command: String, message: String },
+    /// An external command exited with a non-zero status.
+    NonZeroExit {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// A resource, eg, a kubernetes service, does not exist.
+    NotFound(String),
+    /// An initialization file or the output of an external command could not be parsed.
+    Parse(String),
+    /// A process did not reach the expected state in time.
+    Timeout(String),
+    /// The user provided an invalid or empty value, either interactively or as an argument.
+    Input(String),
+    /// A local file or process could not be accessed, eg, the resource registry.
+    Io(String),
+    /// The kubectl context does not belong to the minikube profile that `kube-minion` operates on.
+    ForeignContext(String),
+    /// The Kubernetes API server could not be talked to directly, or answered with an error.
+    Api(String),
+}
+
+impl Error {
+    /// Classifies the failure of an external command, recognizing the resources that `kubectl`
+    /// reports as not found.
+    pub fn from_exit(command: &str, code: Option<i32>, stderr: &str) -> Self {
+        let stderr = stderr.trim().to_string();
+
+        if command == "kubectl" && stderr.contains("(NotFound)") {
+            return Error::NotFound(stderr);
+        }
+
+        Error::NonZeroExit {
+            command: String::from(command),
+            code,
+            stderr,
+        }
+    }
+
+    /// Classifies the failure to start an external command.
+    pub fn from_spawn(command: &str, error: io::Error, message: Option<String>) -> Self {
+        if error.kind() == io::ErrorKind::NotFound {
+            return Error::MissingDependency(String::from(command));
+        }
+
+        Error::Spawn {
+            command: String::from(command),
+            message: message.unwrap_or_else(|| error.to_string()),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::MissingDependency(_) => "missingDependency",
+            Error::Spawn { .. } => "spawn",
+            Error::NonZeroExit { .. } => "nonZeroExit",
+            Error::NotFound(_) => "notFound",
+            Error::Parse(_) => "parse",
+            Error::Timeout(_) => "timeout",
+            Error::Input(_) => "input",
+            Error::Io(_) => "io",
+            Error::ForeignContext(_) => "foreignContext",
+            Error::Api(_) => "api",
+        }
+    }
+
+    /// The exit code of `kube-minion` when a subcommand fails with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Input(_) => 2,
+            Error::MissingDependency(_) => 3,
+            Error::Spawn { .. } => 4,
+            Error::NonZeroExit { .. } => 5,
+            Error::NotFound(_) => 6,
+            Error::Parse(_) => 7,
+            Error::Timeout(_) => 8,
+            Error::Io(_) => 9,
+            Error::ForeignContext(_) => 10,
+            Error::Api(_) => 11,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingDependency(command) => write!(f, "Could not find {command} in path"),
+            Error::Spawn { message, .. } => write!(f, "{message}"),
+            Error::NonZeroExit {
+                command,
+                code,
+                stderr,
+            } => match (stderr.is_empty(), code) {
+                (false, _) => write!(f, "{stderr}"),
+                (true, Some(code)) => write!(f, "{command} exited with status {code}"),
+                (true, None) => write!(f, "{command} was terminated by a signal"),
+            },
+            Error::NotFound(message)
+            | Error::Parse(message)
+            | Error::Timeout(message)
+            | Error::Input(message)
+            | Error::Io(message)
+            | Error::ForeignContext(message)
+            | Error::Api(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
@@ -0,0 +1,411 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::health::HealthCheck;
+use crate::kube_api::RESOURCE_TYPE_LABEL;
+use crate::load_balancer::{
+    exposed_resource_name, list_load_balancer_services, load_balancer_service_name,
+    service_port_mappings, PortMapping, ServiceMetadata,
+};
+use crate::minikube_mount::fetch_minikube_mount_processes;
+use crate::port_forward::PORT_FORWARD_KIND;
+use crate::protocol::Protocol;
+use crate::registry::fetch_registered_processes;
+use crate::socat_tunnel::{TunnelEngine, SOCAT_TUNNEL_KIND};
+use crate::{
+    parse_string, CommandExecutionResult, CommandResultType::*, Error, OptionFunc, Session,
+};
+
+/// The schema that an exported initialization file refers to, for editors to validate it against.
+const SCHEMA_URL: &str =
+    "https://raw.githubusercontent.com/sadesyllas/kube-minion/main/kube-minion.schema.json";
+
+/// Where the initialization file is exported to, unless another path is given.
+pub const DEFAULT_EXPORT_PATH: &str = "kube-minion.json";
+
+pub fn build_export_init_file_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((
+        String::from("Export initialization file"),
+        Box::new(export_init_file_guided),
+        false,
+    ))
+}
+
+/// Writes an initialization file which creates the load balancers, socat tunnels, port forwards and
+/// minikube mounts that exist now, with the current default socat connect host and minikube tunnel
+/// bind address, so that the setup can be reproduced, eg, by a teammate.
+pub fn export_init_file(session: &Session, path: &str, overwrite: bool) -> CommandExecutionResult {
+    if !overwrite && Path::new(path).exists() {
+        return Err(Error::Input(format!(
+            "{path} already exists and would be overwritten"
+        )));
+    }
+
+    let mut results: Vec<String> = Vec::new();
+
+    let load_balancers: Vec<Value> = list_load_balancer_services(session)?
+        .iter()
+        .filter_map(|x| export_load_balancer(x, &mut results))
+        .collect();
+
+    let socat_tunnels: Vec<Value> = fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?
+        .iter()
+        .map(|x| {
+            let mut socat_tunnel = json!({
+                "protocol": x.spec_str("protocol"),
+                "listeningPort": x.spec_u16("listeningPort"),
+                "connectHost": x.spec_str("connectHost"),
+                "connectPort": x.spec_u16("connectPort"),
+                "engine": TunnelEngine::from_spec(x).name(),
+            });
+
+            if let HealthCheck::Http {
+                path,
+                expected_status,
+            } = HealthCheck::from_spec(&x.spec)
+            {
+                socat_tunnel["healthCheck"] =
+                    json!({"path": path, "expectedStatus": expected_status});
+            }
+
+            socat_tunnel
+        })
+        .collect();
+
+    let port_forwards: Vec<Value> = fetch_registered_processes(session, PORT_FORWARD_KIND)?
+        .iter()
+        .map(|x| {
+            json!({
+                "namespace": x.spec_str("namespace"),
+                "resourceType": x.spec_str("resourceType"),
+                "name": x.spec_str("name"),
+                "localPort": x.spec_u16("localPort"),
+                "remotePort": x.spec_u16("remotePort"),
+            })
+        })
+        .collect();
+
+    let minikube_mounts: Vec<Value> = fetch_minikube_mount_processes(session)?
+        .iter()
+        .map(|x| {
+            json!({
+                "hostPath": x.spec_str("hostPath"),
+                "minikubePath": x.spec_str("minikubePath"),
+            })
+        })
+        .collect();
+
+    results.push(format!(
+        "Exported {} load balancers, {} socat tunnels, {} port forwards and {} minikube mounts \
+        to {path}",
+        load_balancers.len(),
+        socat_tunnels.len(),
+        port_forwards.len(),
+        minikube_mounts.len(),
+    ));
+
+    let init_config = json!({
+        "$schema": SCHEMA_URL,
+        "loadBalancers": load_balancers,
+        "socatTunnels": socat_tunnels,
+        "portForwards": port_forwards,
+        "minikubeMounts": minikube_mounts,
+        "defaultSocatConnectHost": session.default_connect_host(),
+        "minikubeTunnelBindAddress": session.bind_address(),
+    });
+
+    fs::write(
+        path,
+        format!("{}\n", serde_json::to_string_pretty(&init_config).unwrap()),
+    )
+    .map_err(|x| Error::Io(format!("Failed to write {path}: {x}")))?;
+
+    Ok(PrintableResults(None, results))
+}
+
+fn export_init_file_guided(session: &Session) -> CommandExecutionResult {
+    let path = parse_string(
+        &format!("Path (leave empty for {DEFAULT_EXPORT_PATH}): "),
+        Some(String::from(DEFAULT_EXPORT_PATH)),
+        None,
+    )?;
+
+    let overwrite = Path::new(&path).exists()
+        && parse_string(
+            &format!("{path} already exists, overwrite it? (y/N): "),
+            Some(String::from("n")),
+            None,
+        )?
+        .eq_ignore_ascii_case("y");
+
+    export_init_file(session, &path, overwrite)
+}
+
+/// Describes a load balancer's service the way the initialization file configures it, recovering
+/// the name of the exposed resource from the service's name, which `kube-minion` has derived from
+/// it, and the type of the exposed resource from the service's labels.
+fn export_load_balancer(service: &Value, results: &mut Vec<String>) -> Option<Value> {
+    let metadata = &service["metadata"];
+    let namespace = metadata["namespace"].as_str().unwrap_or("default");
+    let service_name = metadata["name"].as_str().unwrap_or_default();
+    let ports = service_port_mappings(service);
+
+    let Some(name) = exposed_resource_name(service, &ports) else {
+        results.push(format!(
+            "Skipped load balancer {namespace}/{service_name}, whose name does not match its ports"
+        ));
+        return None;
+    };
+
+    let resource_type = match metadata["labels"][RESOURCE_TYPE_LABEL].as_str() {
+        Some(resource_type) => resource_type,
+        None => {
+            results.push(format!(
+                "The resource type of load balancer {namespace}/{service_name} is unknown, so it \
+                has been exported as services"
+            ));
+            "services"
+        }
+    };
+
+    let mut load_balancer = json!({
+        "namespace": namespace,
+        "resourceType": resource_type,
+        "name": name,
+    });
+
+    // A single unnamed port is exported the short way, which is how most load balancers are
+    // configured.
+    match ports.as_slice() {
+        [port @ PortMapping { name: None, .. }] => export_ports(&mut load_balancer, port),
+        _ => {
+            load_balancer["ports"] = ports
+                .iter()
+                .map(|x| {
+                    let mut port = match &x.name {
+                        Some(name) => json!({"name": name}),
+                        None => json!({}),
+                    };
+                    export_ports(&mut port, x);
+                    port
+                })
+                .collect();
+        }
+    }
+
+    if service_name != load_balancer_service_name(name, &ports) {
+        load_balancer["serviceName"] = json!(service_name);
+    }
+
+    let metadata = ServiceMetadata::from_service(service);
+    if !metadata.labels.is_empty() {
+        load_balancer["labels"] = json!(metadata.labels);
+    }
+    if !metadata.annotations.is_empty() {
+        load_balancer["annotations"] = json!(metadata.annotations);
+    }
+
+    Some(load_balancer)
+}
+
+/// Sets the port, the target port and, unless it is TCP, the protocol of a port mapping.
+fn export_ports(value: &mut Value, port: &PortMapping) {
+    value["port"] = json!(port.port);
+    value["targetPort"] = port.target_port.to_json();
+
+    if port.protocol != Protocol::Tcp {
+        value["protocol"] = json!(port.protocol.name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_file::{parse_init_config_json, parse_load_balancers};
+    use crate::minikube_mount::create_minikube_mount;
+    use crate::port_forward::create_port_forward;
+    use crate::socat_tunnel::create_socat_tunnel;
+    use crate::test_support::TestSession;
+
+    const KUBECTL_GET_SVC: &str = "kubectl get svc -A -l reason=kube-minion -o json";
+
+    fn export_path(session: &Session) -> String {
+        fs::create_dir_all(session.state_dir()).unwrap();
+
+        session
+            .state_dir()
+            .join("kube-minion.json")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn export_init_file_writes_the_current_resources() {
+        let session = TestSession::new();
+        session.set_default_connect_host(String::from("192.168.49.1"));
+        session.set_bind_address(String::from("0.0.0.0"));
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8888,
+            "localhost",
+            8080,
+            &HealthCheck::Http {
+                path: String::from("/health"),
+                expected_status: 204,
+            },
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_port_forward(&session, "apps", "svc", "db", 5432, 5432).unwrap();
+        create_minikube_mount(&session, env!("CARGO_MANIFEST_DIR"), "/src").unwrap();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            r#"{"items": [
+                {"metadata": {"namespace": "apps", "name": "web-8080-80-lb",
+                              "labels": {"reason": "kube-minion",
+                                         "kube-minion/resource-type": "deployment"}},
+                 "spec": {"ports": [{"port": 8080, "targetPort": 80}]}},
+                {"metadata": {"namespace": "apps", "name": "grpc-9000-9000-9001-9001-lb",
+                              "labels": {"reason": "kube-minion",
+                                         "kube-minion/resource-type": "services"}},
+                 "spec": {"ports": [{"name": "grpc", "port": 9000, "targetPort": 9000},
+                                   {"name": "port-9001", "port": 9001, "targetPort": 9001}]}},
+                {"metadata": {"namespace": "default", "name": "dns-53-53-udp-lb",
+                              "labels": {"reason": "kube-minion",
+                                         "kube-minion/resource-type": "services"}},
+                 "spec": {"ports": [{"port": 53, "targetPort": 53, "protocol": "UDP"}]}},
+                {"metadata": {"namespace": "apps", "name": "search",
+                              "labels": {"reason": "kube-minion",
+                                         "kube-minion/resource-type": "statefulset",
+                                         "team": "search"},
+                              "annotations": {"kube-minion/resource-name": "elasticsearch",
+                                              "metallb.universe.tf/address-pool": "dev"}},
+                 "spec": {"ports": [{"port": 9200, "targetPort": 9200}]}},
+                {"metadata": {"namespace": "default", "name": "api-9090-9090-lb",
+                              "labels": {"reason": "kube-minion"}},
+                 "spec": {"ports": [{"port": 9090, "targetPort": 9090}]}},
+                {"metadata": {"namespace": "default", "name": "cache",
+                              "labels": {"reason": "kube-minion"}},
+                 "spec": {"ports": [{"port": 6379, "targetPort": 6379}]}},
+                {"metadata": {"namespace": "kubernetes-dashboard",
+                              "name": "kubernetes-dashboard-lb",
+                              "labels": {"reason": "kube-minion"}},
+                 "spec": {"ports": [{"port": 51515, "targetPort": 9090}]}}
+            ]}"#,
+        );
+        let path = export_path(&session);
+
+        let results = match export_init_file(&session, &path, false) {
+            Ok(PrintableResults(_, results)) => results,
+            _ => panic!("Export has failed"),
+        };
+
+        assert_eq!(
+            results,
+            vec![
+                String::from(
+                    "The resource type of load balancer default/api-9090-9090-lb is unknown, so \
+                    it has been exported as services"
+                ),
+                String::from(
+                    "Skipped load balancer default/cache, whose name does not match its ports"
+                ),
+                format!(
+                    "Exported 5 load balancers, 1 socat tunnels, 1 port forwards and 1 minikube \
+                    mounts to {path}"
+                ),
+            ]
+        );
+        assert_eq!(
+            Value::Object(parse_init_config_json(&path).unwrap()),
+            json!({
+                "$schema": SCHEMA_URL,
+                "loadBalancers": [
+                    {"namespace": "apps", "resourceType": "deployment", "name": "web",
+                     "port": 8080, "targetPort": 80},
+                    {"namespace": "apps", "resourceType": "services", "name": "grpc",
+                     "ports": [{"name": "grpc", "port": 9000, "targetPort": 9000},
+                               {"port": 9001, "targetPort": 9001}]},
+                    {"namespace": "default", "resourceType": "services", "name": "dns",
+                     "port": 53, "targetPort": 53, "protocol": "udp"},
+                    {"namespace": "apps", "resourceType": "statefulset", "name": "elasticsearch",
+                     "port": 9200, "targetPort": 9200, "serviceName": "search",
+                     "labels": {"team": "search"},
+                     "annotations": {"metallb.universe.tf/address-pool": "dev"}},
+                    {"namespace": "default", "resourceType": "services", "name": "api",
+                     "port": 9090, "targetPort": 9090},
+                ],
+                "socatTunnels": [
+                    {"protocol": "tcp", "listeningPort": 8888, "connectHost": "localhost",
+                     "connectPort": 8080, "engine": "socat",
+                     "healthCheck": {"path": "/health", "expectedStatus": 204}},
+                ],
+                "portForwards": [
+                    {"namespace": "apps", "resourceType": "svc", "name": "db",
+                     "localPort": 5432, "remotePort": 5432},
+                ],
+                "minikubeMounts": [
+                    {"hostPath": env!("CARGO_MANIFEST_DIR"), "minikubePath": "/src"},
+                ],
+                "defaultSocatConnectHost": "192.168.49.1",
+                "minikubeTunnelBindAddress": "0.0.0.0",
+            })
+        );
+    }
+
+    #[test]
+    fn exported_load_balancers_can_be_loaded_again() {
+        let session = TestSession::new();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            r#"{"items": [
+                {"metadata": {"namespace": "apps", "name": "my-web-application-8080-80-lb",
+                              "labels": {"reason": "kube-minion",
+                                         "kube-minion/resource-type": "deployment"}},
+                 "spec": {"ports": [{"port": 8080, "targetPort": 80}]}}
+            ]}"#,
+        );
+        let path = export_path(&session);
+
+        export_init_file(&session, &path, false).unwrap();
+
+        let load_balancers = parse_load_balancers(&parse_init_config_json(&path).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(load_balancers.len(), 1);
+        assert_eq!(load_balancers[0].namespace, "apps");
+        assert_eq!(load_balancers[0].resource_type, "deployment");
+        assert_eq!(load_balancers[0].name, "my-web-application");
+        assert_eq!(load_balancers[0].ports, vec![PortMapping::new(8080, 80)]);
+        assert_eq!(
+            load_balancers[0].service_name,
+            "my-web-application-8080-80-lb"
+        );
+    }
+
+    #[test]
+    fn export_init_file_does_not_overwrite_an_existing_file_unless_asked_to() {
+        let session = TestSession::new();
+        session
+            .runner
+            .respond_ok(KUBECTL_GET_SVC, r#"{"items": []}"#);
+        let path = export_path(&session);
+        fs::write(&path, "{}").unwrap();
+
+        assert!(matches!(
+            export_init_file(&session, &path, false),
+            Err(Error::Input(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        export_init_file(&session, &path, true).unwrap();
+
+        assert_eq!(
+            parse_init_config_json(&path).unwrap()["socatTunnels"],
+            json!([])
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{
+    Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket,
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::process_log::file_name;
+use crate::{Error, Session};
+
+/// How often a forwarder writes its statistics to its statistics file.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the connect host may leave a UDP peer without a reply before the peer's upstream
+/// socket is closed.
+const UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The traffic that has gone through a forwarder since it was started.
+/// For UDP, each peer that sends datagrams to the listening port counts as a connection.
+#[derive(Debug, Default)]
+pub struct ForwarderStats {
+    connections: AtomicU64,
+    active_connections: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl ForwarderStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "connections": self.connections.load(Ordering::Relaxed),
+            "activeConnections": self.active_connections.load(Ordering::Relaxed),
+            "bytesSent": self.bytes_sent.load(Ordering::Relaxed),
+            "bytesReceived": self.bytes_received.load(Ordering::Relaxed),
+        })
+    }
+
+    fn open_connection(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn close_connection(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Listens on a port of all interfaces and forwards each connection, or each UDP peer, to the
+/// connect host and port, like `socat PROTOCOL-listen:PORT,fork,reuseaddr PROTOCOL:HOST:PORT`.
+/// The statistics of the forwarder are written to `stats_path` every [`STATS_INTERVAL`].
+/// Only returns when the listening port cannot be bound or read from.
+pub fn run_forwarder(
+    protocol: &str,
+    listening_port: u16,
+    connect_host: &str,
+    connect_port: u16,
+    stats_path: &Path,
+) -> Result<(), Error> {
+    let stats = Arc::new(ForwarderStats::default());
+
+    let writer_stats = stats.clone();
+    let stats_path = stats_path.to_path_buf();
+    thread::spawn(move || loop {
+        if let Err(error) = write_stats(&stats_path, &writer_stats) {
+            eprintln!("Failed to write {}: {error}", stats_path.display());
+        }
+
+        thread::sleep(STATS_INTERVAL);
+    });
+
+    let listen_address = (Ipv4Addr::UNSPECIFIED, listening_port);
+
+    let result = match protocol {
+        "tcp" => forward_tcp(
+            TcpListener::bind(listen_address)?,
+            connect_host,
+            connect_port,
+            stats,
+        ),
+        "udp" => forward_udp(
+            UdpSocket::bind(listen_address)?,
+            connect_host,
+            connect_port,
+            stats,
+        ),
+        protocol => {
+            return Err(Error::Input(format!(
+                "The protocol must be either tcp or udp, not {protocol}"
+            )))
+        }
+    };
+
+    result.map_err(|x| Error::Io(format!("Failed to forward port {listening_port}: {x}")))
+}
+
+/// Forwards each accepted connection to the connect host and port, in a thread of its own.
+pub fn forward_tcp(
+    listener: TcpListener,
+    connect_host: &str,
+    connect_port: u16,
+    stats: Arc<ForwarderStats>,
+) -> io::Result<()> {
+    loop {
+        let (client, peer) = listener.accept()?;
+        let connect_host = connect_host.to_string();
+        let stats = stats.clone();
+
+        thread::spawn(move || {
+            let upstream = match TcpStream::connect((connect_host.as_str(), connect_port)) {
+                Ok(upstream) => upstream,
+                Err(error) => {
+                    eprintln!(
+                        "Failed to connect to {connect_host}:{connect_port} for {peer}: {error}"
+                    );
+                    return;
+                }
+            };
+
+            stats.open_connection();
+
+            let (Ok(client_reader), Ok(upstream_reader)) =
+                (client.try_clone(), upstream.try_clone())
+            else {
+                stats.close_connection();
+                return;
+            };
+
+            let sending_stats = stats.clone();
+            let sending = thread::spawn(move || {
+                copy_counting(client_reader, upstream, &sending_stats.bytes_sent)
+            });
+
+            copy_counting(upstream_reader, client, &stats.bytes_received);
+            let _ = sending.join();
+
+            stats.close_connection();
+        });
+    }
+}
+
+/// Forwards the datagrams of each peer through an upstream socket of its own, so that the replies
+/// of the connect host can be sent back to the peer they are meant for.
+pub fn forward_udp(
+    socket: UdpSocket,
+    connect_host: &str,
+    connect_port: u16,
+    stats: Arc<ForwarderStats>,
+) -> io::Result<()> {
+    let socket = Arc::new(socket);
+    let upstreams: Arc<Mutex<HashMap<SocketAddr, UdpSocket>>> = Arc::default();
+    let mut buffer = [0; 65536];
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer)?;
+
+        let existing_upstream = upstreams
+            .lock()
+            .unwrap()
+            .get(&peer)
+            .map(UdpSocket::try_clone);
+
+        let upstream = match existing_upstream {
+            Some(upstream) => upstream,
+            None => connect_udp_upstream(
+                &socket,
+                peer,
+                connect_host,
+                connect_port,
+                &upstreams,
+                &stats,
+            ),
+        };
+
+        match upstream.and_then(|x| x.send(&buffer[..len])) {
+            Ok(sent) => {
+                stats.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+            }
+            Err(error) => {
+                eprintln!("Failed to forward a datagram of {peer} to {connect_host}:{connect_port}: {error}")
+            }
+        }
+    }
+}
+
+/// The path of the statistics file of a built-in forwarder, which is named after its tunnel.
+pub fn forwarder_stats_path(session: &Session, parts: &[&str]) -> PathBuf {
+    session
+        .state_dir()
+        .join("forwarders")
+        .join(format!("{}.json", file_name(parts)))
+}
+
+/// Reads the statistics that a built-in forwarder has last written.
+pub fn read_forwarder_stats(path: &Path) -> Option<Map<String, Value>> {
+    let contents = fs::read_to_string(path).ok()?;
+
+    serde_json::from_str::<Value>(&contents)
+        .ok()?
+        .as_object()
+        .cloned()
+}
+
+fn connect_udp_upstream(
+    socket: &Arc<UdpSocket>,
+    peer: SocketAddr,
+    connect_host: &str,
+    connect_port: u16,
+    upstreams: &Arc<Mutex<HashMap<SocketAddr, UdpSocket>>>,
+    stats: &Arc<ForwarderStats>,
+) -> io::Result<UdpSocket> {
+    let address = (connect_host, connect_port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{connect_host} does not resolve to any address"),
+            )
+        })?;
+
+    let upstream = match address {
+        SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+        SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+    };
+    upstream.connect(address)?;
+    upstream.set_read_timeout(Some(UDP_IDLE_TIMEOUT))?;
+
+    upstreams
+        .lock()
+        .unwrap()
+        .insert(peer, upstream.try_clone()?);
+    stats.open_connection();
+
+    let socket = socket.clone();
+    let upstreams = upstreams.clone();
+    let stats = stats.clone();
+    let reader = upstream.try_clone()?;
+
+    thread::spawn(move || {
+        let mut buffer = [0; 65536];
+
+        // Stops relaying once the connect host has not replied for a while.
+        while let Ok(len) = reader.recv(&mut buffer) {
+            match socket.send_to(&buffer[..len], peer) {
+                Ok(sent) => {
+                    stats
+                        .bytes_received
+                        .fetch_add(sent as u64, Ordering::Relaxed);
+                }
+                Err(_) => break,
+            }
+        }
+
+        upstreams.lock().unwrap().remove(&peer);
+        stats.close_connection();
+    });
+
+    Ok(upstream)
+}
+
+/// Copies one direction of a connection, counting the copied bytes, and then shuts down the
+/// writing half of the other end, so that it sees the end of the stream.
+fn copy_counting(mut from: TcpStream, mut to: TcpStream, counter: &AtomicU64) {
+    let mut buffer = [0; 16384];
+
+    loop {
+        match from.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => {
+                if to.write_all(&buffer[..len]).is_err() {
+                    break;
+                }
+
+                counter.fetch_add(len as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    let _ = to.shutdown(Shutdown::Write);
+}
+
+/// Replaces the statistics file at once, so that it is never read half-written.
+fn write_stats(path: &Path, stats: &ForwarderStats) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temporary_path = PathBuf::from(format!("{}.tmp", path.display()));
+    fs::write(&temporary_path, stats.to_json().to_string())?;
+    fs::rename(temporary_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts a server which echoes whatever it receives and returns its address.
+    fn serve_tcp_echo() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = stream.try_clone().unwrap();
+                let _ = io::copy(&mut reader, &mut stream);
+            }
+        });
+
+        address
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        for _ in 0..50 {
+            if condition() {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn forward_tcp_relays_both_directions_and_counts_the_traffic() {
+        let upstream = serve_tcp_echo();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listening_address = listener.local_addr().unwrap();
+        let stats = Arc::new(ForwarderStats::default());
+
+        let forwarder_stats = stats.clone();
+        thread::spawn(move || forward_tcp(listener, "127.0.0.1", upstream.port(), forwarder_stats));
+
+        let mut client = TcpStream::connect(listening_address).unwrap();
+        client.write_all(b"hello").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "hello");
+
+        // The connection is closed by the forwarder's threads after the reply has been sent.
+        wait_for(|| stats.active_connections.load(Ordering::Relaxed) == 0);
+
+        assert_eq!(
+            stats.to_json(),
+            json!({"connections": 1, "activeConnections": 0, "bytesSent": 5, "bytesReceived": 5})
+        );
+    }
+
+    #[test]
+    fn forward_udp_relays_replies_to_each_peer() {
+        let upstream = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let mut buffer = [0; 1024];
+
+            loop {
+                let (len, peer) = upstream.recv_from(&mut buffer).unwrap();
+                let reply = String::from_utf8_lossy(&buffer[..len]).to_uppercase();
+                upstream.send_to(reply.as_bytes(), peer).unwrap();
+            }
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listening_address = socket.local_addr().unwrap();
+        let stats = Arc::new(ForwarderStats::default());
+
+        let forwarder_stats = stats.clone();
+        thread::spawn(move || forward_udp(socket, "127.0.0.1", upstream_port, forwarder_stats));
+
+        for message in ["first", "second"] {
+            let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+            peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            peer.send_to(message.as_bytes(), listening_address).unwrap();
+
+            let mut buffer = [0; 1024];
+            let len = peer.recv(&mut buffer).unwrap();
+            assert_eq!(&buffer[..len], message.to_uppercase().as_bytes());
+        }
+
+        // The traffic is counted once it has been relayed, which may be after the reply arrives.
+        wait_for(|| {
+            stats.bytes_sent.load(Ordering::Relaxed) == 11
+                && stats.bytes_received.load(Ordering::Relaxed) == 11
+        });
+
+        assert_eq!(
+            stats.to_json(),
+            json!({"connections": 2, "activeConnections": 2, "bytesSent": 11, "bytesReceived": 11})
+        );
+    }
+}
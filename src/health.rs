@@ -0,0 +1,261 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+
+use crate::Resource;
+
+/// How long a probe waits for an endpoint to accept a connection or to answer a request.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the reachability of an endpoint is checked.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthCheck {
+    /// The endpoint accepts TCP connections.
+    Tcp,
+    /// The endpoint answers an HTTP GET request for the path with the expected status.
+    Http { path: String, expected_status: u16 },
+}
+
+impl HealthCheck {
+    /// Records the health check in the specification of a registered process.
+    pub fn to_spec(&self, spec: &mut Map<String, Value>) {
+        if let HealthCheck::Http {
+            path,
+            expected_status,
+        } = self
+        {
+            spec.insert(String::from("healthCheckPath"), path.as_str().into());
+            spec.insert(String::from("healthCheckStatus"), (*expected_status).into());
+        }
+    }
+
+    /// Reads the health check from the specification of a registered process.
+    pub fn from_spec(spec: &Map<String, Value>) -> Self {
+        match spec.get("healthCheckPath").and_then(Value::as_str) {
+            Some(path) => HealthCheck::Http {
+                path: String::from(path),
+                expected_status: spec
+                    .get("healthCheckStatus")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(200) as u16,
+            },
+            None => HealthCheck::Tcp,
+        }
+    }
+}
+
+/// The outcome of probing an endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    Reachable,
+    Unreachable(String),
+    /// The endpoint could not be probed, eg, because a load balancer has no external IP yet.
+    Unknown(String),
+}
+
+impl Health {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Health::Reachable => "reachable",
+            Health::Unreachable(_) => "unreachable",
+            Health::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Adds the health of a resource's endpoint to its description and to its fields.
+    pub fn annotate(&self, resource: Resource) -> Resource {
+        let mut resource = resource.field("health", self.name());
+        resource.description = format!("{} [{self}]", resource.description);
+
+        match self {
+            Health::Reachable => resource,
+            Health::Unreachable(reason) | Health::Unknown(reason) => {
+                resource.field("healthDetail", reason.as_str())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::Reachable => write!(f, "reachable"),
+            Health::Unreachable(reason) => write!(f, "unreachable: {reason}"),
+            Health::Unknown(reason) => write!(f, "unknown: {reason}"),
+        }
+    }
+}
+
+/// Probes an endpoint with the given health check.
+pub fn probe(host: &str, port: u16, health_check: &HealthCheck) -> Health {
+    let stream = match connect(host, port) {
+        Ok(stream) => stream,
+        Err(reason) => return Health::Unreachable(reason),
+    };
+
+    let result = match health_check {
+        HealthCheck::Tcp => Ok(()),
+        HealthCheck::Http {
+            path,
+            expected_status,
+        } => probe_http(stream, host, port, path, *expected_status),
+    };
+
+    match result {
+        Ok(_) => Health::Reachable,
+        Err(reason) => Health::Unreachable(reason),
+    }
+}
+
+/// Combines the health of the endpoints of a single resource, which is reachable only if all of
+/// its endpoints are.
+pub fn combine(healths: Vec<Health>) -> Health {
+    healths
+        .into_iter()
+        .find(|x| *x != Health::Reachable)
+        .unwrap_or(Health::Reachable)
+}
+
+fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .map_err(|x| format!("failed to resolve {host}: {x}"))?;
+
+    let mut reason = format!("{host} does not resolve to any address");
+
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, PROBE_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => reason = format!("failed to connect to {address}: {error}"),
+        }
+    }
+
+    Err(reason)
+}
+
+fn probe_http(
+    mut stream: TcpStream,
+    host: &str,
+    port: u16,
+    path: &str,
+    expected_status: u16,
+) -> Result<(), String> {
+    let request_error = |x: std::io::Error| format!("HTTP GET {path} failed: {x}");
+
+    stream
+        .set_read_timeout(Some(PROBE_TIMEOUT))
+        .map_err(request_error)?;
+    stream
+        .set_write_timeout(Some(PROBE_TIMEOUT))
+        .map_err(request_error)?;
+
+    write!(
+        stream,
+        "GET {path} HTTP/1.0\r\nHost: {host}:{port}\r\nConnection: close\r\n\r\n"
+    )
+    .map_err(request_error)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .map_err(request_error)?;
+
+    // eg, HTTP/1.1 200 OK
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|x| x.parse::<u16>().ok())
+        .ok_or_else(|| format!("HTTP GET {path} returned an invalid response"))?;
+
+    if status != expected_status {
+        return Err(format!(
+            "HTTP GET {path} returned status {status} instead of {expected_status}"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Starts a server which answers a single request with the given HTTP status.
+    fn serve_status(status: u16) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\n\r\n"
+            );
+        });
+
+        port
+    }
+
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn tcp_probe_checks_that_the_port_accepts_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert_eq!(
+            probe("127.0.0.1", port, &HealthCheck::Tcp),
+            Health::Reachable
+        );
+        assert!(matches!(
+            probe("127.0.0.1", closed_port(), &HealthCheck::Tcp),
+            Health::Unreachable(_)
+        ));
+    }
+
+    #[test]
+    fn http_probe_checks_the_response_status() {
+        let health_check = HealthCheck::Http {
+            path: String::from("/healthz"),
+            expected_status: 204,
+        };
+
+        assert_eq!(
+            probe("127.0.0.1", serve_status(204), &health_check),
+            Health::Reachable
+        );
+        assert_eq!(
+            probe("127.0.0.1", serve_status(500), &health_check),
+            Health::Unreachable(String::from(
+                "HTTP GET /healthz returned status 500 instead of 204"
+            ))
+        );
+    }
+
+    #[test]
+    fn health_check_round_trips_through_a_spec() {
+        let health_check = HealthCheck::Http {
+            path: String::from("/"),
+            expected_status: 200,
+        };
+        let mut spec = Map::new();
+        health_check.to_spec(&mut spec);
+
+        assert_eq!(HealthCheck::from_spec(&spec), health_check);
+        assert_eq!(HealthCheck::from_spec(&Map::new()), HealthCheck::Tcp);
+    }
+}
@@ -1,40 +1,67 @@
-use crate::load_balancer::{create_load_balancer, delete_load_balancer};
+use crate::health::HealthCheck;
+use crate::kube_api::connect_kube_api;
+use crate::kube_context::select_kube_context;
+use crate::load_balancer::{
+    create_load_balancer, delete_load_balancer, load_balancer_service_name, validate_port_name,
+    validate_service_name, PortMapping, ServiceMetadata, TargetPort,
+};
 use crate::minikube_mount::{create_minikube_mount, delete_minikube_mount};
 use crate::minikube_tunnel::set_bind_address;
-use crate::socat_tunnel::{create_socat_tunnel, delete_socat_tunnel, set_default_connect_host};
+use crate::port_forward::{create_port_forward, delete_port_forward};
+use crate::protocol::Protocol;
+use crate::socat_tunnel::{
+    create_socat_tunnel, delete_socat_tunnel, set_default_connect_host, TunnelEngine,
+};
+use crate::validate::{describe_problems, validate_init_config};
 use crate::CommandResultType::PrintableResults;
-use crate::{flush_output, print_results, CommandExecutionResult, OptionFunc};
+use crate::{
+    flush_output, print_message, print_results, CommandExecutionResult, Error, OptionFunc, Session,
+};
 use json_comments::StripComments;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
 use std::{env, fs};
 
-pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
-    let init_file_path = get_init_file_path(path);
-
-    if init_file_path.is_none() {
+pub fn run_init_file(session: &Session, path: Option<String>) -> Result<Option<String>, Error> {
+    let Some(init_file_path) = get_init_file_path(session, path) else {
         return Ok(None);
-    }
+    };
 
-    let init_file_path = init_file_path.unwrap();
+    print_message(
+        session,
+        &format!("Found initialization file: {init_file_path}"),
+    );
 
-    println!("Found initialization file: {init_file_path}");
+    let init_config = parse_init_config_json(&init_file_path)?;
 
-    let init_config = parse_init_config_json(&init_file_path);
+    apply_cluster_settings(session, &init_config)?;
 
-    if let Some(load_balancers) = parse_load_balancers(&init_config) {
-        println!("Processing initialization file section: loadBalancers");
+    if let Some(load_balancers) = parse_load_balancers(&init_config)? {
+        print_message(
+            session,
+            "Processing initialization file section: loadBalancers",
+        );
 
         for LoadBalancerConfig {
             namespace,
             resource_type,
             name,
-            port,
-            target_port,
+            ports,
+            metadata,
+            ..
         } in load_balancers
         {
             print_results(
-                create_load_balancer(&namespace, &resource_type, &name, port, target_port),
+                session,
+                create_load_balancer(
+                    session,
+                    &namespace,
+                    &resource_type,
+                    &name,
+                    &ports,
+                    &metadata,
+                ),
                 true,
                 true,
             );
@@ -42,28 +69,73 @@ pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
         }
     }
 
-    if let Some(default_socat_connect_host) = init_config.get("defaultSocatConnectHost") {
-        let default_socat_connect_host = default_socat_connect_host
-            .as_str()
-            .expect("defaultSocatConnectHost must be a valid JSON string value")
-            .to_string();
-
-        println!("{}", set_default_connect_host(default_socat_connect_host));
+    if let Some(default_socat_connect_host) =
+        get_optional_json_string(&init_config, "defaultSocatConnectHost")?
+    {
+        print_message(
+            session,
+            &set_default_connect_host(session, default_socat_connect_host),
+        );
         flush_output();
     }
 
-    if let Some(socat_tunnels) = parse_socat_tunnels(&init_config) {
-        println!("Processing initialization file section: socatTunnels");
+    if let Some(socat_tunnels) = parse_socat_tunnels(&init_config)? {
+        print_message(
+            session,
+            "Processing initialization file section: socatTunnels",
+        );
 
         for SocatTunnelConfig {
             protocol,
             listening_port,
             connect_host,
             connect_port,
+            health_check,
+            engine,
         } in socat_tunnels
         {
             print_results(
-                create_socat_tunnel(&protocol, listening_port, &connect_host, connect_port),
+                session,
+                create_socat_tunnel(
+                    session,
+                    &protocol,
+                    listening_port,
+                    &connect_host,
+                    connect_port,
+                    &health_check,
+                    engine.unwrap_or(session.default_tunnel_engine()),
+                ),
+                true,
+                true,
+            );
+            flush_output();
+        }
+    }
+
+    if let Some(port_forwards) = parse_port_forwards(&init_config)? {
+        print_message(
+            session,
+            "Processing initialization file section: portForwards",
+        );
+
+        for PortForwardConfig {
+            namespace,
+            resource_type,
+            name,
+            local_port,
+            remote_port,
+        } in port_forwards
+        {
+            print_results(
+                session,
+                create_port_forward(
+                    session,
+                    &namespace,
+                    &resource_type,
+                    &name,
+                    local_port,
+                    remote_port,
+                ),
                 true,
                 true,
             );
@@ -71,8 +143,11 @@ pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
         }
     }
 
-    if let Some(minikube_mounts) = parse_minikube_mounts(&init_config) {
-        println!("Processing initialization file section: minikubeMounts");
+    if let Some(minikube_mounts) = parse_minikube_mounts(&init_config)? {
+        print_message(
+            session,
+            "Processing initialization file section: minikubeMounts",
+        );
 
         for MinikubeMountConfig {
             host_path,
@@ -80,7 +155,8 @@ pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
         } in minikube_mounts
         {
             print_results(
-                create_minikube_mount(&host_path, &minikube_path),
+                session,
+                create_minikube_mount(session, &host_path, &minikube_path),
                 true,
                 true,
             );
@@ -88,15 +164,12 @@ pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
         }
     }
 
-    if let Some(default_minikube_tunnel_bind_address) = init_config.get("minikubeTunnelBindAddress")
+    if let Some(default_minikube_tunnel_bind_address) =
+        get_optional_json_string(&init_config, "minikubeTunnelBindAddress")?
     {
-        let default_minikube_tunnel_bind_address = default_minikube_tunnel_bind_address
-            .as_str()
-            .expect("minikubeTunnelBindAddress must be a valid JSON string value")
-            .to_string();
-
         print_results(
-            set_bind_address(default_minikube_tunnel_bind_address),
+            session,
+            set_bind_address(session, default_minikube_tunnel_bind_address),
             true,
             true,
         );
@@ -107,68 +180,92 @@ pub fn run_init_file(path: Option<String>) -> Result<Option<String>, String> {
 
 pub fn build_clean_up_init_file_option(
     init_file_path: &str,
-) -> Result<(String, OptionFunc, bool), String> {
+) -> Result<(String, OptionFunc, bool), Error> {
     let init_file_path = init_file_path.to_string();
 
     Ok((
         String::from("Clean up initialization file configuration and exit"),
-        Box::new(move || clean_up_init_file(init_file_path.clone())),
+        Box::new(move |session| clean_up_init_file(session, init_file_path.clone())),
         true,
     ))
 }
 
-struct LoadBalancerConfig {
-    namespace: String,
-    resource_type: String,
-    name: String,
-    port: u16,
-    target_port: u16,
+pub struct LoadBalancerConfig {
+    pub namespace: String,
+    pub resource_type: String,
+    pub name: String,
+    pub ports: Vec<PortMapping>,
+    /// The name of the service, as configured or as made of the name and the ports of the exposed
+    /// resource, by which the load balancer is deleted.
+    pub service_name: String,
+    pub metadata: ServiceMetadata,
 }
 
-struct SocatTunnelConfig {
-    protocol: String,
-    listening_port: u16,
-    connect_host: String,
-    connect_port: u16,
+pub struct SocatTunnelConfig {
+    pub protocol: String,
+    pub listening_port: u16,
+    pub connect_host: String,
+    pub connect_port: u16,
+    pub health_check: HealthCheck,
+    /// The engine of the tunnel, or `None` for the session's default engine.
+    pub engine: Option<TunnelEngine>,
 }
 
-struct MinikubeMountConfig {
-    host_path: String,
-    minikube_path: String,
+pub struct PortForwardConfig {
+    pub namespace: String,
+    pub resource_type: String,
+    pub name: String,
+    pub local_port: u16,
+    pub remote_port: u16,
 }
 
-fn clean_up_init_file(init_file_path: String) -> CommandExecutionResult {
-    let init_config = parse_init_config_json(&init_file_path);
+pub struct MinikubeMountConfig {
+    pub host_path: String,
+    pub minikube_path: String,
+}
+
+pub fn clean_up_init_file(session: &Session, init_file_path: String) -> CommandExecutionResult {
+    let init_config = parse_init_config_json(&init_file_path)?;
 
-    if let Some(load_balancers) = parse_load_balancers(&init_config) {
-        println!("Cleaning up configuration from initialization file section: loadBalancers");
+    apply_cluster_settings(session, &init_config)?;
+
+    if let Some(load_balancers) = parse_load_balancers(&init_config)? {
+        print_message(
+            session,
+            "Cleaning up configuration from initialization file section: loadBalancers",
+        );
 
         for LoadBalancerConfig {
             namespace,
-            name,
-            port,
-            target_port,
+            service_name,
             ..
         } in load_balancers
         {
-            let name = format!("{name}-{port}-{target_port}-lb");
-            print_results(delete_load_balancer(&namespace, &name), true, true);
+            print_results(
+                session,
+                delete_load_balancer(session, &namespace, &service_name),
+                true,
+                true,
+            );
             flush_output();
         }
     }
 
-    if let Some(default_socat_connect_host) = init_config.get("defaultSocatConnectHost") {
-        let default_socat_connect_host = default_socat_connect_host
-            .as_str()
-            .expect("defaultSocatConnectHost must be a valid JSON string value")
-            .to_string();
-
-        println!("{}", set_default_connect_host(default_socat_connect_host));
+    if let Some(default_socat_connect_host) =
+        get_optional_json_string(&init_config, "defaultSocatConnectHost")?
+    {
+        print_message(
+            session,
+            &set_default_connect_host(session, default_socat_connect_host),
+        );
         flush_output();
     }
 
-    if let Some(socat_tunnels) = parse_socat_tunnels(&init_config) {
-        println!("Cleaning up configuration from initialization file section: socatTunnels");
+    if let Some(socat_tunnels) = parse_socat_tunnels(&init_config)? {
+        print_message(
+            session,
+            "Cleaning up configuration from initialization file section: socatTunnels",
+        );
 
         for SocatTunnelConfig {
             listening_port,
@@ -178,7 +275,8 @@ fn clean_up_init_file(init_file_path: String) -> CommandExecutionResult {
         } in socat_tunnels
         {
             print_results(
-                delete_socat_tunnel(listening_port, &connect_host, connect_port),
+                session,
+                delete_socat_tunnel(session, listening_port, &connect_host, connect_port),
                 true,
                 true,
             );
@@ -186,8 +284,35 @@ fn clean_up_init_file(init_file_path: String) -> CommandExecutionResult {
         }
     }
 
-    if let Some(minikube_mounts) = parse_minikube_mounts(&init_config) {
-        println!("Cleaning up configuration from initialization file section: minikubeMounts");
+    if let Some(port_forwards) = parse_port_forwards(&init_config)? {
+        print_message(
+            session,
+            "Cleaning up configuration from initialization file section: portForwards",
+        );
+
+        for PortForwardConfig {
+            namespace,
+            resource_type,
+            name,
+            local_port,
+            ..
+        } in port_forwards
+        {
+            print_results(
+                session,
+                delete_port_forward(session, &namespace, &resource_type, &name, local_port),
+                true,
+                true,
+            );
+            flush_output();
+        }
+    }
+
+    if let Some(minikube_mounts) = parse_minikube_mounts(&init_config)? {
+        print_message(
+            session,
+            "Cleaning up configuration from initialization file section: minikubeMounts",
+        );
 
         for MinikubeMountConfig {
             host_path,
@@ -195,7 +320,8 @@ fn clean_up_init_file(init_file_path: String) -> CommandExecutionResult {
         } in minikube_mounts
         {
             print_results(
-                delete_minikube_mount(&host_path, &minikube_path),
+                session,
+                delete_minikube_mount(session, &host_path, &minikube_path),
                 true,
                 true,
             );
@@ -206,12 +332,15 @@ fn clean_up_init_file(init_file_path: String) -> CommandExecutionResult {
     Ok(PrintableResults(None, Vec::new()))
 }
 
-fn get_init_file_path(path: Option<String>) -> Option<String> {
+pub fn get_init_file_path(session: &Session, path: Option<String>) -> Option<String> {
     let init_file_path = path.unwrap_or_else(|| {
         let init_file_environment_part = match env::var("KUBE_MINION_ENVIRONMENT") {
             Ok(envvar) => {
-                println!(
-                    "The KUBE_MINION_ENVIRONMENT environment variable has been set to {envvar}"
+                print_message(
+                    session,
+                    &format!(
+                        "The KUBE_MINION_ENVIRONMENT environment variable has been set to {envvar}"
+                    ),
                 );
 
                 format!(".{envvar}")
@@ -228,40 +357,97 @@ fn get_init_file_path(path: Option<String>) -> Option<String> {
     }
 }
 
-fn parse_init_config_json(init_file_path: &str) -> serde_json::Map<String, serde_json::Value> {
+pub fn parse_init_config_json(
+    init_file_path: &str,
+) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
     let mut init_file_content = String::new();
 
-    File::open(init_file_path)
-        .unwrap()
-        .read_to_string(&mut init_file_content)
-        .unwrap();
+    File::open(init_file_path)?.read_to_string(&mut init_file_content)?;
 
-    let init_file_content_reader = StripComments::new(init_file_content.as_bytes());
+    // Comments are replaced with whitespace, so the positions of the problems found in the
+    // stripped content are their positions in the file.
+    let mut content = String::new();
+    StripComments::new(init_file_content.as_bytes()).read_to_string(&mut content)?;
 
-    #[allow(clippy::expect_fun_call)] // clippy bug?
-    let init_config: serde_json::Value = serde_json::from_reader(init_file_content_reader)
-        .expect(&format!("{init_file_path} is not valid JSON"));
+    let init_config: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|x| Error::Parse(format!("{init_file_path} is not valid JSON: {x}")))?;
 
-    init_config
-        .as_object()
-        .expect("The initial configuration is not a valid JSON object")
-        .to_owned()
+    let problems = validate_init_config(&content, &init_config);
+    if !problems.is_empty() {
+        return Err(Error::Parse(describe_problems(init_file_path, &problems)));
+    }
+
+    match init_config.as_object() {
+        Some(init_config) => Ok(init_config.to_owned()),
+        None => Err(Error::Parse(String::from(
+            "The initial configuration is not a valid JSON object",
+        ))),
+    }
+}
+
+/// Applies the minikube profile, kubectl context and kubeconfig of the initialization file, unless
+/// they have already been chosen on the command line, and selects the kubectl context before any
+/// resource is created.
+pub fn apply_cluster_settings(
+    session: &Session,
+    init_config: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), Error> {
+    if session.minikube_profile().is_none()
+        && let Some(minikube_profile) = get_optional_json_string(init_config, "minikubeProfile")?
+    {
+        print_message(
+            session,
+            &format!("Using minikube profile: {minikube_profile}"),
+        );
+        session.set_minikube_profile(Some(minikube_profile));
+    }
+
+    if session.kube_context().is_none()
+        && let Some(kube_context) = get_optional_json_string(init_config, "kubeContext")?
+    {
+        session.set_kube_context(Some(kube_context));
+    }
+
+    if session.kubeconfig().is_none()
+        && let Some(kubeconfig) = get_optional_json_string(init_config, "kubeconfig")?
+    {
+        session.set_kubeconfig(Some(kubeconfig));
+    }
+
+    select_kube_context(session)?;
+
+    // The Kubernetes API is talked to with the kubectl context that has just been selected.
+    if session.kube_api().is_none() {
+        print_message(session, &connect_kube_api(session));
+    }
+
+    Ok(())
+}
+
+pub fn get_optional_json_string(
+    json: &serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> Result<Option<String>, Error> {
+    match json.get(key) {
+        Some(value) => match value.as_str() {
+            Some(value) => Ok(Some(String::from(value))),
+            None => Err(Error::Parse(format!(
+                "{key} must be a valid JSON string value"
+            ))),
+        },
+        None => Ok(None),
+    }
 }
 
 fn get_json_string(
     json: &serde_json::Map<String, serde_json::Value>,
     key: &str,
     default: Option<&str>,
-) -> String {
-    match json.get(key) {
-        Some(value) => {
-            match value.as_str() {
-                Some(value) => String::from(value),
-                None => panic!("{key} must be a valid JSON string value"),
-            }
-        }
-        None if let Some(default) = default => String::from(default),
-        None => panic!("{key} requires a JSON string value"),
+) -> Result<String, Error> {
+    match get_optional_json_string(json, key)? {
+        Some(value) => Ok(value),
+        None if let Some(default) = default => Ok(String::from(default)),
+        None => Err(Error::Parse(format!("{key} requires a JSON string value"))),
     }
 }
 
@@ -269,123 +455,683 @@ fn get_json_u16(
     json: &serde_json::Map<String, serde_json::Value>,
     key: &str,
     default: Option<u16>,
-) -> u16 {
+) -> Result<u16, Error> {
     match json.get(key) {
-        Some(value) => {
-            match value.as_u64() {
-                Some(value) => {
-                    if value == 0 || value > u16::MAX as u64 {
-                        panic!("{key} must be between 1 and {}", u16::MAX);
-                    }
+        Some(value) => match value.as_u64() {
+            Some(value) if value == 0 || value > u16::MAX as u64 => Err(Error::Parse(format!(
+                "{key} must be between 1 and {}",
+                u16::MAX
+            ))),
+            Some(value) => Ok(value as u16),
+            None => Err(Error::Parse(format!(
+                "{key} must be a valid JSON integer value"
+            ))),
+        },
+        None if let Some(default) = default => Ok(default),
+        None => Err(Error::Parse(format!("{key} requires a JSON integer value"))),
+    }
+}
 
-                    value as u16
-                },
-                None => panic!("{key} must be a valid JSON integer value"),
-            }
-        }
-        None if let Some(default) = default => default,
-        None => panic!("{key} requires a JSON integer value"),
+/// Returns the target port under `key`, which is either a port number or a port name, or the
+/// default port number if the key is absent.
+fn get_json_target_port(
+    json: &serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    default: u16,
+) -> Result<TargetPort, Error> {
+    match json.get(key) {
+        Some(serde_json::Value::String(value)) => value
+            .parse::<TargetPort>()
+            .map_err(|x| Error::Parse(format!("{key} must be a port number or name: {x}"))),
+        Some(_) => Ok(TargetPort::Number(get_json_u16(json, key, None)?)),
+        None => Ok(TargetPort::Number(default)),
     }
 }
 
-fn parse_load_balancers(
+/// Returns the strings of the object under `key`, eg, labels, or none if the key is absent.
+fn get_json_string_map(
+    json: &serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> Result<BTreeMap<String, String>, Error> {
+    let Some(value) = json.get(key) else {
+        return Ok(BTreeMap::new());
+    };
+
+    value
+        .as_object()
+        .ok_or_else(|| Error::Parse(format!("{key} requires a JSON object of strings")))?
+        .iter()
+        .map(|(k, v)| match v.as_str() {
+            Some(v) => Ok((k.clone(), String::from(v))),
+            None => Err(Error::Parse(format!("{key}.{k} must be a JSON string"))),
+        })
+        .collect()
+}
+
+/// Returns the objects of the array under `key`, if the key is present.
+fn get_json_objects<'a>(
+    init_config: &'a serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    what: &str,
+) -> Result<Option<Vec<&'a serde_json::Map<String, serde_json::Value>>>, Error> {
+    let Some(specs) = init_config.get(key) else {
+        return Ok(None);
+    };
+
+    let specs = specs.as_array().ok_or_else(|| {
+        Error::Parse(format!(
+            "The {key} key requires an array of {what} specifications"
+        ))
+    })?;
+
+    specs
+        .iter()
+        .map(|x| {
+            x.as_object().ok_or_else(|| {
+                Error::Parse(format!(
+                    "A {what} specification must be a valid JSON object"
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .map(Some)
+}
+
+pub fn parse_load_balancers(
     init_config: &serde_json::Map<String, serde_json::Value>,
-) -> Option<Vec<LoadBalancerConfig>> {
-    if let Some(load_balancer_specs) = init_config.get("loadBalancers") {
-        let mut load_balancers: Vec<LoadBalancerConfig> = Vec::new();
+) -> Result<Option<Vec<LoadBalancerConfig>>, Error> {
+    let Some(load_balancer_specs) =
+        get_json_objects(init_config, "loadBalancers", "load balancer")?
+    else {
+        return Ok(None);
+    };
 
-        for load_balancer in load_balancer_specs
-            .as_array()
-            .expect("The loadBalancers key requires an array of load balancer specifications")
-        {
-            let load_balancer = load_balancer
-                .as_object()
-                .expect("A load balancer specification must be a valid JSON object");
-
-            let namespace = get_json_string(load_balancer, "namespace", Some("default"));
-            let resource_type = get_json_string(load_balancer, "resourceType", Some("services"));
-            let name = get_json_string(load_balancer, "name", None);
-            let port = get_json_u16(load_balancer, "port", None);
-            let target_port = get_json_u16(load_balancer, "targetPort", Some(port));
-
-            load_balancers.push(LoadBalancerConfig {
-                namespace,
-                resource_type,
-                name,
-                port,
-                target_port,
-            });
+    let mut load_balancers: Vec<LoadBalancerConfig> = Vec::new();
+
+    for load_balancer in load_balancer_specs {
+        let namespace = get_json_string(load_balancer, "namespace", Some("default"))?;
+        let resource_type = get_json_string(load_balancer, "resourceType", Some("services"))?;
+        let name = get_json_string(load_balancer, "name", None)?;
+        let ports = match get_json_objects(load_balancer, "ports", "port mapping")? {
+            Some(port_mappings) => port_mappings
+                .into_iter()
+                .map(|x| {
+                    let name = get_optional_json_string(x, "name")?;
+                    if let Some(name) = &name {
+                        validate_port_name(name).map_err(Error::Parse)?;
+                    }
+
+                    Ok(PortMapping {
+                        name,
+                        ..parse_port_mapping(x)?
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+            None => vec![parse_port_mapping(load_balancer)?],
+        };
+
+        if ports.is_empty() {
+            return Err(Error::Parse(String::from(
+                "ports requires at least one port mapping",
+            )));
+        }
+
+        let metadata = ServiceMetadata {
+            name: get_optional_json_string(load_balancer, "serviceName")?,
+            labels: get_json_string_map(load_balancer, "labels")?,
+            annotations: get_json_string_map(load_balancer, "annotations")?,
+        };
+        let service_name = metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| load_balancer_service_name(&name, &ports));
+
+        if metadata.name.is_none() {
+            validate_service_name(&service_name)
+                .map_err(|x| Error::Parse(format!("{x}, so serviceName is required")))?;
         }
+        metadata.validate().map_err(Error::Parse)?;
 
-        return Some(load_balancers);
+        load_balancers.push(LoadBalancerConfig {
+            namespace,
+            resource_type,
+            name,
+            ports,
+            service_name,
+            metadata,
+        });
     }
 
-    None
+    Ok(Some(load_balancers))
 }
 
-fn parse_socat_tunnels(
+/// Parses the port, the target port and the protocol of an item of `ports`, or of a load balancer
+/// with a single port, but not its name, which, for a load balancer, is the name of the exposed
+/// resource rather than of its port.
+fn parse_port_mapping(
+    port_mapping: &serde_json::Map<String, serde_json::Value>,
+) -> Result<PortMapping, Error> {
+    let port = get_json_u16(port_mapping, "port", None)?;
+
+    Ok(PortMapping {
+        name: None,
+        port,
+        target_port: get_json_target_port(port_mapping, "targetPort", port)?,
+        protocol: get_json_string(port_mapping, "protocol", Some("tcp"))?
+            .parse()
+            .map_err(Error::Parse)?,
+    })
+}
+
+pub fn parse_socat_tunnels(
     init_config: &serde_json::Map<String, serde_json::Value>,
-) -> Option<Vec<SocatTunnelConfig>> {
-    if let Some(socat_tunnel_specs) = init_config.get("socatTunnels") {
-        let mut socat_tunnels: Vec<SocatTunnelConfig> = Vec::new();
+) -> Result<Option<Vec<SocatTunnelConfig>>, Error> {
+    let Some(socat_tunnel_specs) = get_json_objects(init_config, "socatTunnels", "socat tunnel")?
+    else {
+        return Ok(None);
+    };
 
-        for socat_tunnel in socat_tunnel_specs
-            .as_array()
-            .expect("The socatTunnels key requires an array of socat tunnel specifications")
-        {
-            let socat_tunnel = socat_tunnel
-                .as_object()
-                .expect("A socat tunnel specification must be a valid JSON object");
-
-            let protocol = get_json_string(socat_tunnel, "protocol", Some("tcp"))
-                .trim()
-                .to_lowercase();
-            if protocol != "tcp" && protocol != "udp" {
-                panic!("protocol must be either tcp or udp");
+    let mut socat_tunnels: Vec<SocatTunnelConfig> = Vec::new();
+
+    for socat_tunnel in socat_tunnel_specs {
+        let protocol = Protocol::parse_tunnel_protocol(&get_json_string(
+            socat_tunnel,
+            "protocol",
+            Some("tcp"),
+        )?)
+        .map_err(Error::Parse)?
+        .name()
+        .to_string();
+        let listening_port = get_json_u16(socat_tunnel, "listeningPort", None)?;
+        let connect_host = get_json_string(socat_tunnel, "connectHost", Some(""))?;
+        let connect_port = get_json_u16(socat_tunnel, "connectPort", None)?;
+        let health_check = match socat_tunnel.get("healthCheck") {
+            Some(serde_json::Value::Object(health_check)) => HealthCheck::Http {
+                path: get_json_string(health_check, "path", None)?,
+                expected_status: get_json_u16(health_check, "expectedStatus", Some(200))?,
+            },
+            Some(_) => {
+                return Err(Error::Parse(String::from(
+                    "healthCheck must be a valid JSON object",
+                )))
             }
-            let listening_port = get_json_u16(socat_tunnel, "listeningPort", None);
-            let connect_host = get_json_string(socat_tunnel, "connectHost", Some(""));
-            let connect_port = get_json_u16(socat_tunnel, "connectPort", None);
-
-            socat_tunnels.push(SocatTunnelConfig {
-                protocol,
-                listening_port,
-                connect_host,
-                connect_port,
-            });
-        }
+            None => HealthCheck::Tcp,
+        };
+        let engine = match get_optional_json_string(socat_tunnel, "engine")? {
+            Some(engine) => Some(engine.parse::<TunnelEngine>().map_err(Error::Parse)?),
+            None => None,
+        };
 
-        return Some(socat_tunnels);
+        socat_tunnels.push(SocatTunnelConfig {
+            protocol,
+            listening_port,
+            connect_host,
+            connect_port,
+            health_check,
+            engine,
+        });
     }
 
-    None
+    Ok(Some(socat_tunnels))
 }
 
-fn parse_minikube_mounts(
+pub fn parse_port_forwards(
     init_config: &serde_json::Map<String, serde_json::Value>,
-) -> Option<Vec<MinikubeMountConfig>> {
-    if let Some(minikube_mount_specs) = init_config.get("minikubeMounts") {
-        let mut minikube_mounts: Vec<MinikubeMountConfig> = Vec::new();
+) -> Result<Option<Vec<PortForwardConfig>>, Error> {
+    let Some(port_forward_specs) = get_json_objects(init_config, "portForwards", "port forward")?
+    else {
+        return Ok(None);
+    };
 
-        for minikube_mount in minikube_mount_specs
-            .as_array()
-            .expect("The minikubeMounts key requires an array of minikube mount specifications")
-        {
-            let minikube_mount = minikube_mount
-                .as_object()
-                .expect("A minikube mount specification must be a valid JSON object");
+    let mut port_forwards: Vec<PortForwardConfig> = Vec::new();
+
+    for port_forward in port_forward_specs {
+        let namespace = get_json_string(port_forward, "namespace", Some("default"))?;
+        let resource_type = get_json_string(port_forward, "resourceType", Some("services"))?;
+        let name = get_json_string(port_forward, "name", None)?;
+        let local_port = get_json_u16(port_forward, "localPort", None)?;
+        let remote_port = get_json_u16(port_forward, "remotePort", Some(local_port))?;
+
+        port_forwards.push(PortForwardConfig {
+            namespace,
+            resource_type,
+            name,
+            local_port,
+            remote_port,
+        });
+    }
+
+    Ok(Some(port_forwards))
+}
+
+pub fn parse_minikube_mounts(
+    init_config: &serde_json::Map<String, serde_json::Value>,
+) -> Result<Option<Vec<MinikubeMountConfig>>, Error> {
+    let Some(minikube_mount_specs) =
+        get_json_objects(init_config, "minikubeMounts", "minikube mount")?
+    else {
+        return Ok(None);
+    };
+
+    let mut minikube_mounts: Vec<MinikubeMountConfig> = Vec::new();
+
+    for minikube_mount in minikube_mount_specs {
+        let host_path = get_json_string(minikube_mount, "hostPath", None)?;
+        let minikube_path = get_json_string(minikube_mount, "minikubePath", None)?;
+
+        minikube_mounts.push(MinikubeMountConfig {
+            host_path,
+            minikube_path,
+        });
+    }
+
+    Ok(Some(minikube_mounts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestSession;
+
+    fn parse(json: &str) -> serde_json::Map<String, serde_json::Value> {
+        serde_json::from_str::<serde_json::Value>(json)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn write_init_file(session: &Session, content: &str) -> String {
+        fs::create_dir_all(session.state_dir()).unwrap();
+
+        let path = session.state_dir().join("kube-minion.json");
+        fs::write(&path, content).unwrap();
+
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn parse_load_balancers_applies_defaults() {
+        let load_balancers = parse_load_balancers(&parse(
+            r#"{"loadBalancers": [{"name": "web", "port": 8080}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(load_balancers.len(), 1);
+        assert_eq!(load_balancers[0].namespace, "default");
+        assert_eq!(load_balancers[0].resource_type, "services");
+        assert_eq!(load_balancers[0].name, "web");
+        assert_eq!(load_balancers[0].ports, vec![PortMapping::new(8080, 8080)]);
+    }
+
+    #[test]
+    fn parse_load_balancers_does_not_take_the_name_of_a_single_port_load_balancer_for_a_port_name()
+    {
+        let load_balancers = parse_load_balancers(&parse(
+            r#"{"loadBalancers": [{"name": "my-web-application", "port": 8080}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(load_balancers[0].name, "my-web-application");
+        assert_eq!(load_balancers[0].ports, vec![PortMapping::new(8080, 8080)]);
+    }
+
+    #[test]
+    fn parse_load_balancers_reads_several_ports() {
+        let load_balancers = parse_load_balancers(&parse(
+            r#"{"loadBalancers": [{"name": "web", "ports": [
+                {"name": "http", "port": 8080, "targetPort": 80},
+                {"name": "metrics", "port": 9090},
+                {"name": "dns", "port": 53, "protocol": "UDP"},
+                {"name": "admin", "port": 9091, "targetPort": "admin"}
+            ]}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            load_balancers[0].ports,
+            vec![
+                PortMapping {
+                    name: Some(String::from("http")),
+                    port: 8080,
+                    target_port: TargetPort::Number(80),
+                    protocol: Protocol::Tcp,
+                },
+                PortMapping {
+                    name: Some(String::from("metrics")),
+                    port: 9090,
+                    target_port: TargetPort::Number(9090),
+                    protocol: Protocol::Tcp,
+                },
+                PortMapping {
+                    name: Some(String::from("dns")),
+                    port: 53,
+                    target_port: TargetPort::Number(53),
+                    protocol: Protocol::Udp,
+                },
+                PortMapping {
+                    name: Some(String::from("admin")),
+                    port: 9091,
+                    target_port: TargetPort::Name(String::from("admin")),
+                    protocol: Protocol::Tcp,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_load_balancers_reads_the_service_name_labels_and_annotations() {
+        let load_balancers = parse_load_balancers(&parse(
+            r#"{"loadBalancers": [
+                {"name": "web", "port": 8080, "serviceName": "web-lb",
+                 "labels": {"team": "web"}, "annotations": {"owner": "ops"}},
+                {"name": "api", "port": 8081}
+            ]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(load_balancers[0].service_name, "web-lb");
+        assert_eq!(
+            load_balancers[0].metadata.describe(),
+            "labels team=web, annotations owner=ops"
+        );
+        assert_eq!(load_balancers[1].service_name, "api-8081-8081-lb");
+        assert_eq!(load_balancers[1].metadata, ServiceMetadata::default());
+    }
 
-            let host_path = get_json_string(minikube_mount, "hostPath", None);
-            let minikube_path = get_json_string(minikube_mount, "minikubePath", None);
+    #[test]
+    fn parse_load_balancers_rejects_invalid_service_names_and_reserved_keys() {
+        let long_name = "a".repeat(60);
 
-            minikube_mounts.push(MinikubeMountConfig {
-                host_path,
-                minikube_path,
-            });
+        for load_balancer in [
+            format!(r#"{{"name": "{long_name}", "port": 8080}}"#),
+            String::from(r#"{"name": "web", "port": 8080, "serviceName": "Web"}"#),
+            String::from(r#"{"name": "web", "port": 8080, "labels": {"reason": "mine"}}"#),
+            String::from(
+                r#"{"name": "web", "port": 8080, "annotations": {"kube-minion/resource-name": "x"}}"#,
+            ),
+        ] {
+            assert!(matches!(
+                parse_load_balancers(&parse(&format!(
+                    r#"{{"loadBalancers": [{load_balancer}]}}"#
+                ))),
+                Err(Error::Parse(_))
+            ));
         }
+    }
+
+    #[test]
+    fn parse_socat_tunnels_applies_defaults() {
+        let socat_tunnels = parse_socat_tunnels(&parse(
+            r#"{"socatTunnels": [{"protocol": " UDP ", "listeningPort": 5353, "connectPort": 53},
+                                 {"listeningPort": 8080, "connectHost": "db", "connectPort": 80,
+                                  "engine": "builtin"}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(socat_tunnels.len(), 2);
+        assert_eq!(socat_tunnels[0].protocol, "udp");
+        assert_eq!(socat_tunnels[0].listening_port, 5353);
+        assert_eq!(socat_tunnels[0].connect_host, "");
+        assert_eq!(socat_tunnels[0].connect_port, 53);
+        assert_eq!(socat_tunnels[0].engine, None);
+        assert_eq!(socat_tunnels[1].protocol, "tcp");
+        assert_eq!(socat_tunnels[1].connect_host, "db");
+        assert_eq!(socat_tunnels[1].health_check, HealthCheck::Tcp);
+        assert_eq!(socat_tunnels[1].engine, Some(TunnelEngine::Builtin));
+    }
+
+    #[test]
+    fn parse_socat_tunnels_reads_the_http_health_check() {
+        let socat_tunnels = parse_socat_tunnels(&parse(
+            r#"{"socatTunnels": [{"listeningPort": 8080, "connectPort": 80,
+                                  "healthCheck": {"path": "/healthz"}}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            socat_tunnels[0].health_check,
+            HealthCheck::Http {
+                path: String::from("/healthz"),
+                expected_status: 200,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_socat_tunnels_rejects_invalid_specifications() {
+        for json in [
+            r#"{"socatTunnels": {}}"#,
+            r#"{"socatTunnels": [8080]}"#,
+            r#"{"socatTunnels": [{"protocol": "sctp", "listeningPort": 1, "connectPort": 1}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 0, "connectPort": 1}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 65536, "connectPort": 1}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": "8080", "connectPort": 1}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 8080}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 1, "connectPort": 1, "healthCheck": "/"}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 1, "connectPort": 1, "healthCheck": {}}]}"#,
+            r#"{"socatTunnels": [{"listeningPort": 1, "connectPort": 1, "engine": "ssh"}]}"#,
+        ] {
+            assert!(
+                matches!(parse_socat_tunnels(&parse(json)), Err(Error::Parse(_))),
+                "{json} has been accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_port_forwards_applies_defaults() {
+        let port_forwards = parse_port_forwards(&parse(
+            r#"{"portForwards": [{"name": "db", "localPort": 5432},
+                                 {"namespace": "apps", "resourceType": "pod", "name": "web-0",
+                                  "localPort": 8080, "remotePort": 80}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(port_forwards.len(), 2);
+        assert_eq!(port_forwards[0].namespace, "default");
+        assert_eq!(port_forwards[0].resource_type, "services");
+        assert_eq!(port_forwards[0].name, "db");
+        assert_eq!(port_forwards[0].local_port, 5432);
+        assert_eq!(port_forwards[0].remote_port, 5432);
+        assert_eq!(port_forwards[1].namespace, "apps");
+        assert_eq!(port_forwards[1].resource_type, "pod");
+        assert_eq!(port_forwards[1].remote_port, 80);
+
+        assert!(matches!(
+            parse_port_forwards(&parse(r#"{"portForwards": [{"localPort": 5432}]}"#)),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn parse_minikube_mounts_requires_both_paths() {
+        let minikube_mounts = parse_minikube_mounts(&parse(
+            r#"{"minikubeMounts": [{"hostPath": "/home/user/src", "minikubePath": "/src"}]}"#,
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(minikube_mounts.len(), 1);
+        assert_eq!(minikube_mounts[0].host_path, "/home/user/src");
+        assert_eq!(minikube_mounts[0].minikube_path, "/src");
+
+        assert!(matches!(
+            parse_minikube_mounts(&parse(r#"{"minikubeMounts": [{"hostPath": "/src"}]}"#)),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(parse_minikube_mounts(&parse(r#"{}"#)), Ok(None)));
+    }
+
+    #[test]
+    fn run_init_file_creates_the_configured_resources() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(
+            &session,
+            &format!(
+                r#"{{
+                    // Comments are allowed
+                    "loadBalancers": [{{"name": "web", "port": 8080, "targetPort": 80}}],
+                    "defaultSocatConnectHost": "db",
+                    "socatTunnels": [{{"listeningPort": 5432, "connectPort": 5432}}],
+                    "portForwards": [{{"name": "db", "localPort": 5433, "remotePort": 5432}}],
+                    "minikubeMounts": [{{"hostPath": "{}", "minikubePath": "/src"}}],
+                    "minikubeTunnelBindAddress": "0.0.0.0"
+                }}"#,
+                env!("CARGO_MANIFEST_DIR")
+            ),
+        );
 
-        return Some(minikube_mounts);
+        let result = run_init_file(&session, Some(init_file_path.clone()));
+
+        assert_eq!(result, Ok(Some(init_file_path)));
+        assert_eq!(
+            session.runner.calls()[2],
+            "kubectl --context minikube -n default expose services web --type LoadBalancer \
+            --name web-8080-80-lb --port 8080 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=services"
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                String::from(
+                    "kubectl --context minikube -n default port-forward services/db 5433:5432"
+                ),
+                format!("minikube mount {}:/src", env!("CARGO_MANIFEST_DIR")),
+                String::from(
+                    "socat -lpkube-minion-socat tcp-listen:5432,fork,reuseaddr tcp:db:5432"
+                ),
+            ]
+        );
+        assert_eq!(session.bind_address(), "0.0.0.0");
+    }
+
+    #[test]
+    fn run_init_file_uses_the_cluster_settings_unless_they_are_chosen() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(
+            &session,
+            &format!(
+                r#"{{
+                    "minikubeProfile": "experimental",
+                    "kubeContext": "experimental",
+                    "minikubeMounts": [{{"hostPath": "{}", "minikubePath": "/src"}}]
+                }}"#,
+                env!("CARGO_MANIFEST_DIR")
+            ),
+        );
+
+        run_init_file(&session, Some(init_file_path.clone())).unwrap();
+
+        assert_eq!(session.minikube_profile().as_deref(), Some("experimental"));
+        assert_eq!(session.kube_context().as_deref(), Some("experimental"));
+        assert_eq!(
+            session.runner.running(),
+            vec![format!(
+                "minikube -p experimental mount {}:/src",
+                env!("CARGO_MANIFEST_DIR")
+            )]
+        );
+
+        let session = TestSession::new();
+        session.set_minikube_profile(Some(String::from("stable")));
+        session.set_kube_context(Some(String::from("stable")));
+
+        run_init_file(&session, Some(init_file_path)).unwrap();
+
+        assert_eq!(session.minikube_profile().as_deref(), Some("stable"));
+        assert_eq!(session.kube_context().as_deref(), Some("stable"));
+    }
+
+    #[test]
+    fn run_init_file_refuses_a_foreign_kube_context() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"kubeContext": "production", "loadBalancers": [{"name": "web", "port": 8080}]}"#,
+        );
+
+        assert!(matches!(
+            run_init_file(&session, Some(init_file_path)),
+            Err(Error::ForeignContext(_))
+        ));
+        assert_eq!(session.runner.calls(), vec!["minikube profile"]);
     }
 
-    None
+    #[test]
+    fn run_init_file_fails_on_invalid_json() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(&session, r#"{"loadBalancers": ["#);
+
+        assert!(matches!(
+            run_init_file(&session, Some(init_file_path)),
+            Err(Error::Parse(_))
+        ));
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn run_init_file_reports_all_problems_before_creating_anything() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{
+                // A misspelled key is not ignored.
+                "socatTunnels": [{"listeningPort": 5432, "connectport": 5432}]
+            }"#,
+        );
+
+        let result = run_init_file(&session, Some(init_file_path.clone()));
+
+        assert_eq!(
+            result,
+            Err(Error::Parse(format!(
+                "{init_file_path} is not a valid initialization file:\n\
+                {init_file_path}:3:34: connectPort is required\n\
+                {init_file_path}:3:58: unknown key connectport (did you mean connectPort?)"
+            )))
+        );
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn clean_up_init_file_deletes_the_configured_resources() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{
+                "loadBalancers": [{"name": "web", "port": 8080}],
+                "socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5432}],
+                "portForwards": [{"resourceType": "pod", "name": "db-0", "localPort": 5433}]
+            }"#,
+        );
+        run_init_file(&session, Some(init_file_path.clone())).unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "db",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        clean_up_init_file(&session, init_file_path).unwrap();
+
+        assert!(session.runner.calls().contains(&String::from(
+            "kubectl --context minikube -n default delete svc web-8080-8080-lb"
+        )));
+        assert_eq!(
+            session.runner.running(),
+            vec!["socat -lpkube-minion-socat tcp-listen:8080,fork,reuseaddr tcp:db:80"]
+        );
+    }
 }
@@ -0,0 +1,408 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use inotify::{Inotify, WatchMask};
+use serde_json::{Map, Value};
+
+use crate::apply::apply_init_config;
+use crate::init_file::{apply_cluster_settings, parse_init_config_json};
+use crate::CommandResultType::*;
+use crate::{CommandExecutionResult, Error, KubeApi, Session, Settings};
+
+/// How long to wait, once the initialization file has changed, for the editor to finish writing it,
+/// which can take more than one write.
+const SETTLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The keys of the initialization file which select the cluster that `kube-minion` operates on.
+const CLUSTER_SETTINGS: [&str; 3] = ["minikubeProfile", "kubeContext", "kubeconfig"];
+
+/// The settings and the Kubernetes API client with which a session operates on a cluster.
+type Cluster = (Settings, Option<Arc<KubeApi>>);
+
+/// Reloads the initialization file whenever it changes, by applying it, and records what has
+/// changed as events, to be reported to the user.
+/// Only the resources that the previous version of the file configures are deleted, so that the
+/// resources created from the menu survive the reload.
+pub struct InitFileWatcher {
+    init_file_path: String,
+    /// The session's settings before the initialization file has first been applied, whose
+    /// cluster settings, if given on the command line, take precedence over the file's.
+    initial_settings: Settings,
+    state: Mutex<InitFileWatcherState>,
+    stopped: AtomicBool,
+}
+
+struct InitFileWatcherState {
+    /// The version of the file that has last been applied, or `None` if it could not be parsed.
+    init_config: Option<Map<String, Value>>,
+    events: Vec<String>,
+}
+
+impl InitFileWatcher {
+    /// Prepares to reload the initialization file, which has already been processed as it is now.
+    pub fn new(init_file_path: &str, initial_settings: Settings) -> Self {
+        InitFileWatcher {
+            init_file_path: String::from(init_file_path),
+            initial_settings,
+            state: Mutex::new(InitFileWatcherState {
+                init_config: parse_init_config_json(init_file_path).ok(),
+                events: Vec::new(),
+            }),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Starts a thread which watches the initialization file for as long as `kube-minion` runs.
+    /// The file's directory is watched rather than the file itself, since editors often replace
+    /// the file instead of writing to it.
+    pub fn start(
+        session: Arc<Session>,
+        init_file_path: &str,
+        initial_settings: Settings,
+    ) -> Result<Arc<InitFileWatcher>, Error> {
+        let watcher = Arc::new(InitFileWatcher::new(init_file_path, initial_settings));
+        let thread_watcher = watcher.clone();
+
+        let path = Path::new(init_file_path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path.file_name().map(OsString::from);
+
+        let mut inotify = Inotify::init()?;
+        inotify
+            .watches()
+            .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+
+            loop {
+                let changed = match inotify.read_events_blocking(&mut buffer) {
+                    Ok(mut events) => events.any(|x| x.name == file_name.as_deref()),
+                    Err(_) => break,
+                };
+
+                if !changed {
+                    continue;
+                }
+
+                thread::sleep(SETTLE_INTERVAL);
+                while inotify.read_events(&mut buffer).is_ok() {}
+
+                thread_watcher.reload(&session);
+            }
+        });
+
+        Ok(watcher)
+    }
+
+    /// Applies the changes made to the initialization file since it has last been applied.
+    pub fn reload(&self, session: &Session) {
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let init_file_path = self.init_file_path.as_str();
+
+        let init_config = match parse_init_config_json(init_file_path) {
+            Ok(init_config) => init_config,
+            Err(error) => {
+                self.push_events(vec![format!(
+                    "Failed to reload {init_file_path}, the previous configuration remains in \
+                    effect: {error}"
+                )]);
+                return;
+            }
+        };
+
+        // A file which could not be parsed at start-up has not configured anything.
+        let previous_init_config = self
+            .state
+            .lock()
+            .unwrap()
+            .init_config
+            .clone()
+            .unwrap_or_default();
+
+        let previous_cluster =
+            match self.reload_cluster_settings(session, &previous_init_config, &init_config) {
+                Ok(previous_cluster) => previous_cluster,
+                Err(error) => {
+                    self.push_events(vec![format!(
+                        "Failed to reload {init_file_path}, the previous configuration remains in \
+                        effect: {error}"
+                    )]);
+                    return;
+                }
+            };
+
+        self.state.lock().unwrap().init_config = Some(init_config.clone());
+
+        let mut events: Vec<String> = vec![format!("{init_file_path} has changed")];
+
+        // The resources that the previous version of the file has configured are deleted from the
+        // cluster they have been created on, so that they are created on the new one.
+        if let Some((settings, kube_api)) = previous_cluster {
+            let (new_settings, new_kube_api) = (session.settings(), session.kube_api());
+            session.restore(settings);
+            session.set_kube_api(kube_api);
+
+            let result = apply_init_config(
+                session,
+                init_file_path,
+                &Map::new(),
+                Some(&previous_init_config),
+                &mut |result| events.extend(describe_changes(result)),
+            );
+            events.extend(describe_changes(result));
+
+            session.restore(new_settings);
+            session.set_kube_api(new_kube_api);
+        }
+
+        let result = apply_init_config(
+            session,
+            init_file_path,
+            &init_config,
+            Some(&previous_init_config),
+            &mut |result| events.extend(describe_changes(result)),
+        );
+        events.extend(describe_changes(result));
+
+        self.push_events(events);
+    }
+
+    /// Selects the cluster again, as at start-up, when the file's cluster settings have changed,
+    /// and returns the session's previous settings and Kubernetes API client, if so.
+    /// The session is left as it was when the cluster cannot be selected, eg, when the new kubectl
+    /// context does not belong to the new minikube profile.
+    fn reload_cluster_settings(
+        &self,
+        session: &Session,
+        previous_init_config: &Map<String, Value>,
+        init_config: &Map<String, Value>,
+    ) -> Result<Option<Cluster>, Error> {
+        if CLUSTER_SETTINGS
+            .iter()
+            .all(|x| previous_init_config.get(*x) == init_config.get(*x))
+        {
+            return Ok(None);
+        }
+
+        let settings = session.settings();
+        let kube_api = session.kube_api();
+
+        session.restore(Settings {
+            minikube_profile: self.initial_settings.minikube_profile.clone(),
+            kube_context: self.initial_settings.kube_context.clone(),
+            kubeconfig: self.initial_settings.kubeconfig.clone(),
+            ..settings.clone()
+        });
+        session.set_kube_api(None);
+
+        match apply_cluster_settings(session, init_config) {
+            Ok(()) => {
+                if let Some(kube_api) = session.kube_api() {
+                    kube_api.watch_services();
+                }
+
+                Ok(Some((settings, kube_api)))
+            }
+            Err(error) => {
+                session.restore(settings);
+                session.set_kube_api(kube_api);
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Stops reloading the initialization file, eg, before the resources are cleaned up.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns the events recorded since the last call.
+    pub fn take_events(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().events)
+    }
+
+    fn push_events(&self, events: Vec<String>) {
+        self.state.lock().unwrap().events.extend(events);
+    }
+}
+
+/// Describes the result of a step of applying the initialization file, leaving out the resources
+/// that are kept as they are.
+fn describe_changes(result: CommandExecutionResult) -> Vec<String> {
+    match result {
+        Ok(PrintableResults(_, messages)) => messages,
+        Ok(Resources(_, resources)) => resources
+            .into_iter()
+            .filter(|x| x.fields.get("action").and_then(Value::as_str) != Some("keep"))
+            .map(|x| x.description)
+            .collect(),
+        Ok(ChildProcess(_)) => Vec::new(),
+        Err(error) => vec![error.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthCheck;
+    use crate::run_init_file;
+    use crate::socat_tunnel::{create_socat_tunnel, TunnelEngine};
+    use crate::test_support::TestSession;
+    use std::fs;
+
+    fn write_init_file(session: &Session, content: &str) -> String {
+        fs::create_dir_all(session.state_dir()).unwrap();
+
+        let path = session.state_dir().join("kube-minion.json");
+        fs::write(&path, content).unwrap();
+
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn reload_applies_the_changes_made_to_the_file() {
+        let session = TestSession::new();
+        let initial_settings = session.settings();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5432},
+                                 {"listeningPort": 6379, "connectHost": "cache", "connectPort": 6379}]}"#,
+        );
+        run_init_file(&session, Some(init_file_path.clone())).unwrap();
+        // Created from the menu, so not configured by any version of the file.
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "web",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        let watcher = InitFileWatcher::new(&init_file_path, initial_settings);
+
+        write_init_file(
+            &session,
+            r#"{"socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5433}],
+                "portForwards": [{"name": "web", "localPort": 8081, "remotePort": 80}]}"#,
+        );
+        watcher.reload(&session);
+
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                "kubectl --context minikube -n default port-forward services/web 8081:80",
+                "socat -lpkube-minion-socat tcp-listen:5432,fork,reuseaddr tcp:db:5433",
+                "socat -lpkube-minion-socat tcp-listen:8080,fork,reuseaddr tcp:web:80",
+            ]
+        );
+        let events = watcher.take_events();
+        assert_eq!(events[0], format!("{init_file_path} has changed"));
+        assert!(events.contains(&String::from(
+            "recreate socat tunnel 5432/tcp (db:5433 with socat, tcp check, \
+            was db:5432 with socat, tcp check)"
+        )));
+        assert!(events.contains(&String::from(
+            "delete socat tunnel 6379/tcp (cache:6379 with socat, tcp check)"
+        )));
+        assert_eq!(
+            events.last().unwrap(),
+            &format!("Applied {init_file_path}: 1 created, 1 recreated, 1 deleted, 0 kept")
+        );
+        assert!(watcher.take_events().is_empty());
+    }
+
+    #[test]
+    fn reload_keeps_the_previous_configuration_when_the_file_is_invalid() {
+        let session = TestSession::new();
+        let initial_settings = session.settings();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5432}]}"#,
+        );
+        run_init_file(&session, Some(init_file_path.clone())).unwrap();
+        let watcher = InitFileWatcher::new(&init_file_path, initial_settings);
+
+        write_init_file(&session, r#"{"socatTunnels": ["#);
+        watcher.reload(&session);
+
+        assert!(watcher.take_events()[0].starts_with(&format!("Failed to reload {init_file_path}")));
+        assert_eq!(session.runner.running().len(), 1);
+
+        write_init_file(&session, r#"{}"#);
+        watcher.reload(&session);
+
+        assert!(session.runner.running().is_empty());
+    }
+
+    #[test]
+    fn reload_selects_the_cluster_again_when_the_profile_changes() {
+        let session = TestSession::new();
+        let initial_settings = session.settings();
+        let init_file_path = write_init_file(
+            &session,
+            r#"{"portForwards": [{"name": "web", "localPort": 8080, "remotePort": 80}]}"#,
+        );
+        run_init_file(&session, Some(init_file_path.clone())).unwrap();
+        let watcher = InitFileWatcher::new(&init_file_path, initial_settings);
+
+        write_init_file(
+            &session,
+            r#"{"minikubeProfile": "dev", "kubeContext": "dev",
+                "portForwards": [{"name": "web", "localPort": 8080, "remotePort": 80}]}"#,
+        );
+        watcher.reload(&session);
+
+        assert_eq!(session.minikube_profile().as_deref(), Some("dev"));
+        assert_eq!(session.kube_context().as_deref(), Some("dev"));
+        assert_eq!(
+            session.runner.running(),
+            vec!["kubectl --context dev -n default port-forward services/web 8080:80"]
+        );
+
+        // The kubectl context of the minikube profile is no longer given, so the current one,
+        // which belongs to another profile, would be operated on.
+        write_init_file(
+            &session,
+            r#"{"minikubeProfile": "staging",
+                "portForwards": [{"name": "web", "localPort": 8080, "remotePort": 80}]}"#,
+        );
+        watcher.reload(&session);
+
+        assert!(watcher.take_events().last().unwrap().starts_with(&format!(
+            "Failed to reload {init_file_path}, the previous configuration remains in effect"
+        )));
+        assert_eq!(session.minikube_profile().as_deref(), Some("dev"));
+        assert_eq!(session.kube_context().as_deref(), Some("dev"));
+    }
+
+    #[test]
+    fn a_stopped_watcher_does_not_reload() {
+        let session = TestSession::new();
+        let init_file_path = write_init_file(&session, r#"{}"#);
+        let watcher = InitFileWatcher::new(&init_file_path, session.settings());
+
+        write_init_file(
+            &session,
+            r#"{"socatTunnels": [{"listeningPort": 5432, "connectHost": "db", "connectPort": 5432}]}"#,
+        );
+        watcher.stop();
+        watcher.reload(&session);
+
+        assert!(session.runner.running().is_empty());
+        assert!(watcher.take_events().is_empty());
+    }
+}
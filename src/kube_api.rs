@@ -0,0 +1,772 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{env, fs, thread};
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::load_balancer::ServiceMetadata;
+use crate::{Error, Session};
+
+/// How long connecting to the API server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a request to the API server may take, apart from a watch.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the API server keeps a watch open, after which the services are listed and watched
+/// again.
+const WATCH_TIMEOUT_SECS: u64 = 300;
+
+/// How long to wait before listing and watching the services again, once a watch has ended.
+const WATCH_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The URL-encoded label selector of the services created by `kube-minion`.
+const KUBE_MINION_SELECTOR: &str = "reason%3Dkube-minion";
+
+/// The label which records the type of the resource that a load balancer exposes, so that the load
+/// balancer can be exported to an initialization file.
+pub const RESOURCE_TYPE_LABEL: &str = "kube-minion/resource-type";
+
+/// The kubeconfig files that kubectl reads, merged the way kubectl merges them, ie, the first file
+/// to set the current context, or to define a context, cluster or user of some name, wins.
+pub struct Kubeconfig {
+    current_context: Option<String>,
+    contexts: BTreeMap<String, Value>,
+    clusters: BTreeMap<String, Value>,
+    users: BTreeMap<String, Value>,
+}
+
+impl Kubeconfig {
+    /// Reads the session's kubeconfig file or, when none is set, the files in `$KUBECONFIG` or
+    /// `~/.kube/config`.
+    pub fn load(session: &Session) -> Result<Kubeconfig, Error> {
+        let paths = match session.kubeconfig() {
+            Some(kubeconfig) => vec![PathBuf::from(kubeconfig)],
+            None => match env::var_os("KUBECONFIG") {
+                Some(kubeconfig) if !kubeconfig.is_empty() => env::split_paths(&kubeconfig)
+                    .filter(|x| !x.as_os_str().is_empty())
+                    .collect(),
+                _ => vec![PathBuf::from(env::var("HOME").unwrap_or_default())
+                    .join(".kube")
+                    .join("config")],
+            },
+        };
+
+        Kubeconfig::load_files(&paths)
+    }
+
+    pub fn current_context(&self) -> Option<String> {
+        self.current_context.clone()
+    }
+
+    fn load_files(paths: &[PathBuf]) -> Result<Kubeconfig, Error> {
+        let mut kubeconfig = Kubeconfig {
+            current_context: None,
+            contexts: BTreeMap::new(),
+            clusters: BTreeMap::new(),
+            users: BTreeMap::new(),
+        };
+        let mut found = false;
+
+        for path in paths {
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            found = true;
+
+            let config: Value = serde_yaml::from_str(&contents).map_err(|x| {
+                Error::Parse(format!(
+                    "Failed to parse kubeconfig {}: {x}",
+                    path.display()
+                ))
+            })?;
+            let dir = path.parent().unwrap_or(Path::new("."));
+
+            if kubeconfig.current_context.is_none() {
+                kubeconfig.current_context = config["current-context"]
+                    .as_str()
+                    .filter(|x| !x.is_empty())
+                    .map(String::from);
+            }
+
+            for (key, entry_key, entries) in [
+                ("contexts", "context", &mut kubeconfig.contexts),
+                ("clusters", "cluster", &mut kubeconfig.clusters),
+                ("users", "user", &mut kubeconfig.users),
+            ] {
+                for entry in config[key].as_array().into_iter().flatten() {
+                    if let Some(name) = entry["name"].as_str() {
+                        entries
+                            .entry(String::from(name))
+                            .or_insert_with(|| resolve_paths(&entry[entry_key], dir));
+                    }
+                }
+            }
+        }
+
+        if !found {
+            return Err(Error::NotFound(format!(
+                "No kubeconfig file found at {}",
+                paths
+                    .iter()
+                    .map(|x| x.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )));
+        }
+
+        Ok(kubeconfig)
+    }
+}
+
+/// A client of the API server of a kubectl context, through which `kube-minion` manages its
+/// services instead of running kubectl.
+pub struct KubeApi {
+    server: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+    /// The services created by `kube-minion`, by namespace and name, as last seen by the watch, or
+    /// `None` while the services are not being watched.
+    services: Mutex<Option<BTreeMap<(String, String), Value>>>,
+}
+
+impl KubeApi {
+    /// Prepares a client for the cluster of a kubectl context, which authenticates with the
+    /// context's client certificate or bearer token, as minikube's contexts do.
+    /// Any other kind of credentials, eg, an exec plugin, is reported as an error, in which case
+    /// kubectl has to be run instead.
+    pub fn new(kubeconfig: &Kubeconfig, context: &str) -> Result<KubeApi, Error> {
+        let context_spec = kubeconfig
+            .contexts
+            .get(context)
+            .ok_or_else(|| Error::NotFound(format!("The kubeconfig has no context {context}")))?;
+
+        let cluster_name = context_spec["cluster"].as_str().unwrap_or_default();
+        let cluster = kubeconfig.clusters.get(cluster_name).ok_or_else(|| {
+            Error::NotFound(format!("The kubeconfig has no cluster {cluster_name}"))
+        })?;
+
+        let user_name = context_spec["user"].as_str().unwrap_or_default();
+        let user = kubeconfig.users.get(user_name).cloned().unwrap_or_default();
+
+        let server = cluster["server"]
+            .as_str()
+            .ok_or_else(|| Error::Parse(format!("The cluster {cluster_name} has no server")))?
+            .trim_end_matches('/')
+            .to_string();
+
+        if let Some(key) = ["exec", "auth-provider", "username"]
+            .into_iter()
+            .find(|x| !user[x].is_null())
+        {
+            return Err(Error::Api(format!(
+                "The {key} credentials of user {user_name} are not supported"
+            )));
+        }
+
+        if cluster["insecure-skip-tls-verify"].as_bool() == Some(true) {
+            return Err(Error::Api(format!(
+                "Skipping the TLS verification of cluster {cluster_name} is not supported"
+            )));
+        }
+
+        let token = match (user["token"].as_str(), user["tokenFile"].as_str()) {
+            (Some(token), _) => Some(String::from(token)),
+            (None, Some(token_file)) => Some(fs::read_to_string(token_file)?.trim().to_string()),
+            (None, None) => None,
+        };
+
+        let mut agent = ureq::AgentBuilder::new().timeout_connect(CONNECT_TIMEOUT);
+
+        if server.starts_with("https://") {
+            let certificate_authority =
+                read_data(cluster, "certificate-authority")?.ok_or_else(|| {
+                    Error::Api(format!(
+                        "The cluster {cluster_name} has no certificate authority"
+                    ))
+                })?;
+            let client_certificate = match (
+                read_data(&user, "client-certificate")?,
+                read_data(&user, "client-key")?,
+            ) {
+                (Some(certificate), Some(key)) => Some((certificate, key)),
+                _ => None,
+            };
+
+            agent = agent.tls_config(Arc::new(tls_config(
+                &certificate_authority,
+                client_certificate,
+            )?));
+        }
+
+        Ok(KubeApi {
+            server,
+            token,
+            agent: agent.build(),
+            services: Mutex::new(None),
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// Whether a load balancer can be created for a resource of the given type through the API,
+    /// which `kube-minion` has to ask for the selector of the resource, as `kubectl expose` does.
+    pub fn can_expose(resource_type: &str) -> bool {
+        exposable_resource(resource_type).is_some()
+    }
+
+    /// Creates a load balancer service for a resource, with the selector of the resource and the
+    /// labels and annotations of the metadata, and returns what `kubectl expose` would print.
+    pub fn expose(
+        &self,
+        namespace: &str,
+        resource_type: &str,
+        name: &str,
+        service_name: &str,
+        ports: Vec<Value>,
+        metadata: &ServiceMetadata,
+    ) -> Result<String, Error> {
+        let (_, _, selector_pointer) = exposable_resource(resource_type).ok_or_else(|| {
+            Error::Input(format!("Cannot expose resources of type {resource_type}"))
+        })?;
+
+        let resource = self.get_resource(namespace, resource_type, name)?;
+
+        let selector = resource
+            .pointer(selector_pointer)
+            .filter(|x| x.as_object().is_some_and(|x| !x.is_empty()))
+            .ok_or_else(|| {
+                Error::Api(format!(
+                    "Could not find a selector for {resource_type}/{name}"
+                ))
+            })?;
+
+        let mut labels = json!({"reason": "kube-minion", RESOURCE_TYPE_LABEL: resource_type});
+        for (key, value) in &metadata.labels {
+            labels[key] = value.as_str().into();
+        }
+
+        let service = self.request(
+            "POST",
+            &format!("/api/v1/namespaces/{namespace}/services"),
+            Some(&json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": {
+                    "name": service_name,
+                    "namespace": namespace,
+                    "labels": labels,
+                    "annotations": metadata.annotations,
+                },
+                "spec": {
+                    "type": "LoadBalancer",
+                    "selector": selector,
+                    "ports": ports,
+                },
+            })),
+        )?;
+
+        if let Some(services) = self.services.lock().unwrap().as_mut() {
+            services.insert(service_key(&service), service);
+        }
+
+        Ok(format!("service/{service_name} exposed"))
+    }
+
+    /// Returns a resource of a type that can be exposed, as the API server reports it.
+    pub fn get_resource(
+        &self,
+        namespace: &str,
+        resource_type: &str,
+        name: &str,
+    ) -> Result<Value, Error> {
+        let (api, resources, _) = exposable_resource(resource_type).ok_or_else(|| {
+            Error::Input(format!("Cannot expose resources of type {resource_type}"))
+        })?;
+
+        self.request(
+            "GET",
+            &format!("{api}/namespaces/{namespace}/{resources}/{name}"),
+            None,
+        )
+    }
+
+    /// Deletes a service and returns what `kubectl delete` would print.
+    pub fn delete_service(&self, namespace: &str, name: &str) -> Result<String, Error> {
+        self.request(
+            "DELETE",
+            &format!("/api/v1/namespaces/{namespace}/services/{name}"),
+            None,
+        )?;
+
+        if let Some(services) = self.services.lock().unwrap().as_mut() {
+            services.remove(&(String::from(namespace), String::from(name)));
+        }
+
+        Ok(format!("service \"{name}\" deleted"))
+    }
+
+    /// Lists the services created by `kube-minion` in all namespaces.
+    pub fn list_services(&self) -> Result<Vec<Value>, Error> {
+        let services = self.request(
+            "GET",
+            &format!("/api/v1/services?labelSelector={KUBE_MINION_SELECTOR}"),
+            None,
+        )?;
+
+        Ok(services["items"].as_array().cloned().unwrap_or_default())
+    }
+
+    /// Returns a service, as the API server reports it.
+    pub fn get_service(&self, namespace: &str, name: &str) -> Result<Value, Error> {
+        self.request(
+            "GET",
+            &format!("/api/v1/namespaces/{namespace}/services/{name}"),
+            None,
+        )
+    }
+
+    /// Whether a service created by `kube-minion` exists, as last seen by the watch, or as the API
+    /// server reports it while the services are not being watched.
+    pub fn has_service(&self, namespace: &str, name: &str) -> Result<bool, Error> {
+        if let Some(services) = self.services.lock().unwrap().as_ref() {
+            return Ok(services.contains_key(&(String::from(namespace), String::from(name))));
+        }
+
+        match self.request(
+            "GET",
+            &format!("/api/v1/namespaces/{namespace}/services/{name}"),
+            None,
+        ) {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Starts a thread which keeps track of the services created by `kube-minion` by watching
+    /// them, so that checking on them, eg, whenever the menu is refreshed, needs no request.
+    pub fn watch_services(self: &Arc<Self>) {
+        let kube_api = self.clone();
+
+        thread::spawn(move || loop {
+            if kube_api.sync_services().is_err() {
+                *kube_api.services.lock().unwrap() = None;
+            }
+
+            thread::sleep(WATCH_RETRY_INTERVAL);
+        });
+    }
+
+    /// Lists the services created by `kube-minion` and applies the changes to them until the
+    /// watch ends.
+    fn sync_services(&self) -> Result<(), Error> {
+        let services = self.request(
+            "GET",
+            &format!("/api/v1/services?labelSelector={KUBE_MINION_SELECTOR}"),
+            None,
+        )?;
+
+        *self.services.lock().unwrap() = Some(
+            services["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|x| (service_key(x), x.clone()))
+                .collect(),
+        );
+
+        // A watch stays open for as long as the API server keeps it, so it has no timeout.
+        let watch = self.send(
+            self.agent.get(&format!(
+                "{}/api/v1/services?labelSelector={KUBE_MINION_SELECTOR}&watch=1\
+                &resourceVersion={}&timeoutSeconds={WATCH_TIMEOUT_SECS}",
+                self.server,
+                services["metadata"]["resourceVersion"]
+                    .as_str()
+                    .unwrap_or_default()
+            )),
+            None,
+        )?;
+
+        for line in BufReader::new(watch.into_reader()).lines() {
+            let event: Value = serde_json::from_str(&line?)
+                .map_err(|x| Error::Parse(format!("Failed to parse a watch event: {x}")))?;
+            let service = &event["object"];
+
+            let mut services = self.services.lock().unwrap();
+            let Some(services) = services.as_mut() else {
+                break;
+            };
+
+            match event["type"].as_str() {
+                Some("ADDED" | "MODIFIED") => {
+                    services.insert(service_key(service), service.clone());
+                }
+                Some("DELETED") => {
+                    services.remove(&service_key(service));
+                }
+                // eg, the resource version is too old, in which case the services are listed again.
+                _ => {
+                    return Err(Error::Api(format!(
+                        "The watch of services has failed: {}",
+                        service["message"].as_str().unwrap_or_default()
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, Error> {
+        let request = self
+            .agent
+            .request(method, &format!("{}{path}", self.server))
+            .timeout(REQUEST_TIMEOUT);
+
+        self.send(request, body)?.into_json().map_err(|x| {
+            Error::Parse(format!(
+                "Failed to parse the response to {method} {path}: {x}"
+            ))
+        })
+    }
+
+    fn send(&self, request: ureq::Request, body: Option<&Value>) -> Result<ureq::Response, Error> {
+        let request = match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+            None => request,
+        };
+
+        let result = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, response)) => {
+                // The API server describes what has failed with a Status object.
+                let message = response
+                    .into_json::<Value>()
+                    .ok()
+                    .and_then(|x| x["message"].as_str().map(String::from))
+                    .unwrap_or_else(|| format!("The Kubernetes API answered with status {status}"));
+
+                Err(match status {
+                    404 => Error::NotFound(message),
+                    _ => Error::Api(message),
+                })
+            }
+            Err(error) => Err(Error::Api(format!(
+                "Failed to reach the Kubernetes API at {}: {error}",
+                self.server
+            ))),
+        }
+    }
+}
+
+/// Connects the session to the API server of its kubectl context, unless it has to run kubectl,
+/// and returns a message which says which of the two it does.
+/// When the kubeconfig cannot be used, eg, because its credentials are not supported, the session
+/// falls back to running kubectl.
+pub fn connect_kube_api(session: &Session) -> String {
+    // A dry run prints the kubectl commands that would change anything.
+    if session.use_kubectl() || session.dry_run() {
+        session.set_kube_api(None);
+
+        return String::from("Running kubectl for every Kubernetes operation");
+    }
+
+    let kube_api = Kubeconfig::load(session).and_then(|kubeconfig| {
+        let context = session
+            .kube_context()
+            .or(kubeconfig.current_context())
+            .ok_or_else(|| {
+                Error::NotFound(String::from(
+                    "The kubeconfig does not set a current context",
+                ))
+            })?;
+
+        KubeApi::new(&kubeconfig, &context)
+    });
+
+    match kube_api {
+        Ok(kube_api) => {
+            let message = format!("Talking to the Kubernetes API at {}", kube_api.server());
+            session.set_kube_api(Some(Arc::new(kube_api)));
+
+            message
+        }
+        Err(error) => {
+            session.set_kube_api(None);
+
+            format!("Falling back to kubectl, since the Kubernetes API cannot be used: {error}")
+        }
+    }
+}
+
+/// The API, the resource name and the pointer to the selector of the resource types that
+/// `kubectl expose` supports, by any of their names.
+fn exposable_resource(resource_type: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    match resource_type.to_lowercase().as_str() {
+        "svc" | "service" | "services" => Some(("/api/v1", "services", "/spec/selector")),
+        "po" | "pod" | "pods" => Some(("/api/v1", "pods", "/metadata/labels")),
+        "rc" | "replicationcontroller" | "replicationcontrollers" => {
+            Some(("/api/v1", "replicationcontrollers", "/spec/selector"))
+        }
+        "deploy" | "deployment" | "deployments" => {
+            Some(("/apis/apps/v1", "deployments", "/spec/selector/matchLabels"))
+        }
+        "rs" | "replicaset" | "replicasets" => {
+            Some(("/apis/apps/v1", "replicasets", "/spec/selector/matchLabels"))
+        }
+        _ => None,
+    }
+}
+
+fn service_key(service: &Value) -> (String, String) {
+    (
+        service["metadata"]["namespace"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        service["metadata"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    )
+}
+
+/// Resolves the file paths of a kubeconfig entry against the directory of its kubeconfig file, as
+/// kubectl does.
+fn resolve_paths(entry: &Value, dir: &Path) -> Value {
+    let mut entry = entry.clone();
+
+    for key in [
+        "certificate-authority",
+        "client-certificate",
+        "client-key",
+        "tokenFile",
+    ] {
+        if let Some(path) = entry[key].as_str().map(PathBuf::from)
+            && path.is_relative()
+        {
+            entry[key] = Value::from(dir.join(path).to_string_lossy().to_string());
+        }
+    }
+
+    entry
+}
+
+/// Reads the base64 encoded `KEY-data` of a kubeconfig entry or, when there is none, the file at
+/// `KEY`.
+fn read_data(entry: &Value, key: &str) -> Result<Option<Vec<u8>>, Error> {
+    if let Some(data) = entry[format!("{key}-data")].as_str() {
+        return base64::engine::general_purpose::STANDARD
+            .decode(data.trim())
+            .map(Some)
+            .map_err(|x| Error::Parse(format!("{key}-data is not valid base64: {x}")));
+    }
+
+    match entry[key].as_str() {
+        Some(path) => Ok(Some(fs::read(path)?)),
+        None => Ok(None),
+    }
+}
+
+fn tls_config(
+    certificate_authority: &[u8],
+    client_certificate: Option<(Vec<u8>, Vec<u8>)>,
+) -> Result<rustls::ClientConfig, Error> {
+    let tls_error = |x: rustls::Error| Error::Api(format!("Failed to set up TLS: {x}"));
+
+    let mut roots = rustls::RootCertStore::empty();
+    for certificate in rustls_pemfile::certs(&mut &certificate_authority[..]) {
+        roots.add(certificate?).map_err(tls_error)?;
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(tls_error)?
+    .with_root_certificates(roots);
+
+    match client_certificate {
+        Some((certificate, key)) => {
+            let certificates = rustls_pemfile::certs(&mut &certificate[..])
+                .collect::<Result<Vec<_>, io::Error>>()?;
+            let key = rustls_pemfile::private_key(&mut &key[..])?.ok_or_else(|| {
+                Error::Parse(String::from(
+                    "The client key does not contain a private key",
+                ))
+            })?;
+
+            config
+                .with_client_auth_cert(certificates, key)
+                .map_err(tls_error)
+        }
+        None => Ok(config.with_no_client_auth()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{StubApiServer, TestSession};
+
+    fn write_file(session: &Session, name: &str, contents: &str) -> PathBuf {
+        fs::create_dir_all(session.state_dir()).unwrap();
+
+        let path = session.state_dir().join(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn kubeconfig_files_are_merged_as_kubectl_merges_them() {
+        let session = TestSession::new();
+        let first = write_file(
+            &session,
+            "first",
+            "current-context: stable
+contexts:
+- name: stable
+  context: {cluster: stable, user: stable}
+users:
+- name: stable
+  user: {client-certificate: certs/stable.crt, client-key: /keys/stable.key}
+",
+        );
+        let second = write_file(
+            &session,
+            "second",
+            "current-context: experimental
+contexts:
+- name: stable
+  context: {cluster: experimental, user: experimental}
+- name: experimental
+  context: {cluster: experimental, user: experimental}
+clusters:
+- name: stable
+  cluster: {server: 'https://192.168.49.2:8443/'}
+",
+        );
+        let missing = session.state_dir().join("missing");
+
+        let kubeconfig = Kubeconfig::load_files(&[missing, first, second]).unwrap();
+
+        assert_eq!(kubeconfig.current_context().as_deref(), Some("stable"));
+        assert_eq!(kubeconfig.contexts["stable"]["cluster"], "stable");
+        assert_eq!(kubeconfig.contexts.len(), 2);
+        assert_eq!(
+            kubeconfig.users["stable"]["client-certificate"],
+            session
+                .state_dir()
+                .join("certs/stable.crt")
+                .to_string_lossy()
+                .as_ref()
+        );
+        assert_eq!(kubeconfig.users["stable"]["client-key"], "/keys/stable.key");
+        assert!(matches!(
+            Kubeconfig::load_files(&[session.state_dir().join("missing")]),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_credentials_fall_back_to_kubectl() {
+        let session = TestSession::new();
+        let kubeconfig = write_file(
+            &session,
+            "kubeconfig",
+            "current-context: eks
+contexts:
+- name: eks
+  context: {cluster: eks, user: eks}
+clusters:
+- name: eks
+  cluster: {server: 'https://eks.example.com'}
+users:
+- name: eks
+  user: {exec: {command: aws}}
+",
+        );
+        session.restore(crate::Settings {
+            kubeconfig: Some(kubeconfig.to_string_lossy().to_string()),
+            use_kubectl: false,
+            ..session.settings()
+        });
+
+        assert_eq!(
+            connect_kube_api(&session),
+            "Falling back to kubectl, since the Kubernetes API cannot be used: \
+            The exec credentials of user eks are not supported"
+        );
+        assert!(session.kube_api().is_none());
+    }
+
+    #[test]
+    fn watched_services_are_kept_up_to_date() {
+        let session = TestSession::new();
+        let server = StubApiServer::start();
+        session.connect_kube_api(&server);
+        server.respond(
+            "GET /api/v1/services?labelSelector=reason%3Dkube-minion",
+            200,
+            r#"{"metadata": {"resourceVersion": "7"}, "items": [
+                {"metadata": {"namespace": "default", "name": "web-8080-80-lb"}}
+            ]}"#,
+        );
+        server.respond(
+            "GET /api/v1/services?labelSelector=reason%3Dkube-minion&watch=1&resourceVersion=7",
+            200,
+            r#"{"type": "ADDED", "object": {"metadata": {"namespace": "kubernetes-dashboard", "name": "kubernetes-dashboard-lb"}}}
+{"type": "DELETED", "object": {"metadata": {"namespace": "default", "name": "web-8080-80-lb"}}}
+"#,
+        );
+        let kube_api = session.kube_api().unwrap();
+
+        kube_api.sync_services().unwrap();
+
+        assert_eq!(
+            kube_api.has_service("kubernetes-dashboard", "kubernetes-dashboard-lb"),
+            Ok(true)
+        );
+        assert_eq!(kube_api.has_service("default", "web-8080-80-lb"), Ok(false));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn api_errors_are_classified_by_status() {
+        let session = TestSession::new();
+        let server = StubApiServer::start();
+        session.connect_kube_api(&server);
+        server.respond(
+            "DELETE /api/v1/namespaces/default/services/web",
+            403,
+            r#"{"kind": "Status", "reason": "Forbidden", "message": "services \"web\" is forbidden"}"#,
+        );
+        let kube_api = session.kube_api().unwrap();
+
+        assert_eq!(
+            kube_api.delete_service("default", "web"),
+            Err(Error::Api(String::from("services \"web\" is forbidden")))
+        );
+        assert!(matches!(
+            kube_api.delete_service("default", "db"),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(kube_api.has_service("default", "db"), Ok(false));
+    }
+}
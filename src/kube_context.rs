@@ -0,0 +1,149 @@
+use crate::kube_api::Kubeconfig;
+use crate::{run_kubectl, start_and_read_process, Error, Session};
+
+/// Selects the kubectl context that every kubectl call of the session is made with and checks that
+/// it belongs to the minikube profile, which minikube names its context after.
+/// When no context has been given, the current context is pinned, so that switching contexts in
+/// another shell does not make `kube-minion` create load balancers in some other cluster.
+pub fn select_kube_context(session: &Session) -> Result<(), Error> {
+    let kube_context = match session.kube_context() {
+        Some(kube_context) => kube_context,
+        None => read_current_kube_context(session)?,
+    };
+
+    let minikube_profile = match session.minikube_profile() {
+        Some(minikube_profile) => minikube_profile,
+        None => start_and_read_process(
+            session,
+            "minikube",
+            &["profile"],
+            Some(String::from("Failed to read the active minikube profile")),
+        )?
+        .trim()
+        .to_string(),
+    };
+
+    if kube_context != minikube_profile && !session.allow_foreign_kube_context() {
+        return Err(Error::ForeignContext(format!(
+            "The kubectl context {kube_context} does not belong to the minikube profile \
+            {minikube_profile}, use --allow-foreign-context to operate on it anyway"
+        )));
+    }
+
+    session.set_kube_context(Some(kube_context));
+
+    Ok(())
+}
+
+/// Reads the current context from the kubeconfig, or has kubectl read it, when the session runs
+/// kubectl or the kubeconfig cannot be read directly.
+fn read_current_kube_context(session: &Session) -> Result<String, Error> {
+    if !session.use_kubectl()
+        && let Ok(Some(kube_context)) = Kubeconfig::load(session).map(|x| x.current_context())
+    {
+        return Ok(kube_context);
+    }
+
+    Ok(run_kubectl(
+        session,
+        &["config", "current-context"],
+        Some(String::from("Failed to read the current kubectl context")),
+    )?
+    .trim()
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_balancer::{create_load_balancer, PortMapping, ServiceMetadata};
+    use crate::test_support::{StubApiServer, TestSession};
+    use crate::Settings;
+
+    #[test]
+    fn select_kube_context_pins_the_current_context() {
+        let session = TestSession::new();
+
+        select_kube_context(&session).unwrap();
+        create_load_balancer(
+            &session,
+            "default",
+            "services",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        )
+        .unwrap();
+
+        assert_eq!(session.kube_context().as_deref(), Some("minikube"));
+        assert_eq!(
+            session.runner.calls()[2],
+            "kubectl --context minikube -n default expose services web --type LoadBalancer \
+            --name web-8080-80-lb --port 8080 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=services"
+        );
+    }
+
+    #[test]
+    fn select_kube_context_refuses_a_foreign_context() {
+        let session = TestSession::new();
+        session.set_minikube_profile(Some(String::from("experimental")));
+        session
+            .runner
+            .respond_ok("kubectl config current-context", "production\n");
+
+        assert!(matches!(
+            select_kube_context(&session),
+            Err(Error::ForeignContext(_))
+        ));
+        assert_eq!(session.kube_context(), None);
+
+        session.restore(Settings {
+            allow_foreign_kube_context: true,
+            ..session.settings()
+        });
+
+        assert_eq!(select_kube_context(&session), Ok(()));
+        assert_eq!(session.kube_context().as_deref(), Some("production"));
+    }
+
+    #[test]
+    fn select_kube_context_uses_the_given_context_and_kubeconfig() {
+        let session = TestSession::new();
+        session.set_minikube_profile(Some(String::from("stable")));
+        session.set_kube_context(Some(String::from("stable")));
+        session.set_kubeconfig(Some(String::from("/home/user/.kube/minikube")));
+
+        select_kube_context(&session).unwrap();
+        create_load_balancer(
+            &session,
+            "default",
+            "services",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl --kubeconfig /home/user/.kube/minikube --context stable -n default \
+                expose services web --type LoadBalancer --name web-8080-80-lb --port 8080 \
+                --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=services"
+            ]
+        );
+    }
+
+    #[test]
+    fn select_kube_context_reads_the_current_context_from_the_kubeconfig() {
+        let session = TestSession::new();
+        session.connect_kube_api(&StubApiServer::start());
+
+        select_kube_context(&session).unwrap();
+
+        assert_eq!(session.kube_context().as_deref(), Some("minikube"));
+        assert_eq!(session.runner.calls(), vec!["minikube profile"]);
+    }
+}
@@ -1,94 +1,118 @@
 #![feature(let_chains)]
 #![feature(if_let_guard)]
 
+mod apply;
 mod clean_up_and_exit;
 mod dashboard;
+mod error;
+mod export;
+mod forwarder;
+mod health;
 mod init_file;
+mod init_file_watcher;
+mod kube_api;
+mod kube_context;
 mod load_balancer;
 mod minikube_mount;
 mod minikube_tunnel;
+mod output;
+mod port_forward;
+mod process_log;
+mod process_runner;
+mod protocol;
+mod registry;
+mod session;
 mod socat_tunnel;
+mod subcommand;
+mod supervisor;
+#[cfg(test)]
+mod test_support;
+mod validate;
 
 use std::io::{stderr, stdin, stdout, BufRead, Write};
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::{
-    io::Read,
-    process::{self, Command, ExitStatus, Stdio},
-    sync::{Arc, Mutex},
-};
 
+use apply::build_apply_init_file_option;
+use export::build_export_init_file_option;
 use init_file::build_clean_up_init_file_option;
-use sysinfo::{ProcessExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
 
-pub use crate::clean_up_and_exit::clean_up;
-pub use dashboard::{create_kubernetes_dashboard_load_balancer, set_dashboard_port};
-pub use init_file::run_init_file;
-pub use minikube_tunnel::create_minikube_tunnel;
+pub use crate::apply::apply_init_file;
+pub use crate::clean_up_and_exit::{clean_up, clean_up_on_signal, SignalPolicy};
+pub use dashboard::create_kubernetes_dashboard_load_balancer;
+pub use error::Error;
+pub use health::{Health, HealthCheck};
+pub use init_file::{get_init_file_path, run_init_file};
+pub use init_file_watcher::InitFileWatcher;
+pub use kube_api::{connect_kube_api, KubeApi};
+pub use kube_context::select_kube_context;
+pub use minikube_tunnel::{create_minikube_tunnel, create_minikube_tunnel_with};
+pub use output::{print_message, OutputFormat, Resource};
+pub use process_log::RotatingLog;
+pub use process_runner::{
+    DryRunProcessRunner, FakeProcessRunner, OnExit, ProcessInfo, ProcessOutput, ProcessRunner,
+    SystemProcessRunner,
+};
+pub use session::{Session, Settings};
+pub use socat_tunnel::TunnelEngine;
+pub use subcommand::{parse_subcommand, run_subcommand, subcommand_usage, Subcommand, SUBCOMMANDS};
+pub use supervisor::Supervisor;
 
 use crate::clean_up_and_exit::build_clean_up_and_exit_option;
 use dashboard::*;
 use load_balancer::*;
 use minikube_mount::*;
 use minikube_tunnel::*;
+use output::*;
+use port_forward::*;
+use process_log::*;
 use socat_tunnel::*;
 use CommandResultType::*;
 
-type CommandExecutionResult = Result<CommandResultType, String>;
+type CommandExecutionResult = Result<CommandResultType, Error>;
 
-pub type OptionFunc = Box<dyn Fn() -> CommandExecutionResult>;
+pub type OptionFunc = Box<dyn Fn(&Session) -> CommandExecutionResult>;
 
 pub enum CommandResultType {
-    ChildProcess(Option<(Arc<Mutex<process::Child>>, ExitStatus)>),
+    ChildProcess(ProcessOutput),
     PrintableResults(Option<String>, Vec<String>),
+    Resources(Option<String>, Vec<Resource>),
 }
 
-impl CommandResultType {
-    fn child_process(self) -> Option<(Arc<Mutex<process::Child>>, ExitStatus)> {
-        match self {
-            ChildProcess(maybe_child) => maybe_child,
-            PrintableResults(..) => {
-                panic!("CommandResultType.child_process called on a non ChildProcess variant")
-            }
-        }
+/// Checks that the required commands are present.
+/// `kubectl` is required even when the session talks to the Kubernetes API directly, since port
+/// forwards run it and the resources that the API cannot handle fall back to it, whereas `socat`
+/// is optional, since socat tunnels default to the built-in forwarder without it.
+pub fn verify_dependencies(session: &Session) -> Result<(), Error> {
+    for (command, version_arg) in [
+        ("minikube", "version"),
+        ("ssh", "-V"),
+        ("kubectl", "--version"),
+    ] {
+        session
+            .runner()
+            .run(command, &[version_arg])
+            .map_err(|_| Error::MissingDependency(String::from(command)))?;
     }
-}
-
-pub fn verify_dependencies() -> Result<(), String> {
-    let could_not_find = |what| format!("Could not find {what} in path");
-
-    Command::new("minikube")
-        .arg("version")
-        .output()
-        .map_err(|_| could_not_find("minikube"))?;
-
-    Command::new("kubectl")
-        .arg("--version")
-        .output()
-        .map_err(|_| could_not_find("kubectl"))?;
 
-    Command::new("socat")
-        .arg("-V")
-        .output()
-        .map_err(|_| could_not_find("socat"))?;
-
-    Command::new("ssh")
-        .arg("-V")
-        .output()
-        .map_err(|_| could_not_find("ssh"))?;
+    if session.runner().run("socat", &["-V"]).is_err() {
+        session.set_default_tunnel_engine(TunnelEngine::Builtin);
+    }
 
     Ok(())
 }
 
 pub fn build_options(
+    session: &Session,
     init_file_path: Option<&String>,
-) -> Result<Vec<(String, OptionFunc, bool)>, String> {
-    let do_nothing: fn() -> OptionFunc = || Box::new(|| Ok(PrintableResults(None, Vec::new())));
+) -> Result<Vec<(String, OptionFunc, bool)>, Error> {
+    let do_nothing: fn() -> OptionFunc = || Box::new(|_| Ok(PrintableResults(None, Vec::new())));
 
     Ok(vec![
         Some((String::from("# Dashboard"), do_nothing(), false)),
-        Some(build_kubernetes_dashboard_option()?),
+        Some(build_kubernetes_dashboard_option(session)?),
         Some((String::from("# Minikube tunnel"), do_nothing(), false)),
-        Some(build_minikube_tunnel_option()?),
+        Some(build_minikube_tunnel_option(session)?),
         Some(build_set_bind_address_option()?),
         Some((String::from("# Load balancers"), do_nothing(), false)),
         Some(build_create_load_balancer_option()?),
@@ -101,11 +125,21 @@ pub fn build_options(
         Some(build_delete_socat_tunnel_option()?),
         Some(build_delete_all_socat_tunnels_option()?),
         Some(build_set_default_connect_host_option()?),
+        Some((String::from("# Port forwards"), do_nothing(), false)),
+        Some(build_create_port_forward_option()?),
+        Some(build_fetch_port_forwards_option()?),
+        Some(build_delete_port_forward_option()?),
+        Some(build_delete_all_port_forwards_option()?),
         Some((String::from("# Minikube mounts"), do_nothing(), false)),
         Some(build_create_minikube_mount_option()?),
         Some(build_fetch_minikube_mounts_option()?),
         Some(build_delete_minikube_mount_option()?),
         Some(build_delete_all_minikube_mounts_option()?),
+        Some((String::from("# Logs"), do_nothing(), false)),
+        Some(build_fetch_logs_option()?),
+        Some(build_show_log_option()?),
+        Some((String::from("# Export"), do_nothing(), false)),
+        Some(build_export_init_file_option()?),
         Some((String::from("# Clean up and exit"), do_nothing(), false)),
         Some(build_clean_up_and_exit_option()?),
         Some((String::from("Exit without cleaning up"), do_nothing(), true)),
@@ -114,17 +148,54 @@ pub fn build_options(
         } else {
             None
         },
+        if let Some(init_file_path) = init_file_path {
+            Some((
+                format!("# Initialization file ({init_file_path})"),
+                do_nothing(),
+                false,
+            ))
+        } else {
+            None
+        },
+        if let Some(init_file_path) = init_file_path {
+            Some(build_apply_init_file_option(init_file_path)?)
+        } else {
+            None
+        },
     ]
     .into_iter()
     .flatten()
     .collect())
 }
 
-pub fn get_sys_info() -> System {
-    System::new_with_specifics(RefreshKind::new().with_processes(ProcessRefreshKind::everything()))
+pub fn print_results(
+    session: &Session,
+    result: CommandExecutionResult,
+    stdout: bool,
+    stderr: bool,
+) {
+    print_results_as(session.output_format(), result, stdout, stderr);
 }
 
-pub fn print_results(result: CommandExecutionResult, stdout: bool, stderr: bool) {
+fn print_results_as(
+    output_format: OutputFormat,
+    result: CommandExecutionResult,
+    stdout: bool,
+    stderr: bool,
+) {
+    // The document of a result that is only meant for stderr, eg, that of a child process which
+    // has exited in the background, is not mixed with the documents printed to stdout.
+    if output_format == OutputFormat::Json {
+        if stdout {
+            println!("{}", render_json(result));
+        } else if stderr {
+            eprintln!("{}", render_json(result));
+        }
+        flush_output();
+
+        return;
+    }
+
     match result {
         Ok(ChildProcess(_)) => {
             let (_, result_stdout, result_stderr) = process_exited_with_success(result);
@@ -158,6 +229,16 @@ pub fn print_results(result: CommandExecutionResult, stdout: bool, stderr: bool)
                 println!("{indentation}{index}{x}");
             });
         }
+        Ok(Resources(title, resources)) => {
+            let descriptions = resources.into_iter().map(|x| x.description).collect();
+
+            print_results_as(
+                output_format,
+                Ok(PrintableResults(title, descriptions)),
+                stdout,
+                stderr,
+            );
+        }
         Err(error) => {
             if stderr {
                 eprintln!("{error}");
@@ -168,100 +249,136 @@ pub fn print_results(result: CommandExecutionResult, stdout: bool, stderr: bool)
     flush_output();
 }
 
-fn start_and_wait_process(
+/// Runs a command to completion and returns its stdout.
+/// A non-zero exit status is reported as an error carrying the command's stderr.
+fn start_and_read_process(
+    session: &Session,
     command: &str,
     args: &[&str],
     custom_error: Option<String>,
-) -> CommandExecutionResult {
-    let mut child = Command::new(command)
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|x| {
-            if let Some(y) = custom_error {
-                y
-            } else {
-                x.to_string()
-            }
-        })?;
+) -> Result<String, Error> {
+    let output = session
+        .runner()
+        .run(command, args)
+        .map_err(|x| Error::from_spawn(command, x, custom_error))?;
+
+    if !output.success() {
+        return Err(Error::from_exit(command, output.code, &output.stderr));
+    }
 
-    match child.wait().map_err(|x| x.to_string()) {
-        Ok(exit_status) => Ok(ChildProcess(Some((
-            Arc::new(Mutex::new(child)),
-            exit_status,
-        )))),
-        Err(error) => Err(error),
+    Ok(output.stdout)
+}
+
+/// Runs `kubectl` with the session's kubeconfig and kubectl context, rather than with whatever
+/// context happens to be the current one.
+fn run_kubectl(
+    session: &Session,
+    args: &[&str],
+    custom_error: Option<String>,
+) -> Result<String, Error> {
+    let kubectl_args = kubectl_args(session, args);
+
+    start_and_read_process(
+        session,
+        "kubectl",
+        &kubectl_args
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>(),
+        custom_error,
+    )
+}
+
+/// Prepends the session's kubeconfig and kubectl context, if any, to the arguments of a kubectl
+/// command.
+fn kubectl_args(session: &Session, args: &[&str]) -> Vec<String> {
+    let mut kubectl_args: Vec<String> = Vec::new();
+
+    if let Some(kubeconfig) = session.kubeconfig() {
+        kubectl_args.extend([String::from("--kubeconfig"), kubeconfig]);
+    }
+
+    if let Some(kube_context) = session.kube_context() {
+        kubectl_args.extend([String::from("--context"), kube_context]);
     }
+
+    kubectl_args.extend(args.iter().map(|x| x.to_string()));
+
+    kubectl_args
 }
 
-fn kill_process(name: &str, patterns: Vec<&str>) -> CommandExecutionResult {
-    let sys_info = get_sys_info();
-
-    let killed_processes: Vec<(&sysinfo::Process, bool)> = sys_info
-        .processes_by_name(name)
-        .filter(|x| {
-            let cmd = x.cmd().join(" ");
-
-            patterns
-                .iter()
-                .all(|pattern| cmd.contains(&String::from(*pattern)))
-        })
-        .map(|x| (x, x.kill_with(sysinfo::Signal::Interrupt).unwrap()))
-        .collect();
-
-    killed_processes
-        .iter()
-        .for_each(|(process, _)| process.wait());
-
-    if killed_processes
-        .iter()
-        .any(|(_, successfully_killed)| !*successfully_killed)
-    {
-        Err(format!("Failed to kill {name} with patterns {patterns:?}"))
-    } else {
-        Ok(PrintableResults(None, Vec::new()))
+/// Starts a child process which keeps running until it is killed (eg, a socat tunnel, a minikube
+/// mount, the minikube tunnel) and returns its process id.
+/// The child process' output is written to the log file at `log_path`.
+/// The child process is waited for in the background and its stderr is printed if it exits.
+/// When child processes are detached, their standard streams are not piped, so that they are not
+/// killed by a broken pipe when `kube-minion` exits.
+fn spawn_long_running_process(
+    session: &Session,
+    command: &str,
+    args: &[&str],
+    log_path: PathBuf,
+    custom_error: Option<String>,
+) -> Result<u32, Error> {
+    let output_format = session.output_format();
+
+    let mut log = RotatingLog::open(&log_path)?;
+    if !session.dry_run() {
+        log.write_event(&format!("starting {command} {}", args.join(" ")))?;
     }
+
+    session
+        .runner()
+        .spawn(
+            command,
+            args,
+            session.detach_child_processes(),
+            log,
+            Box::new(move |result| {
+                // A child process that has exited without an error message has nothing to report.
+                let result = match result.map_err(Error::from) {
+                    Ok(output) if output.stderr.is_empty() => return,
+                    Ok(output) => Ok(ChildProcess(output)),
+                    Err(error) => Err(error),
+                };
+
+                print_results_as(output_format, result, false, true);
+            }),
+        )
+        .map_err(|x| Error::from_spawn(command, x, custom_error))
+}
+
+/// Prepends `-p PROFILE` to the arguments of a minikube command, unless the session operates on
+/// minikube's active profile.
+fn minikube_args(session: &Session, args: &[&str]) -> Vec<String> {
+    let mut minikube_args = match session.minikube_profile() {
+        Some(minikube_profile) => vec![String::from("-p"), minikube_profile],
+        None => Vec::new(),
+    };
+
+    minikube_args.extend(args.iter().map(|x| x.to_string()));
+
+    minikube_args
+}
+
+/// Whether a registered minikube process has been started for the session's minikube profile.
+fn is_minikube_profile_process(session: &Session, entry: &registry::RegistryEntry) -> bool {
+    entry.spec_str("profile") == session.minikube_profile().unwrap_or_default()
 }
 
 fn process_exited_with_success(
     child_process_result: CommandExecutionResult,
 ) -> (bool, Option<String>, Option<String>) {
+    let non_empty = |x: String| if x.is_empty() { None } else { Some(x) };
+
     match child_process_result {
-        Ok(ChildProcess(Some((child, exit_status)))) => {
-            let mut child = child.lock().unwrap();
-
-            let mut stdout = String::new();
-            child
-                .stdout
-                .take()
-                .unwrap()
-                .read_to_string(&mut stdout)
-                .unwrap();
-            let stdout = if stdout.is_empty() {
-                None
-            } else {
-                Some(stdout)
-            };
-
-            let mut stderr = String::new();
-            child
-                .stderr
-                .take()
-                .unwrap()
-                .read_to_string(&mut stderr)
-                .unwrap();
-            let stderr = if stderr.is_empty() {
-                None
-            } else {
-                Some(stderr)
-            };
-
-            (exit_status.success(), stdout, stderr)
-        }
+        Ok(ChildProcess(output)) => (
+            output.success(),
+            non_empty(output.stdout),
+            non_empty(output.stderr),
+        ),
         Ok(_) => (true, None, None),
-        Err(error) => (false, None, Some(error)),
+        Err(error) => (false, None, Some(error.to_string())),
     }
 }
 
@@ -269,7 +386,7 @@ fn parse_string(
     prompt: &str,
     default_value: Option<String>,
     error_when_empty: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, Error> {
     let mut input = String::new();
     let mut stdin = stdin().lock();
     let mut stdout = stdout().lock();
@@ -284,14 +401,14 @@ fn parse_string(
 
     if input.is_empty() {
         if let Some(error_when_empty) = error_when_empty {
-            return Err(error_when_empty);
+            return Err(Error::Input(error_when_empty));
         }
 
         if let Some(default_value) = default_value {
             return Ok(default_value);
         }
 
-        Err(String::from("An empty value is not allowed"))
+        Err(Error::Input(String::from("An empty value is not allowed")))
     } else {
         Ok(String::from(input))
     }
@@ -301,7 +418,7 @@ fn parse_num<T: FromStr>(
     prompt: &str,
     default_value: Option<T>,
     error_when_empty: Option<String>,
-) -> Result<T, String> {
+) -> Result<T, Error> {
     let mut input = String::new();
     let mut stdin = stdin().lock();
     let mut stdout = stdout().lock();
@@ -316,58 +433,35 @@ fn parse_num<T: FromStr>(
 
     if input.is_empty() {
         if let Some(error_when_empty) = error_when_empty {
-            return Err(error_when_empty);
+            return Err(Error::Input(error_when_empty));
         }
 
         if let Some(default_value) = default_value {
             return Ok(default_value);
         }
 
-        Err(String::from("An empty value is not allowed"))
+        Err(Error::Input(String::from("An empty value is not allowed")))
     } else {
         input
             .parse::<T>()
-            .map_err(|_| format!("Failed to parse {input} as a number"))
+            .map_err(|_| Error::Input(format!("Failed to parse {input} as a number")))
     }
 }
 
 /// It takes a closure that is expected to execute a command and return its result.
 /// If the result is Ok, it appends the command's results to the `results` vector argument.
-fn merge_if_ok<T>(results: &mut Vec<String>, f: T) -> Result<(), String>
+fn merge_if_ok<T>(results: &mut Vec<String>, f: T) -> Result<(), Error>
 where
     T: FnOnce() -> CommandExecutionResult,
 {
     match f() {
-        Ok(ChildProcess(Some((child, _)))) => {
-            let mut child = child.lock().unwrap();
-            let mut output = String::new();
-
-            child
-                .stdout
-                .take()
-                .unwrap()
-                .read_to_string(&mut output)
-                .unwrap();
-
-            let output = output.trim();
+        Ok(ChildProcess(output)) => {
+            for output in [output.stdout, output.stderr] {
+                let output = output.trim();
 
-            if !output.is_empty() {
-                results.push(output.to_string());
-            }
-
-            let mut output = String::new();
-
-            child
-                .stderr
-                .take()
-                .unwrap()
-                .read_to_string(&mut output)
-                .unwrap();
-
-            let output = output.trim();
-
-            if !output.is_empty() {
-                results.push(output.to_string());
+                if !output.is_empty() {
+                    results.push(output.to_string());
+                }
             }
 
             Ok(())
@@ -376,8 +470,11 @@ where
             results.append(&mut new_results);
             Ok(())
         }
+        Ok(Resources(_, new_results)) => {
+            results.extend(new_results.into_iter().map(|x| x.description));
+            Ok(())
+        }
         Err(error) => Err(error),
-        _ => Ok(()),
     }
 }
 
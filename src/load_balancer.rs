@@ -1,13 +1,33 @@
-use std::io::Read;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crate::health::{combine, probe, Health, HealthCheck};
+use crate::kube_api::RESOURCE_TYPE_LABEL;
+use crate::minikube_tunnel::check_minikube_tunnel;
+use crate::output::print_message;
+use crate::process_log::parse_timestamp;
+use crate::protocol::Protocol;
 use crate::{
-    merge_if_ok, parse_num, parse_string, start_and_wait_process, CommandExecutionResult,
-    CommandResultType::*, OptionFunc,
+    merge_if_ok, parse_num, parse_string, run_kubectl, CommandExecutionResult,
+    CommandResultType::*, Error, KubeApi, OptionFunc, Resource, Session,
 };
 
-use regex::Regex;
+use serde_json::{json, Value};
 
-pub fn build_create_load_balancer_option() -> Result<(String, OptionFunc, bool), String> {
+/// How long to wait, after creating a load balancer, for the minikube tunnel to assign it an
+/// external IP.
+const EXTERNAL_IP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const EXTERNAL_IP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The annotation of a load balancer whose service has a name of its own, which records the name of
+/// the exposed resource, since it cannot be recovered from the name of the service.
+pub const RESOURCE_NAME_ANNOTATION: &str = "kube-minion/resource-name";
+
+pub fn build_create_load_balancer_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Create load balancer"),
         Box::new(create_load_balancer_guided),
@@ -15,7 +35,7 @@ pub fn build_create_load_balancer_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_fetch_load_balancers_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_fetch_load_balancers_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("List load balancers"),
         Box::new(fetch_load_balancers),
@@ -23,7 +43,7 @@ pub fn build_fetch_load_balancers_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_delete_load_balancer_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_load_balancer_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete load balancer"),
         Box::new(delete_load_balancer_guided),
@@ -31,7 +51,7 @@ pub fn build_delete_load_balancer_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_delete_all_load_balancers_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_all_load_balancers_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete all load balancers"),
         Box::new(delete_all_load_balancers),
@@ -39,94 +59,594 @@ pub fn build_delete_all_load_balancers_option() -> Result<(String, OptionFunc, b
     ))
 }
 
+/// A port of a load balancer and the port of the exposed resource to which it forwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortMapping {
+    /// The name of the port, which Kubernetes requires when a service has more than one port, so
+    /// the ports of a load balancer with more than one port are named after their number, unless
+    /// they have a name.
+    pub name: Option<String>,
+    pub port: u16,
+    pub target_port: TargetPort,
+    pub protocol: Protocol,
+}
+
+impl PortMapping {
+    pub fn new(port: u16, target_port: u16) -> Self {
+        PortMapping {
+            name: None,
+            port,
+            target_port: TargetPort::Number(target_port),
+            protocol: Protocol::Tcp,
+        }
+    }
+
+    /// The name of an unnamed port of a load balancer with more than one port, eg, port-8080 or,
+    /// since a port can be both TCP and UDP, port-53-udp.
+    pub fn default_name(&self) -> String {
+        match self.protocol {
+            Protocol::Tcp => format!("port-{}", self.port),
+            protocol => format!("port-{}-{}", self.port, protocol.name()),
+        }
+    }
+}
+
+impl FromStr for PortMapping {
+    type Err = String;
+
+    /// Parses `[NAME=]PORT[:TARGET_PORT][/PROTOCOL]`, the target port being the port and the
+    /// protocol being TCP unless they are given.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, ports) = match s.trim().split_once('=') {
+            Some((name, ports)) => {
+                validate_port_name(name)?;
+                (Some(String::from(name)), ports)
+            }
+            None => (None, s.trim()),
+        };
+        let (ports, protocol) = match ports.split_once('/') {
+            Some((ports, protocol)) => (ports, protocol.parse::<Protocol>()?),
+            None => (ports, Protocol::Tcp),
+        };
+        let (port, target_port) = ports.split_once(':').unwrap_or((ports, ports));
+
+        match (port.parse::<u16>(), target_port.parse::<TargetPort>()) {
+            (Ok(port), Ok(target_port)) if port > 0 => Ok(PortMapping {
+                name,
+                port,
+                target_port,
+                protocol,
+            }),
+            _ => Err(format!(
+                "{s} is not a valid port mapping (expected [NAME=]PORT[:TARGET_PORT][/PROTOCOL])"
+            )),
+        }
+    }
+}
+
+/// The port of the exposed resource to which a port of a load balancer forwards, either by number
+/// or by the name of a container port, eg, http, which Kubernetes looks up in each of the pods, so
+/// that the load balancer keeps routing when the number of the port changes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetPort {
+    Number(u16),
+    Name(String),
+}
+
+impl TargetPort {
+    /// The target port, as the Kubernetes API describes it, ie, an integer or a string.
+    pub fn to_json(&self) -> Value {
+        match self {
+            TargetPort::Number(number) => json!(number),
+            TargetPort::Name(name) => json!(name),
+        }
+    }
+}
+
+impl fmt::Display for TargetPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetPort::Number(number) => write!(f, "{number}"),
+            TargetPort::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl FromStr for TargetPort {
+    type Err = String;
+
+    /// Parses a port number or, unless it is made of digits only, a port name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.chars().all(|x| x.is_ascii_digit()) {
+            return match s.parse::<u16>() {
+                Ok(number) if number > 0 => Ok(TargetPort::Number(number)),
+                _ => Err(format!(
+                    "{s} is not a valid target port (expected a port between 1 and {} or a \
+                    port name)",
+                    u16::MAX
+                )),
+            };
+        }
+
+        validate_port_name(s)?;
+
+        Ok(TargetPort::Name(String::from(s)))
+    }
+}
+
+/// How a load balancer's service is named, labeled and annotated, apart from the name that is made
+/// of the name and ports of the exposed resource and the labels that `kube-minion` selects its load
+/// balancers with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServiceMetadata {
+    /// The name of the service, instead of the name made of the name and ports of the exposed
+    /// resource, which can be longer than Kubernetes allows.
+    pub name: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// The annotations of the service, eg, `metallb.universe.tf/loadBalancerIPs`.
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl ServiceMetadata {
+    /// Checks the name, the labels and the annotations, as Kubernetes would, and that they do not
+    /// override those of `kube-minion`.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.name {
+            validate_service_name(name)?;
+        }
+
+        for (key, value) in &self.labels {
+            validate_metadata_key(key, "label")?;
+
+            if !value.is_empty() && !is_qualified_name(value) {
+                return Err(format!(
+                    "The value of label {key} must consist of at most 63 alphanumeric characters, \
+                    '-', '_' or '.', and start and end with an alphanumeric character"
+                ));
+            }
+        }
+
+        for key in self.annotations.keys() {
+            validate_metadata_key(key, "annotation")?;
+        }
+
+        Ok(())
+    }
+
+    /// Describes the labels and the annotations, eg, for the plan of `apply`.
+    pub fn describe(&self) -> String {
+        let describe = |what: &str, map: &BTreeMap<String, String>| {
+            let pairs: Vec<String> = map.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            format!("{what} {}", pairs.join(","))
+        };
+
+        let mut parts = Vec::new();
+        if !self.labels.is_empty() {
+            parts.push(describe("labels", &self.labels));
+        }
+        if !self.annotations.is_empty() {
+            parts.push(describe("annotations", &self.annotations));
+        }
+
+        parts.join(", ")
+    }
+
+    /// Reads the labels and the annotations of a load balancer's service, as the Kubernetes API
+    /// describes it, leaving out those of `kube-minion`.
+    pub fn from_service(service: &Value) -> Self {
+        let read = |map: &Value, skip: &dyn Fn(&str) -> bool| {
+            map.as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(k, _)| !skip(k))
+                .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+                .collect()
+        };
+
+        ServiceMetadata {
+            name: service["metadata"]["name"].as_str().map(String::from),
+            labels: read(&service["metadata"]["labels"], &|x| {
+                is_reserved_key(x) || x == "reason"
+            }),
+            annotations: read(&service["metadata"]["annotations"], &is_reserved_key),
+        }
+    }
+}
+
+/// Parses `KEY=VALUE`, eg, a label or an annotation.
+pub fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("{s} is not a valid KEY=VALUE pair")),
+    }
+}
+
+/// Checks that a name is a DNS-1035 label, which Kubernetes requires the name of a service to be.
+pub fn validate_service_name(name: &str) -> Result<(), String> {
+    let valid = name.len() <= 63
+        && name.starts_with(|x: char| x.is_ascii_lowercase())
+        && name.ends_with(|x: char| x.is_ascii_lowercase() || x.is_ascii_digit())
+        && name
+            .chars()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} is not a valid service name, which must consist of at most 63 lower case \
+            alphanumeric characters or '-', start with a letter and end with an alphanumeric \
+            character"
+        ))
+    }
+}
+
+/// Checks that a port name is one that Kubernetes accepts for a container port, ie, up to 15 lower
+/// case letters, digits and dashes, with at least one letter and no leading, trailing or adjacent
+/// dashes.
+pub fn validate_port_name(name: &str) -> Result<(), String> {
+    let valid = (1..=15).contains(&name.len())
+        && name
+            .chars()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == '-')
+        && name.chars().any(|x| x.is_ascii_lowercase())
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && !name.contains("--");
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} is not a valid port name (expected up to 15 lower case letters, digits and \
+            dashes, with at least one letter)"
+        ))
+    }
+}
+
+/// Whether a label or an annotation belongs to `kube-minion`.
+fn is_reserved_key(key: &str) -> bool {
+    key.starts_with("kube-minion/")
+}
+
+/// Checks that a key is a valid label or annotation key, ie, a name, optionally prefixed by a DNS
+/// subdomain and a '/', eg, `metallb.universe.tf/address-pool`.
+fn validate_metadata_key(key: &str, what: &str) -> Result<(), String> {
+    if is_reserved_key(key) || (what == "label" && key == "reason") {
+        return Err(format!("The {what} {key} is reserved by kube-minion"));
+    }
+
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+
+    let valid_prefix = prefix.is_none_or(|x| {
+        x.len() <= 253
+            && x.split('.').all(|x| {
+                !x.is_empty()
+                    && !x.starts_with('-')
+                    && !x.ends_with('-')
+                    && x.chars()
+                        .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == '-')
+            })
+    });
+
+    if valid_prefix && is_qualified_name(name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{key} is not a valid {what} key, which must be a name of at most 63 alphanumeric \
+            characters, '-', '_' or '.', that starts and ends with an alphanumeric character, \
+            optionally prefixed by a DNS subdomain and a '/'"
+        ))
+    }
+}
+
+/// Whether a string is a name of a label or an annotation key, or a label value.
+fn is_qualified_name(s: &str) -> bool {
+    (1..=63).contains(&s.len())
+        && s.starts_with(|x: char| x.is_ascii_alphanumeric())
+        && s.ends_with(|x: char| x.is_ascii_alphanumeric())
+        && s.chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '-' | '_' | '.'))
+}
+
+/// The name of the service of a load balancer, which is made of the name of the exposed resource
+/// and of the ports of the load balancer, eg, web-8080-80-lb or, with a named target port,
+/// web-8080-http-lb, with the protocol of the ports that are not TCP, eg, dns-53-53-udp-lb.
+pub fn load_balancer_service_name(name: &str, ports: &[PortMapping]) -> String {
+    let ports: Vec<String> = ports
+        .iter()
+        .map(|x| match x.protocol {
+            Protocol::Tcp => format!("{}-{}", x.port, x.target_port),
+            protocol => format!("{}-{}-{}", x.port, x.target_port, protocol.name()),
+        })
+        .collect();
+
+    format!("{name}-{}-lb", ports.join("-"))
+}
+
+/// Recovers the port mappings of a load balancer from its service, as the Kubernetes API describes
+/// it, leaving out the names that `kube-minion` gives to the ports of a load balancer with several
+/// of them, which it gives again when it is created.
+pub fn service_port_mappings(service: &Value) -> Vec<PortMapping> {
+    service["spec"]["ports"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|port| {
+            let target_port = match &port["targetPort"] {
+                Value::String(target_port) => target_port.parse().ok()?,
+                target_port => TargetPort::Number(u16::try_from(target_port.as_u64()?).ok()?),
+            };
+
+            let mut port_mapping = PortMapping {
+                name: None,
+                port: u16::try_from(port["port"].as_u64()?).ok()?,
+                target_port,
+                protocol: port["protocol"].as_str().unwrap_or("TCP").parse().ok()?,
+            };
+
+            port_mapping.name = port["name"]
+                .as_str()
+                .filter(|x| *x != port_mapping.default_name())
+                .map(String::from);
+
+            Some(port_mapping)
+        })
+        .collect()
+}
+
+/// Recovers the name of the resource that a load balancer exposes from the annotation of a service
+/// with a name of its own, or from the name of its service, unless the service has not been named
+/// after its ports by `kube-minion`.
+pub fn exposed_resource_name<'a>(service: &'a Value, ports: &[PortMapping]) -> Option<&'a str> {
+    if let Some(name) = service["metadata"]["annotations"][RESOURCE_NAME_ANNOTATION].as_str() {
+        return Some(name);
+    }
+
+    service["metadata"]["name"]
+        .as_str()?
+        .strip_suffix(&load_balancer_service_name("", ports))
+        .filter(|_| !ports.is_empty())
+}
+
+/// The ports of the service of a load balancer, as the Kubernetes API describes them.
+fn service_ports(ports: &[PortMapping]) -> Vec<Value> {
+    ports
+        .iter()
+        .map(|x| {
+            let mut port = json!({
+                "port": x.port,
+                "targetPort": x.target_port.to_json(),
+                "protocol": x.protocol.kubernetes_name(),
+            });
+
+            match &x.name {
+                Some(name) => port["name"] = name.as_str().into(),
+                None if ports.len() > 1 => port["name"] = x.default_name().into(),
+                None => (),
+            }
+
+            port
+        })
+        .collect()
+}
+
+/// Creates a single service of type LoadBalancer which forwards each of the ports to its target
+/// port of the exposed resource.
+/// Since `kubectl expose` takes a single port and no annotations, a service with more than one
+/// port, with a named port or with annotations is exposed with its first port and then patched,
+/// and it is deleted again if it cannot be patched.
+/// The name of the service, which is made of the name and the ports of the exposed resource unless
+/// it is given, is checked before anything is created, since Kubernetes limits it to 63 characters.
 pub fn create_load_balancer(
+    session: &Session,
     namespace: &str,
     resource_type: &str,
     name: &str,
-    port: u16,
-    target_port: u16,
+    ports: &[PortMapping],
+    metadata: &ServiceMetadata,
 ) -> CommandExecutionResult {
-    start_and_wait_process(
-        "kubectl",
-        &[
-            "-n",
+    let Some(first_port) = ports.first() else {
+        return Err(Error::Input(format!(
+            "At least one port is required to create a load balancer for {resource_type}/{name}"
+        )));
+    };
+
+    let derived_service_name = load_balancer_service_name(name, ports);
+    let service_name = match &metadata.name {
+        Some(service_name) => service_name.clone(),
+        None => {
+            validate_service_name(&derived_service_name).map_err(|x| {
+                Error::Input(format!("{x}, so the load balancer requires a service name"))
+            })?;
+            derived_service_name.clone()
+        }
+    };
+
+    metadata.validate().map_err(Error::Input)?;
+
+    let mut metadata = metadata.clone();
+    if service_name != derived_service_name {
+        metadata
+            .annotations
+            .insert(String::from(RESOURCE_NAME_ANNOTATION), String::from(name));
+    }
+
+    let stdout = match session.kube_api() {
+        Some(kube_api) if KubeApi::can_expose(resource_type) => kube_api.expose(
             namespace,
-            "expose",
             resource_type,
             name,
-            "--type",
-            "LoadBalancer",
-            "--name",
-            &(String::from(name) + &format!("-{port}-{target_port}-lb")),
-            "--port",
-            &port.to_string(),
-            "--target-port",
-            &target_port.to_string(),
-            "-l",
-            "reason=kube-minion",
-        ],
-        None,
-    )
-}
+            &service_name,
+            service_ports(ports),
+            &metadata,
+        )?,
+        _ => {
+            let port = first_port.port.to_string();
+            let target_port = first_port.target_port.to_string();
+            let mut labels = vec![
+                String::from("reason=kube-minion"),
+                format!("{RESOURCE_TYPE_LABEL}={resource_type}"),
+            ];
+            labels.extend(metadata.labels.iter().map(|(k, v)| format!("{k}={v}")));
+            let labels = labels.join(",");
+            let mut args = vec![
+                "-n",
+                namespace,
+                "expose",
+                resource_type,
+                name,
+                "--type",
+                "LoadBalancer",
+                "--name",
+                &service_name,
+                "--port",
+                &port,
+                "--target-port",
+                &target_port,
+                "-l",
+                &labels,
+            ];
 
-pub fn delete_load_balancer(namespace: &str, name: &str) -> CommandExecutionResult {
-    let mut results: Vec<String> = Vec::new();
+            if first_port.protocol != Protocol::Tcp {
+                args.extend(["--protocol", first_port.protocol.kubernetes_name()]);
+            }
 
-    merge_if_ok(&mut results, || {
-        start_and_wait_process("kubectl", &["-n", namespace, "delete", "svc", name], None)
-    })?;
+            let stdout = run_kubectl(
+                session,
+                &args,
+                Some(format!(
+                    "Failed to create load balancer for {resource_type}/{name}"
+                )),
+            )?;
 
-    Ok(PrintableResults(None, results))
+            let mut patch: Vec<Value> = Vec::new();
+
+            if ports.len() > 1 || first_port.name.is_some() {
+                patch.push(json!({
+                    "op": "replace",
+                    "path": "/spec/ports",
+                    "value": service_ports(ports),
+                }));
+            }
+
+            if !metadata.annotations.is_empty() {
+                patch.push(json!({
+                    "op": "add",
+                    "path": "/metadata/annotations",
+                    "value": metadata.annotations,
+                }));
+            }
+
+            if !patch.is_empty() {
+                let result = run_kubectl(
+                    session,
+                    &[
+                        "-n",
+                        namespace,
+                        "patch",
+                        "svc",
+                        &service_name,
+                        "--type",
+                        "json",
+                        "-p",
+                        &Value::from(patch).to_string(),
+                    ],
+                    Some(format!(
+                        "Failed to set the ports and annotations of load balancer \
+                        {namespace}/{service_name}"
+                    )),
+                );
+
+                // The exposed service is not left behind with only its first port and without its
+                // annotations.
+                if let Err(error) = result {
+                    let _ = run_kubectl(
+                        session,
+                        &["-n", namespace, "delete", "svc", &service_name],
+                        None,
+                    );
+
+                    return Err(error);
+                }
+            }
+
+            stdout
+        }
+    };
+
+    let load_balancer = Resource::new("loadBalancer", stdout.trim().to_string())
+        .field("namespace", namespace)
+        .field("name", service_name.as_str())
+        .field("resourceType", resource_type)
+        .field("resourceName", name)
+        .field("ports", service_ports(ports))
+        .field("labels", json!(metadata.labels))
+        .field("annotations", json!(metadata.annotations))
+        .field("state", "created");
+
+    Ok(Resources(
+        None,
+        vec![wait_for_external_ip(
+            session,
+            namespace,
+            &service_name,
+            ports,
+            load_balancer,
+        )],
+    ))
 }
 
-pub fn delete_all_load_balancers() -> CommandExecutionResult {
-    let load_balancers = match fetch_load_balancers()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
+pub fn delete_load_balancer(
+    session: &Session,
+    namespace: &str,
+    name: &str,
+) -> CommandExecutionResult {
+    let stdout = match session.kube_api() {
+        Some(kube_api) => kube_api.delete_service(namespace, name)?,
+        None => run_kubectl(
+            session,
+            &["-n", namespace, "delete", "svc", name],
+            Some(format!("Failed to delete load balancer {namespace}/{name}")),
+        )?,
     };
 
+    Ok(Resources(
+        None,
+        vec![Resource::new("loadBalancer", stdout.trim().to_string())
+            .field("namespace", namespace)
+            .field("name", name)
+            .field("state", "deleted")],
+    ))
+}
+
+pub fn delete_all_load_balancers(session: &Session) -> CommandExecutionResult {
+    let load_balancers = list_load_balancers(session)?;
+
     let mut results: Vec<String> = Vec::new();
 
     for load_balancer in &load_balancers {
-        let (namespace, name) = parse_load_balancer(load_balancer);
+        let (namespace, name) = load_balancer_id(load_balancer);
 
-        merge_if_ok(&mut results, || delete_load_balancer(&namespace, &name))?;
+        merge_if_ok(&mut results, || {
+            delete_load_balancer(session, &namespace, &name)
+        })?;
     }
 
     Ok(PrintableResults(None, results))
 }
 
-fn fetch_load_balancers() -> CommandExecutionResult {
-    let (child, _) = start_and_wait_process(
-        "kubectl",
-        &[
-            "get",
-            "svc",
-            "-A",
-            "-l",
-            "reason=kube-minion",
-            "--no-headers",
-        ],
-        Some(String::from("Failed to fetch load balancers")),
-    )?
-    .child_process()
-    .take()
-    .unwrap();
-
-    let mut child = child.lock().unwrap();
-
-    let mut stdout = String::new();
-    child
-        .stdout
-        .take()
-        .unwrap()
-        .read_to_string(&mut stdout)
-        .unwrap();
-
-    let load_balancers = stdout
-        .lines()
-        .filter(|x| !x.contains("kubernetes-dashboard-lb"))
-        .map(String::from)
+pub fn fetch_load_balancers(session: &Session) -> CommandExecutionResult {
+    let load_balancers = list_load_balancers(session)?
+        .into_iter()
+        .map(|x| probe_load_balancer(&x).annotate(x))
         .collect::<Vec<_>>();
 
     let title = if load_balancers.is_empty() {
@@ -135,10 +655,170 @@ fn fetch_load_balancers() -> CommandExecutionResult {
         Some(String::from("Load balancers:"))
     };
 
-    Ok(PrintableResults(title, load_balancers))
+    Ok(Resources(title, load_balancers))
+}
+
+/// Returns the load balancers created by `kube-minion`, apart from the dashboard's, without
+/// probing them.
+pub fn list_load_balancers(session: &Session) -> Result<Vec<Resource>, Error> {
+    let tunnel_running = check_minikube_tunnel(session)?;
+
+    Ok(list_load_balancer_services(session)?
+        .iter()
+        .map(|x| describe_load_balancer(x, tunnel_running))
+        .collect())
+}
+
+/// Returns the service of a load balancer, as the Kubernetes API describes it.
+fn fetch_load_balancer_service(
+    session: &Session,
+    namespace: &str,
+    name: &str,
+) -> Result<Value, Error> {
+    match session.kube_api() {
+        Some(kube_api) => kube_api.get_service(namespace, name),
+        None => {
+            let stdout = run_kubectl(
+                session,
+                &["-n", namespace, "get", "svc", name, "-o", "json"],
+                Some(format!("Failed to fetch load balancer {namespace}/{name}")),
+            )?;
+
+            serde_json::from_str(&stdout).map_err(|x| {
+                Error::Parse(format!(
+                    "Failed to parse load balancer {namespace}/{name}: {x}"
+                ))
+            })
+        }
+    }
+}
+
+/// Waits, for a while, for the minikube tunnel to assign an external IP to a load balancer that has
+/// just been created and tells where it can be accessed, like the dashboard option does.
+fn wait_for_external_ip(
+    session: &Session,
+    namespace: &str,
+    name: &str,
+    ports: &[PortMapping],
+    mut load_balancer: Resource,
+) -> Resource {
+    if session.dry_run() {
+        return load_balancer;
+    }
+
+    let message = match check_minikube_tunnel(session) {
+        Ok(true) => {
+            let deadline = Instant::now() + EXTERNAL_IP_TIMEOUT;
+
+            loop {
+                let service = match fetch_load_balancer_service(session, namespace, name) {
+                    Ok(service) => service,
+                    Err(error) => break format!("Could not wait for an external IP: {error}"),
+                };
+                let external_ips = external_ips(&service);
+
+                if let Some(external_ip) = external_ips.first() {
+                    let urls = load_balancer_urls(ports, external_ip);
+                    let message = format!(
+                        "Load balancer {namespace}/{name} can be accessed at {}",
+                        load_balancer_address(&urls, &external_ips)
+                    );
+
+                    load_balancer = load_balancer
+                        .field("externalIp", external_ips.join(","))
+                        .field("urls", urls);
+
+                    break message;
+                }
+
+                if Instant::now() >= deadline {
+                    break format!(
+                        "Load balancer {namespace}/{name} has not been assigned an external IP \
+                        within {}s",
+                        EXTERNAL_IP_TIMEOUT.as_secs()
+                    );
+                }
+
+                thread::sleep(EXTERNAL_IP_POLL_INTERVAL);
+            }
+        }
+        _ => format!(
+            "Load balancer {namespace}/{name} will be assigned an external IP once the minikube \
+            tunnel is started"
+        ),
+    };
+
+    load_balancer.description = format!("{}\n{message}", load_balancer.description);
+
+    load_balancer
+}
+
+/// Returns the services of the load balancers created by `kube-minion`, apart from the dashboard's,
+/// as the Kubernetes API describes them.
+pub fn list_load_balancer_services(session: &Session) -> Result<Vec<Value>, Error> {
+    let services = match session.kube_api() {
+        Some(kube_api) => kube_api.list_services()?,
+        None => {
+            let stdout = run_kubectl(
+                session,
+                &["get", "svc", "-A", "-l", "reason=kube-minion", "-o", "json"],
+                Some(String::from("Failed to fetch load balancers")),
+            )?;
+
+            serde_json::from_str::<Value>(&stdout)
+                .map_err(|x| Error::Parse(format!("Failed to parse the load balancers: {x}")))?
+                ["items"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        }
+    };
+
+    Ok(services
+        .into_iter()
+        .filter(|x| x["metadata"]["name"] != "kubernetes-dashboard-lb")
+        .collect())
+}
+
+/// Probes every TCP port of a load balancer on its external IP.
+fn probe_load_balancer(load_balancer: &Resource) -> Health {
+    let external_ip = load_balancer
+        .fields
+        .get("externalIp")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+
+    if external_ip.is_empty() || external_ip.starts_with('<') {
+        return Health::Unknown(String::from("no external IP has been assigned yet"));
+    }
+
+    // A service can have more than one external IP, eg, 127.0.0.1,::1.
+    let external_ip = external_ip.split(',').next().unwrap_or_default();
+
+    let ports = load_balancer
+        .fields
+        .get("ports")
+        .and_then(serde_json::Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    combine(
+        ports
+            .iter()
+            .map(|x| {
+                let port = x.get("port").and_then(serde_json::Value::as_u64);
+                let protocol = x.get("protocol").and_then(serde_json::Value::as_str);
+
+                match (port, protocol) {
+                    (Some(port), Some("TCP")) => probe(external_ip, port as u16, &HealthCheck::Tcp),
+                    _ => Health::Unknown(String::from("only TCP ports can be probed")),
+                }
+            })
+            .collect(),
+    )
 }
 
-fn create_load_balancer_guided() -> CommandExecutionResult {
+fn create_load_balancer_guided(session: &Session) -> CommandExecutionResult {
     let namespace = parse_string(
         "Namespace (leave empty for default namespace): ",
         Some(String::from("default")),
@@ -157,24 +837,307 @@ fn create_load_balancer_guided() -> CommandExecutionResult {
         Some(format!("The name of the {resource_type} is required")),
     )?;
 
-    let port: u16 = parse_num(
-        "Port: ",
-        None,
-        Some(format!(
-            "A port is required to create a load balancer for {resource_type}/{name}"
-        )),
-    )?;
+    let declared_ports = match fetch_exposed_resource(session, &namespace, &resource_type, &name) {
+        Ok(resource) => declared_ports(&resource),
+        Err(error) => {
+            print_message(
+                session,
+                &format!("Could not find the ports of {resource_type}/{name}: {error}"),
+            );
+            Vec::new()
+        }
+    };
+
+    if !declared_ports.is_empty() {
+        print_message(session, &format!("Ports of {resource_type}/{name}:"));
+        for (index, declared_port) in declared_ports.iter().enumerate() {
+            print_message(
+                session,
+                &format!("\t{}. {}", index + 1, declared_port.description),
+            );
+        }
+    }
+
+    let mut ports: Vec<PortMapping> = Vec::new();
 
-    let target_port: u16 = parse_num(
-        "Target port (leave empty to use the same as --port): ",
-        Some(port),
+    loop {
+        // An empty answer, which stands for no more ports, is read as port 0.
+        let port: u16 = if ports.is_empty() {
+            parse_num(
+                "Port: ",
+                None,
+                Some(format!(
+                    "A port is required to create a load balancer for {resource_type}/{name}"
+                )),
+            )?
+        } else {
+            parse_num("Another port (leave empty when done): ", Some(0), None)?
+        };
+
+        if port == 0 {
+            break;
+        }
+
+        let default_target_port = default_declared_port(port, &declared_ports);
+        let prompt = match (declared_ports.len(), default_target_port) {
+            (0, _) => String::from(
+                "Target port (a number or a name / leave empty to use the same as the port): ",
+            ),
+            (count, Some(default)) => format!(
+                "Target port (1-{count} for one of the above, or a number or a name / leave \
+                empty for {}): ",
+                default.target_port
+            ),
+            (count, None) => format!(
+                "Target port (1-{count} for one of the above, or a number or a name / leave \
+                empty to use the same as the port): "
+            ),
+        };
+        let answer = parse_string(&prompt, Some(String::new()), None)?;
+        let (target_port, declared_port) =
+            choose_target_port(&answer, port, &declared_ports).map_err(Error::Input)?;
+
+        if !declared_ports.is_empty() && declared_port.is_none() {
+            print_message(
+                session,
+                &format!(
+                    "Warning: {resource_type}/{name} does not declare port {target_port}, so \
+                    the load balancer may not route anywhere"
+                ),
+            );
+        }
+
+        let default_protocol = declared_port.map(|x| x.protocol).unwrap_or_default();
+        let protocol = parse_string(
+            &format!(
+                "Protocol (either tcp, udp or sctp / leave empty for {}): ",
+                default_protocol.name()
+            ),
+            Some(String::from(default_protocol.name())),
+            None,
+        )?
+        .parse::<Protocol>()
+        .map_err(Error::Input)?;
+
+        // An invalid port name is asked for again, rather than being rejected by Kubernetes.
+        let port_name = loop {
+            let port_name = parse_string(
+                "Port name (leave empty for none): ",
+                Some(String::new()),
+                None,
+            )?;
+
+            if port_name.is_empty() {
+                break None;
+            }
+
+            match validate_port_name(&port_name) {
+                Ok(()) => break Some(port_name),
+                Err(error) => print_message(session, &error),
+            }
+        };
+
+        ports.push(PortMapping {
+            name: port_name,
+            port,
+            target_port,
+            protocol,
+        });
+    }
+
+    let derived_service_name = load_balancer_service_name(&name, &ports);
+    let service_name = parse_string(
+        &format!("Service name (leave empty for {derived_service_name}): "),
+        Some(derived_service_name.clone()),
         None,
     )?;
 
-    create_load_balancer(&namespace, &resource_type, &name, port, target_port)
+    let parse_key_values = |prompt: &str| -> Result<BTreeMap<String, String>, Error> {
+        parse_string(prompt, Some(String::new()), None)?
+            .split(',')
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| parse_key_value(x).map_err(Error::Input))
+            .collect()
+    };
+
+    let metadata = ServiceMetadata {
+        name: Some(service_name).filter(|x| *x != derived_service_name),
+        labels: parse_key_values("Labels (KEY=VALUE,... / leave empty for none): ")?,
+        annotations: parse_key_values("Annotations (KEY=VALUE,... / leave empty for none): ")?,
+    };
+
+    create_load_balancer(
+        session,
+        &namespace,
+        &resource_type,
+        &name,
+        &ports,
+        &metadata,
+    )
+}
+
+/// A port that the exposed resource of a load balancer declares, ie, a port of a service or a
+/// container port of a pod or of the pods of a workload, which the guided creation of a load
+/// balancer offers as a target port.
+#[derive(Debug, PartialEq)]
+struct DeclaredPort {
+    /// The target port, which is the name of a named container port, since a load balancer that
+    /// targets it by name keeps routing when its number changes.
+    target_port: TargetPort,
+    /// The number of the port, unless it is the named target port of a service.
+    number: Option<u16>,
+    protocol: Protocol,
+    description: String,
+}
+
+/// Returns a resource that a load balancer would expose, as the Kubernetes API describes it.
+fn fetch_exposed_resource(
+    session: &Session,
+    namespace: &str,
+    resource_type: &str,
+    name: &str,
+) -> Result<Value, Error> {
+    match session.kube_api() {
+        Some(kube_api) if KubeApi::can_expose(resource_type) => {
+            kube_api.get_resource(namespace, resource_type, name)
+        }
+        _ => {
+            let stdout = run_kubectl(
+                session,
+                &["-n", namespace, "get", resource_type, name, "-o", "json"],
+                Some(format!("Failed to fetch {resource_type}/{name}")),
+            )?;
+
+            serde_json::from_str(&stdout)
+                .map_err(|x| Error::Parse(format!("Failed to parse {resource_type}/{name}: {x}")))
+        }
+    }
+}
+
+/// The ports that a resource declares, which are the target ports of a service, since a load
+/// balancer for a service forwards to the same pods, or the container ports of a pod or of the pod
+/// template of a workload.
+fn declared_ports(resource: &Value) -> Vec<DeclaredPort> {
+    let protocol = |x: &Value| x["protocol"].as_str().unwrap_or("TCP").parse().ok();
+    let number = |x: &Value| x.as_u64().and_then(|x| u16::try_from(x).ok());
+
+    if let Some(ports) = resource["spec"]["ports"].as_array() {
+        return ports
+            .iter()
+            .filter_map(|x| {
+                let port = number(&x["port"])?;
+                let target_port = match &x["targetPort"] {
+                    Value::Null => TargetPort::Number(port),
+                    Value::String(target_port) => target_port.parse().ok()?,
+                    target_port => TargetPort::Number(number(target_port)?),
+                };
+                let protocol: Protocol = protocol(x)?;
+
+                Some(DeclaredPort {
+                    number: match target_port {
+                        TargetPort::Number(number) => Some(number),
+                        TargetPort::Name(_) => None,
+                    },
+                    description: format!(
+                        "{target_port}/{} (port {port} of the service)",
+                        protocol.kubernetes_name()
+                    ),
+                    target_port,
+                    protocol,
+                })
+            })
+            .collect();
+    }
+
+    let containers = resource["spec"]["containers"]
+        .as_array()
+        .or(resource["spec"]["template"]["spec"]["containers"].as_array());
+
+    containers
+        .into_iter()
+        .flatten()
+        .flat_map(|container| {
+            let container_name = container["name"].as_str().unwrap_or("<none>");
+
+            container["ports"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(move |x| {
+                    let container_port = number(&x["containerPort"])?;
+                    let protocol: Protocol = protocol(x)?;
+                    let (target_port, description) = match x["name"].as_str() {
+                        Some(port_name) => (
+                            TargetPort::Name(String::from(port_name)),
+                            format!(
+                                "{port_name} ({container_port}/{}, container {container_name})",
+                                protocol.kubernetes_name()
+                            ),
+                        ),
+                        None => (
+                            TargetPort::Number(container_port),
+                            format!(
+                                "{container_port}/{} (container {container_name})",
+                                protocol.kubernetes_name()
+                            ),
+                        ),
+                    };
+
+                    Some(DeclaredPort {
+                        target_port,
+                        number: Some(container_port),
+                        protocol,
+                        description,
+                    })
+                })
+        })
+        .collect()
 }
 
-fn delete_load_balancer_guided() -> CommandExecutionResult {
+/// The declared port that a port of a load balancer targets unless another one is chosen, which is
+/// the one with the same number or, failing that, the only one.
+fn default_declared_port(port: u16, declared_ports: &[DeclaredPort]) -> Option<&DeclaredPort> {
+    declared_ports
+        .iter()
+        .find(|x| x.number == Some(port))
+        .or(match declared_ports {
+            [declared_port] => Some(declared_port),
+            _ => None,
+        })
+}
+
+/// Reads the answer to the target port prompt, which is the index of a declared port, a port
+/// number or a port name, or, when empty, stands for the default declared port or for the port
+/// itself, and returns the target port along with the declared port that it matches, if any.
+fn choose_target_port<'a>(
+    answer: &str,
+    port: u16,
+    declared_ports: &'a [DeclaredPort],
+) -> Result<(TargetPort, Option<&'a DeclaredPort>), String> {
+    let answer = answer.trim();
+
+    if answer.is_empty() {
+        return Ok(match default_declared_port(port, declared_ports) {
+            Some(declared_port) => (declared_port.target_port.clone(), Some(declared_port)),
+            None => (TargetPort::Number(port), None),
+        });
+    }
+
+    let index = answer.parse::<usize>().ok().filter(|x| *x >= 1);
+    if let Some(declared_port) = index.and_then(|x| declared_ports.get(x - 1)) {
+        return Ok((declared_port.target_port.clone(), Some(declared_port)));
+    }
+
+    let target_port = answer.parse::<TargetPort>()?;
+    let declared_port = declared_ports.iter().find(|x| {
+        x.target_port == target_port
+            || matches!(target_port, TargetPort::Number(number) if x.number == Some(number))
+    });
+
+    Ok((target_port, declared_port))
+}
+
+fn delete_load_balancer_guided(session: &Session) -> CommandExecutionResult {
     let index: usize = parse_num(
         "Index: ",
         None,
@@ -183,29 +1146,909 @@ fn delete_load_balancer_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    delete_load_balancer_by_index(index - 1)
+    delete_load_balancer_by_index(session, index - 1)
 }
 
-fn delete_load_balancer_by_index(index: usize) -> CommandExecutionResult {
-    let load_balancers = match fetch_load_balancers()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
-    };
+pub fn delete_load_balancer_by_index(session: &Session, index: usize) -> CommandExecutionResult {
+    let load_balancers = list_load_balancers(session)?;
 
     if index >= load_balancers.len() {
-        return Err(format!(
+        return Err(Error::NotFound(format!(
             "Index {index} does not correspond to a load balancer"
-        ));
+        )));
+    }
+
+    let (namespace, name) = load_balancer_id(&load_balancers[index]);
+
+    delete_load_balancer(session, &namespace, &name)
+}
+
+/// The namespace and the name of a listed load balancer.
+pub fn load_balancer_id(load_balancer: &Resource) -> (String, String) {
+    let field = |key: &str| {
+        load_balancer
+            .fields
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    (field("namespace"), field("name"))
+}
+
+/// The labels and the annotations of a listed load balancer, apart from those of `kube-minion`.
+pub fn load_balancer_metadata(load_balancer: &Resource) -> ServiceMetadata {
+    let field = |key: &str| {
+        load_balancer
+            .fields
+            .get(key)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+            .collect()
+    };
+
+    ServiceMetadata {
+        name: None,
+        labels: field("labels"),
+        annotations: field("annotations"),
+    }
+}
+
+/// The external IPs, or host names, that have been assigned to a load balancer, eg, by the minikube
+/// tunnel.
+fn external_ips(service: &Value) -> Vec<&str> {
+    service["status"]["loadBalancer"]["ingress"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x["ip"].as_str().or(x["hostname"].as_str()))
+        .collect()
+}
+
+/// The URLs of the TCP ports of a load balancer on one of its external IPs.
+fn load_balancer_urls(ports: &[PortMapping], external_ip: &str) -> Vec<String> {
+    let host = if external_ip.contains(':') {
+        format!("[{external_ip}]")
+    } else {
+        String::from(external_ip)
+    };
+
+    ports
+        .iter()
+        .filter(|x| x.protocol == Protocol::Tcp)
+        .map(|x| format!("http://{host}:{}", x.port))
+        .collect()
+}
+
+/// Where a load balancer can be accessed, which is its URLs, or its external IP when it has no TCP
+/// ports.
+fn load_balancer_address(urls: &[String], external_ips: &[&str]) -> String {
+    if urls.is_empty() {
+        external_ips.join(",")
+    } else {
+        urls.join(", ")
+    }
+}
+
+/// The time since a timestamp, in the unit kubectl would show it in, eg, 90s, 5m, 3h or 2d.
+fn age(timestamp: &str) -> String {
+    let Some(created) = parse_timestamp(timestamp) else {
+        return String::from("<unknown>");
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+
+    match now.saturating_sub(created) {
+        secs @ 0..120 => format!("{secs}s"),
+        secs @ 120..7200 => format!("{}m", secs / 60),
+        secs @ 7200..172800 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86400),
+    }
+}
+
+/// Describes a port of a service, as the Kubernetes API describes it, eg, http=8080->80/TCP.
+fn describe_service_port(port: &Value) -> String {
+    let name = match port["name"].as_str() {
+        Some(name) => format!("{name}="),
+        None => String::new(),
+    };
+    let target_port = match &port["targetPort"] {
+        Value::Null => port["port"].to_string(),
+        Value::String(target_port) => target_port.clone(),
+        target_port => target_port.to_string(),
+    };
+
+    format!(
+        "{name}{}->{target_port}/{}",
+        port["port"],
+        port["protocol"].as_str().unwrap_or("TCP")
+    )
+}
+
+/// Describes what a load balancer exposes and how, eg, deployment/web on http=8080->80/TCP, labels
+/// team=web, so that a configured load balancer can be compared with an existing one.
+fn describe_config(resource: &str, ports: &[Value], metadata: &ServiceMetadata) -> String {
+    let ports: Vec<String> = ports.iter().map(describe_service_port).collect();
+    let mut config = format!("{resource} on {}", ports.join(", "));
+
+    let metadata = metadata.describe();
+    if !metadata.is_empty() {
+        config.push_str(&format!(", {metadata}"));
     }
 
-    let (namespace, name) = parse_load_balancer(&load_balancers[index]);
+    config
+}
+
+/// The configuration of a load balancer, as the initialization file configures it.
+pub fn load_balancer_config(
+    resource_type: &str,
+    name: &str,
+    ports: &[PortMapping],
+    metadata: &ServiceMetadata,
+) -> String {
+    describe_config(
+        &format!("{resource_type}/{name}"),
+        &service_ports(ports),
+        metadata,
+    )
+}
+
+/// The configuration of a listed load balancer, which is comparable with that of a configured one.
+pub fn listed_load_balancer_config(load_balancer: &Resource) -> String {
+    let resource = load_balancer
+        .fields
+        .get("resource")
+        .and_then(Value::as_str)
+        .unwrap_or("<unknown>");
+    let ports = load_balancer
+        .fields
+        .get("ports")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    describe_config(resource, &ports, &load_balancer_metadata(load_balancer))
+}
+
+/// Builds a resource out of a service, as the Kubernetes API describes it, which tells the resource
+/// that it exposes, its ports and where it can be accessed, which is only the case once the
+/// minikube tunnel routes it.
+fn describe_load_balancer(service: &Value, tunnel_running: bool) -> Resource {
+    let value = |x: &Value| x.as_str().unwrap_or("<none>").to_string();
+    let namespace = value(&service["metadata"]["namespace"]);
+    let name = value(&service["metadata"]["name"]);
+    let external_ips = external_ips(service);
+    let port_mappings = service_port_mappings(service);
+    let urls = external_ips
+        .first()
+        .map(|x| load_balancer_urls(&port_mappings, x))
+        .unwrap_or_default();
+    let routed = !external_ips.is_empty() && tunnel_running;
+    let metadata = ServiceMetadata::from_service(service);
+
+    let resource = match (
+        service["metadata"]["labels"][RESOURCE_TYPE_LABEL].as_str(),
+        exposed_resource_name(service, &port_mappings),
+    ) {
+        (Some(resource_type), Some(resource_name)) => format!("{resource_type}/{resource_name}"),
+        (None, Some(resource_name)) => String::from(resource_name),
+        (_, None) => String::from("<unknown>"),
+    };
+
+    let ports: Vec<Value> = service["spec"]["ports"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|x| {
+            json!({
+                "name": x["name"],
+                "port": x["port"],
+                "targetPort": x["targetPort"],
+                "nodePort": x["nodePort"],
+                "protocol": x["protocol"].as_str().unwrap_or("TCP"),
+            })
+        })
+        .collect();
+
+    let port_descriptions: Vec<String> = ports.iter().map(describe_service_port).collect();
+
+    let location = match (external_ips.is_empty(), tunnel_running) {
+        (false, true) => format!("at {}", load_balancer_address(&urls, &external_ips)),
+        (false, false) => format!(
+            "at {} (not routed, the minikube tunnel is not running)",
+            load_balancer_address(&urls, &external_ips)
+        ),
+        (true, true) => String::from("waiting for an external IP"),
+        (true, false) => {
+            String::from("waiting for an external IP (the minikube tunnel is not running)")
+        }
+    };
 
-    start_and_wait_process("kubectl", &["-n", &namespace, "delete", "svc", &name], None)
+    Resource::new(
+        "loadBalancer",
+        format!(
+            "Load balancer {namespace}/{name} for {resource} on {}, {location}",
+            port_descriptions.join(", ")
+        ),
+    )
+    .field("namespace", namespace)
+    .field("name", name)
+    .field("resource", resource)
+    .field("clusterIp", value(&service["spec"]["clusterIP"]))
+    .field(
+        "externalIp",
+        if external_ips.is_empty() {
+            String::from("<pending>")
+        } else {
+            external_ips.join(",")
+        },
+    )
+    .field("ports", ports)
+    .field("labels", json!(metadata.labels))
+    .field("annotations", json!(metadata.annotations))
+    .field("urls", urls)
+    .field("routed", routed)
+    .field(
+        "age",
+        age(service["metadata"]["creationTimestamp"]
+            .as_str()
+            .unwrap_or_default()),
+    )
+    .field(
+        "state",
+        if external_ips.is_empty() {
+            "pending"
+        } else {
+            "ready"
+        },
+    )
 }
 
-fn parse_load_balancer(spec: &str) -> (String, String) {
-    let re = Regex::new(r"\s+").unwrap();
-    let parts: Vec<&str> = re.split(spec).collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minikube_tunnel::create_minikube_tunnel;
+    use crate::test_support::{resource_fields, StubApiServer, TestSession};
+
+    const KUBECTL_GET_SVC: &str = "kubectl get svc -A -l reason=kube-minion -o json";
+
+    /// A load balancer's service, as `kubectl get svc -o json` lists it.
+    fn service(namespace: &str, name: &str, external_ip: Option<&str>, port: u16) -> Value {
+        let ingress: Vec<Value> = external_ip.map(|x| json!({"ip": x})).into_iter().collect();
+
+        json!({
+            "metadata": {"namespace": namespace, "name": name},
+            "spec": {
+                "type": "LoadBalancer",
+                "clusterIP": "10.0.0.1",
+                "ports": [{"port": port, "targetPort": port, "nodePort": 31000, "protocol": "TCP"}]
+            },
+            "status": {"loadBalancer": {"ingress": ingress}}
+        })
+    }
+
+    fn services(items: &[Value]) -> String {
+        json!({ "items": items }).to_string()
+    }
+
+    #[test]
+    fn port_mappings_are_parsed_with_an_optional_name_and_target_port() {
+        assert_eq!("8080".parse(), Ok(PortMapping::new(8080, 8080)));
+        assert_eq!("8080:80".parse(), Ok(PortMapping::new(8080, 80)));
+        assert_eq!(
+            "http=8080:80".parse(),
+            Ok(PortMapping {
+                name: Some(String::from("http")),
+                port: 8080,
+                target_port: TargetPort::Number(80),
+                protocol: Protocol::Tcp,
+            })
+        );
+        assert_eq!(
+            "53/UDP".parse(),
+            Ok(PortMapping {
+                protocol: Protocol::Udp,
+                ..PortMapping::new(53, 53)
+            })
+        );
+        assert!("http=".parse::<PortMapping>().is_err());
+        assert!("53/icmp".parse::<PortMapping>().is_err());
+        assert!("8080:0".parse::<PortMapping>().is_err());
+        assert_eq!(
+            "8080:http".parse::<PortMapping>().map(|x| x.target_port),
+            Ok(TargetPort::Name(String::from("http")))
+        );
+        assert!("8080:HTTP".parse::<PortMapping>().is_err());
+        assert!("8080:-http".parse::<PortMapping>().is_err());
+        assert!("8080:a-very-long-port-name".parse::<PortMapping>().is_err());
+        assert!("Web_Http=8080".parse::<PortMapping>().is_err());
+    }
+
+    #[test]
+    fn create_load_balancer_targets_a_named_port() {
+        let session = TestSession::new();
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &["8080:http".parse().unwrap()],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["name", "ports"]),
+            vec![vec![
+                "web-8080-http-lb",
+                r#"[{"port":8080,"protocol":"TCP","targetPort":"http"}]"#
+            ]]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl -n apps expose deployment web --type LoadBalancer --name \
+                web-8080-http-lb --port 8080 --target-port http -l \
+                reason=kube-minion,kube-minion/resource-type=deployment"
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_ports_are_the_container_ports_or_the_target_ports_of_a_service() {
+        let deployment = json!({"spec": {"template": {"spec": {"containers": [
+            {"name": "web", "ports": [{"name": "http", "containerPort": 8080},
+                                      {"containerPort": 9090, "protocol": "TCP"}]},
+            {"name": "dns", "ports": [{"containerPort": 53, "protocol": "UDP"}]}
+        ]}}}});
+        let service = json!({"spec": {"ports": [{"port": 80, "targetPort": "http"},
+                                                {"port": 443}]}});
+
+        assert_eq!(
+            declared_ports(&deployment)
+                .iter()
+                .map(|x| (x.target_port.to_string(), x.number, x.protocol))
+                .collect::<Vec<_>>(),
+            vec![
+                (String::from("http"), Some(8080), Protocol::Tcp),
+                (String::from("9090"), Some(9090), Protocol::Tcp),
+                (String::from("53"), Some(53), Protocol::Udp),
+            ]
+        );
+        assert_eq!(
+            declared_ports(&deployment)
+                .into_iter()
+                .map(|x| x.description)
+                .collect::<Vec<_>>(),
+            vec![
+                "http (8080/TCP, container web)",
+                "9090/TCP (container web)",
+                "53/UDP (container dns)",
+            ]
+        );
+        assert_eq!(
+            declared_ports(&service)
+                .into_iter()
+                .map(|x| x.description)
+                .collect::<Vec<_>>(),
+            vec![
+                "http/TCP (port 80 of the service)",
+                "443/TCP (port 443 of the service)"
+            ]
+        );
+    }
+
+    #[test]
+    fn choose_target_port_picks_a_declared_port_or_takes_a_number_or_a_name() {
+        let declared_ports = declared_ports(&json!({"spec": {"containers": [
+            {"name": "web", "ports": [{"name": "http", "containerPort": 8080},
+                                      {"name": "metrics", "containerPort": 9090}]}
+        ]}}));
+        let choose = |answer: &str, port: u16| {
+            choose_target_port(answer, port, &declared_ports)
+                .map(|(target_port, x)| (target_port.to_string(), x.is_some()))
+        };
+
+        // An empty answer stands for the declared port with the same number, if any.
+        assert_eq!(choose("", 8080), Ok((String::from("http"), true)));
+        assert_eq!(choose("", 80), Ok((String::from("80"), false)));
+        assert_eq!(choose("2", 80), Ok((String::from("metrics"), true)));
+        assert_eq!(choose("9090", 80), Ok((String::from("9090"), true)));
+        assert_eq!(choose("http", 80), Ok((String::from("http"), true)));
+        assert_eq!(choose("admin", 80), Ok((String::from("admin"), false)));
+        assert!(choose("Admin", 80).is_err());
+        assert_eq!(
+            choose_target_port("", 80, &[]).map(|(x, _)| x),
+            Ok(TargetPort::Number(80))
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_patches_in_the_ports_after_the_first() {
+        let session = TestSession::new();
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80), "metrics=9090".parse().unwrap()],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["name", "ports"]),
+            vec![vec![
+                "web-8080-80-9090-9090-lb",
+                r#"[{"name":"port-8080","port":8080,"protocol":"TCP","targetPort":80},{"name":"metrics","port":9090,"protocol":"TCP","targetPort":9090}]"#
+            ]]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl -n apps expose deployment web --type LoadBalancer --name \
+                web-8080-80-9090-9090-lb --port 8080 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=deployment",
+                r#"kubectl -n apps patch svc web-8080-80-9090-9090-lb --type json -p [{"op":"replace","path":"/spec/ports","value":[{"name":"port-8080","port":8080,"protocol":"TCP","targetPort":80},{"name":"metrics","port":9090,"protocol":"TCP","targetPort":9090}]}]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_deletes_the_exposed_service_when_it_cannot_be_patched() {
+        let session = TestSession::new();
+        session.runner.respond_err(
+            "kubectl -n apps patch svc web-8080-80-9090-9090-lb",
+            "The Service \"web-8080-80-9090-9090-lb\" is invalid",
+        );
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80), PortMapping::new(9090, 9090)],
+            &ServiceMetadata::default(),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(
+            session.runner.calls().last().unwrap(),
+            "kubectl -n apps delete svc web-8080-80-9090-9090-lb"
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_exposes_a_udp_port_with_its_protocol() {
+        let session = TestSession::new();
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "services",
+            "dns",
+            &["53/udp".parse().unwrap(), "53/tcp".parse().unwrap()],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["name"]),
+            vec![vec!["dns-53-53-udp-53-53-lb"]]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl -n apps expose services dns --type LoadBalancer --name \
+                dns-53-53-udp-53-53-lb --port 53 --target-port 53 -l \
+                reason=kube-minion,kube-minion/resource-type=services --protocol UDP",
+                r#"kubectl -n apps patch svc dns-53-53-udp-53-53-lb --type json -p [{"op":"replace","path":"/spec/ports","value":[{"name":"port-53-udp","port":53,"protocol":"UDP","targetPort":53},{"name":"port-53","port":53,"protocol":"TCP","targetPort":53}]}]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_requires_a_port() {
+        let session = TestSession::new();
+
+        assert!(matches!(
+            create_load_balancer(
+                &session,
+                "apps",
+                "deployment",
+                "web",
+                &[],
+                &ServiceMetadata::default()
+            ),
+            Err(Error::Input(_))
+        ));
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn create_load_balancer_names_labels_and_annotates_the_service() {
+        let session = TestSession::new();
+        let metadata = ServiceMetadata {
+            name: Some(String::from("web-lb")),
+            labels: BTreeMap::from([(String::from("team"), String::from("web"))]),
+            annotations: BTreeMap::from([(
+                String::from("metallb.universe.tf/address-pool"),
+                String::from("default"),
+            )]),
+        };
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &metadata,
+        );
+
+        assert_eq!(
+            resource_fields(result, &["name", "resourceName", "labels", "annotations"]),
+            vec![vec![
+                "web-lb",
+                "web",
+                r#"{"team":"web"}"#,
+                r#"{"kube-minion/resource-name":"web","metallb.universe.tf/address-pool":"default"}"#
+            ]]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl -n apps expose deployment web --type LoadBalancer --name web-lb \
+                --port 8080 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=deployment,team=web",
+                r#"kubectl -n apps patch svc web-lb --type json -p [{"op":"add","path":"/metadata/annotations","value":{"kube-minion/resource-name":"web","metallb.universe.tf/address-pool":"default"}}]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_rejects_invalid_names_and_reserved_keys() {
+        let session = TestSession::new();
+        let long_name = "a".repeat(60);
+        let reserved_label = ServiceMetadata {
+            labels: BTreeMap::from([(String::from("reason"), String::from("mine"))]),
+            ..ServiceMetadata::default()
+        };
+        let invalid_name = ServiceMetadata {
+            name: Some(String::from("Web_LB")),
+            ..ServiceMetadata::default()
+        };
 
-    (String::from(parts[0]), String::from(parts[1]))
+        for (name, metadata) in [
+            (long_name.as_str(), &ServiceMetadata::default()),
+            ("web", &reserved_label),
+            ("web", &invalid_name),
+        ] {
+            assert!(matches!(
+                create_load_balancer(
+                    &session,
+                    "apps",
+                    "deployment",
+                    name,
+                    &[PortMapping::new(8080, 80)],
+                    metadata
+                ),
+                Err(Error::Input(_))
+            ));
+        }
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn create_load_balancer_exposes_the_resource() {
+        let session = TestSession::new();
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "name", "state"]),
+            vec![vec!["apps", "web-8080-80-lb", "created"]]
+        );
+        assert_eq!(
+            session.runner.calls(),
+            vec![
+                "kubectl -n apps expose deployment web --type LoadBalancer --name web-8080-80-lb \
+                --port 8080 --target-port 80 -l \
+                reason=kube-minion,kube-minion/resource-type=deployment"
+            ]
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_selects_the_pods_of_the_resource_through_the_api() {
+        let session = TestSession::new();
+        let server = StubApiServer::start();
+        session.connect_kube_api(&server);
+        server.respond(
+            "GET /apis/apps/v1/namespaces/apps/deployments/web",
+            200,
+            r#"{"spec": {"selector": {"matchLabels": {"app": "web"}}}}"#,
+        );
+        server.respond(
+            "POST /api/v1/namespaces/apps/services",
+            201,
+            r#"{"metadata": {"namespace": "apps", "name": "web-8080-80-lb"}}"#,
+        );
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "name", "state"]),
+            vec![vec!["apps", "web-8080-80-lb", "created"]]
+        );
+        let requests = server.requests();
+        assert_eq!(
+            requests[0],
+            "GET /apis/apps/v1/namespaces/apps/deployments/web"
+        );
+        let (request, body) = requests[1].split_once(" {").unwrap();
+        assert_eq!(request, "POST /api/v1/namespaces/apps/services");
+        let body: Value = serde_json::from_str(&format!("{{{body}")).unwrap();
+        assert_eq!(body["spec"]["selector"], serde_json::json!({"app": "web"}));
+        assert_eq!(body["metadata"]["labels"]["reason"], "kube-minion");
+        assert_eq!(
+            body["metadata"]["labels"]["kube-minion/resource-type"],
+            "deployment"
+        );
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn fetch_load_balancers_lists_the_services_through_the_api() {
+        let session = TestSession::new();
+        let server = StubApiServer::start();
+        session.connect_kube_api(&server);
+        server.respond(
+            "GET /api/v1/services?labelSelector=reason%3Dkube-minion",
+            200,
+            r#"{"items": [
+                {
+                    "metadata": {"namespace": "default", "name": "web-8080-80-lb"},
+                    "spec": {
+                        "type": "LoadBalancer",
+                        "clusterIP": "10.0.0.1",
+                        "ports": [{"port": 8080, "nodePort": 31000, "protocol": "TCP"}]
+                    },
+                    "status": {"loadBalancer": {"ingress": [{"ip": "127.0.0.1"}]}}
+                },
+                {
+                    "metadata": {"namespace": "kubernetes-dashboard", "name": "kubernetes-dashboard-lb"},
+                    "spec": {"type": "LoadBalancer", "ports": [{"port": 51515, "protocol": "TCP"}]}
+                },
+                {
+                    "metadata": {"namespace": "apps", "name": "db-5432-5432-lb"},
+                    "spec": {"type": "LoadBalancer", "ports": [{"port": 5432, "protocol": "TCP"}]},
+                    "status": {"loadBalancer": {}}
+                }
+            ]}"#,
+        );
+
+        let result = fetch_load_balancers(&session);
+
+        assert_eq!(
+            resource_fields(
+                result,
+                &["namespace", "name", "externalIp", "ports", "state"]
+            ),
+            vec![
+                vec![
+                    "default",
+                    "web-8080-80-lb",
+                    "127.0.0.1",
+                    r#"[{"name":null,"nodePort":31000,"port":8080,"protocol":"TCP","targetPort":null}]"#,
+                    "ready"
+                ],
+                vec![
+                    "apps",
+                    "db-5432-5432-lb",
+                    "<pending>",
+                    r#"[{"name":null,"nodePort":null,"port":5432,"protocol":"TCP","targetPort":null}]"#,
+                    "pending"
+                ],
+            ]
+        );
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn fetch_load_balancers_skips_the_dashboard_load_balancer() {
+        let session = TestSession::new();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            &services(&[
+                service("default", "web-8080-80-lb", Some("127.0.0.1"), 8080),
+                service(
+                    "kubernetes-dashboard",
+                    "kubernetes-dashboard-lb",
+                    Some("127.0.0.1"),
+                    51515,
+                ),
+                service("apps", "db-5432-5432-lb", None, 5432),
+            ]),
+        );
+
+        let result = fetch_load_balancers(&session);
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "name", "externalIp", "state"]),
+            vec![
+                vec!["default", "web-8080-80-lb", "127.0.0.1", "ready"],
+                vec!["apps", "db-5432-5432-lb", "<pending>", "pending"],
+            ]
+        );
+    }
+
+    #[test]
+    fn fetch_load_balancers_tells_where_the_routed_load_balancers_can_be_accessed() {
+        let session = TestSession::new();
+        create_minikube_tunnel(&session).unwrap();
+        let mut web = service("apps", "web-8080-80-lb", Some("127.0.0.1"), 8080);
+        web["metadata"]["labels"] = json!({RESOURCE_TYPE_LABEL: "deployment"});
+        web["spec"]["ports"][0]["targetPort"] = json!(80);
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            &services(&[web, service("apps", "db-5432-5432-lb", None, 5432)]),
+        );
+
+        let load_balancers = match list_load_balancers(&session) {
+            Ok(load_balancers) => load_balancers,
+            Err(error) => panic!("Listing load balancers has failed: {error}"),
+        };
+
+        assert_eq!(
+            load_balancers
+                .iter()
+                .map(|x| x.description.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Load balancer apps/web-8080-80-lb for deployment/web on 8080->80/TCP, at \
+                http://127.0.0.1:8080",
+                "Load balancer apps/db-5432-5432-lb for db on 5432->5432/TCP, waiting for an \
+                external IP",
+            ]
+        );
+        assert_eq!(
+            resource_fields(
+                Ok(Resources(None, load_balancers)),
+                &["resource", "urls", "routed"]
+            ),
+            vec![
+                vec!["deployment/web", r#"["http://127.0.0.1:8080"]"#, "true"],
+                vec!["db", "[]", "false"],
+            ]
+        );
+    }
+
+    #[test]
+    fn create_load_balancer_waits_for_the_external_ip_while_the_tunnel_runs() {
+        let session = TestSession::new();
+        create_minikube_tunnel(&session).unwrap();
+        session.runner.respond_ok(
+            "kubectl -n apps get svc web-8080-80-lb -o json",
+            &service("apps", "web-8080-80-lb", Some("127.0.0.1"), 8080).to_string(),
+        );
+
+        let result = create_load_balancer(
+            &session,
+            "apps",
+            "deployment",
+            "web",
+            &[PortMapping::new(8080, 80)],
+            &ServiceMetadata::default(),
+        );
+
+        assert_eq!(
+            resource_fields(result, &["externalIp", "urls"]),
+            vec![vec!["127.0.0.1", r#"["http://127.0.0.1:8080"]"#]]
+        );
+    }
+
+    #[test]
+    fn fetch_load_balancers_probes_the_external_ports() {
+        let session = TestSession::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            &services(&[
+                service(
+                    "default",
+                    &format!("web-{port}-80-lb"),
+                    Some("127.0.0.1"),
+                    port,
+                ),
+                service("apps", "db-5432-5432-lb", None, 5432),
+            ]),
+        );
+
+        let result = fetch_load_balancers(&session);
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "health"]),
+            vec![vec!["default", "reachable"], vec!["apps", "unknown"]]
+        );
+    }
+
+    #[test]
+    fn delete_load_balancer_by_index_deletes_the_listed_service() {
+        let session = TestSession::new();
+        session.runner.respond_ok(
+            KUBECTL_GET_SVC,
+            &services(&[
+                service("default", "web-8080-80-lb", Some("127.0.0.1"), 8080),
+                service("apps", "db-5432-5432-lb", Some("127.0.0.1"), 5432),
+            ]),
+        );
+
+        let result = delete_load_balancer_by_index(&session, 1);
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "name", "state"]),
+            vec![vec!["apps", "db-5432-5432-lb", "deleted"]]
+        );
+        assert_eq!(
+            session.runner.calls().last().unwrap(),
+            "kubectl -n apps delete svc db-5432-5432-lb"
+        );
+    }
+
+    #[test]
+    fn delete_load_balancer_by_index_fails_for_an_unknown_index() {
+        let session = TestSession::new();
+        session.runner.respond_ok(KUBECTL_GET_SVC, &services(&[]));
+
+        assert!(matches!(
+            delete_load_balancer_by_index(&session, 0),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn delete_load_balancer_reports_a_missing_service_as_not_found() {
+        let session = TestSession::new();
+        session.runner.respond_err(
+            "kubectl -n default delete svc web-8080-80-lb",
+            "Error from server (NotFound): services \"web-8080-80-lb\" not found",
+        );
+
+        assert!(matches!(
+            delete_load_balancer(&session, "default", "web-8080-80-lb"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn missing_kubectl_is_reported_as_a_missing_dependency() {
+        let session = TestSession::new();
+        session.runner.remove_command("kubectl");
+
+        assert_eq!(
+            fetch_load_balancers(&session).err(),
+            Some(Error::MissingDependency(String::from("kubectl")))
+        );
+    }
 }
@@ -1,15 +1,46 @@
 use args::Args;
 use getopts::Occur;
 use regex::Regex;
+use signal_hook::consts::{SIGHUP, SIGTERM};
+use signal_hook::iterator::Signals;
 use std::env;
+use std::fs::File;
 use std::io::{stdin, stdout, BufRead, Write};
+use std::os::fd::AsRawFd;
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use kube_minion::{
-    self, build_options, create_kubernetes_dashboard_load_balancer, create_minikube_tunnel,
-    print_results, run_init_file, set_dashboard_port, verify_dependencies, OptionFunc,
+    self, apply_init_file, build_options, clean_up_on_signal, connect_kube_api,
+    create_kubernetes_dashboard_load_balancer, create_minikube_tunnel, get_init_file_path,
+    parse_subcommand, print_message, print_results, run_init_file, run_subcommand,
+    select_kube_context, subcommand_usage, verify_dependencies, CommandResultType,
+    DryRunProcessRunner, Error, InitFileWatcher, OptionFunc, OutputFormat, Session, Settings,
+    SignalPolicy, Subcommand, Supervisor, SystemProcessRunner, SUBCOMMANDS,
 };
 
-fn main() -> Result<(), String> {
+/// The command line options which do not take a value.
+const FLAGS: [&str; 6] = [
+    "-h",
+    "--help",
+    "--apply",
+    "--allow-foreign-context",
+    "--use-kubectl",
+    "--dry-run",
+];
+
+/// Held while the signal policy is being applied, so that `kube-minion` only exits once.
+static TERMINATION: Mutex<()> = Mutex::new(());
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{error}");
+        exit(error.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
     let mut args = Args::new(
         "kube-minion",
         "https://github.com/sadesyllas/kube-minion/blob/main/README.md",
@@ -31,41 +62,250 @@ fn main() -> Result<(), String> {
         Occur::Optional,
         None,
     );
-    args.parse(env::args()).map_err(|x| x.to_string())?;
+    args.flag(
+        "",
+        "apply",
+        "Make the resources match the initialization file, instead of only creating them",
+    );
+    args.option(
+        "o",
+        "output",
+        "The format in which to print results, either text or json",
+        "OUTPUT",
+        Occur::Optional,
+        Some(String::from("text")),
+    );
+    args.option(
+        "",
+        "profile",
+        "The minikube profile to operate on, instead of minikube's active profile",
+        "PROFILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "context",
+        "The kubectl context to operate on, instead of the current context",
+        "CONTEXT",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "kubeconfig",
+        "The kubeconfig file for kubectl to read",
+        "KUBECONFIG",
+        Occur::Optional,
+        None,
+    );
+    args.flag(
+        "",
+        "allow-foreign-context",
+        "Operate on a kubectl context which does not belong to the minikube profile",
+    );
+    args.flag(
+        "",
+        "use-kubectl",
+        "Run kubectl instead of talking to the Kubernetes API directly",
+    );
+    args.flag(
+        "",
+        "dry-run",
+        "Print the commands that would change anything instead of running them",
+    );
+    args.option(
+        "",
+        "on-signal",
+        "What to do with the created resources on SIGTERM or SIGHUP, either cleanup, detach or \
+        cleanup-init-file",
+        "POLICY",
+        Occur::Optional,
+        Some(String::from("cleanup")),
+    );
+
+    let (raw_args, subcommand_args) = split_subcommand_args(env::args().collect());
+
+    args.parse(raw_args)
+        .map_err(|x| Error::Input(x.to_string()))?;
 
     if args.value_of("help").unwrap_or_default() {
-        println!("{}", args.full_usage());
+        println!("{}\n{}", args.full_usage(), subcommand_usage());
         return Ok(());
     }
 
     let dashboard_port: u16 = args
         .value_of("dashboard-port")
-        .expect("The provided value is not a valid u16");
-    set_dashboard_port(dashboard_port);
+        .map_err(|_| Error::Input(String::from("The dashboard port is not a valid u16")))?;
+
+    let output_format: OutputFormat = args
+        .value_of("output")
+        .map_err(|x| Error::Input(x.to_string()))?;
+
+    let signal_policy: SignalPolicy = args
+        .value_of("on-signal")
+        .map_err(|x| Error::Input(x.to_string()))?;
+
+    let dry_run: bool = args.value_of("dry-run").unwrap_or_default();
+
+    let settings = Settings {
+        dashboard_port,
+        output_format,
+        minikube_profile: args.value_of::<String>("profile").ok(),
+        kube_context: args.value_of::<String>("context").ok(),
+        kubeconfig: args.value_of::<String>("kubeconfig").ok(),
+        allow_foreign_kube_context: args.value_of("allow-foreign-context").unwrap_or_default(),
+        use_kubectl: args.value_of("use-kubectl").unwrap_or_default(),
+        dry_run,
+        ..Settings::default()
+    };
+
+    let session = Arc::new(if dry_run {
+        Session::with_runner(
+            settings,
+            Arc::new(DryRunProcessRunner::new(Arc::new(SystemProcessRunner))),
+        )
+    } else {
+        Session::new(settings)
+    });
+
+    if !subcommand_args.is_empty() {
+        let subcommand = match parse_subcommand(&subcommand_args) {
+            Ok(subcommand) => subcommand,
+            Err(error) => {
+                eprintln!("{error}\n\n{}", subcommand_usage());
+                exit(error.exit_code());
+            }
+        };
+
+        // Neither the built-in forwarder of a socat tunnel nor validating an initialization file run
+        // commands or talk to the cluster, and the process that reconnects a detached port forward
+        // runs kubectl with the context it has been given, whereas an initialization file can set
+        // the kubectl context, so it is selected by applying the file.
+        let result = match subcommand {
+            Subcommand::Forward { .. }
+            | Subcommand::Reconnect { .. }
+            | Subcommand::Validate { .. } => run_subcommand(&session, subcommand),
+            Subcommand::Apply { .. } => {
+                verify_dependencies(&session).and_then(|_| run_subcommand(&session, subcommand))
+            }
+            subcommand => verify_dependencies(&session)
+                .and_then(|_| select_kube_context(&session))
+                .and_then(|_| {
+                    connect_kube_api(&session);
+                    run_subcommand(&session, subcommand)
+                }),
+        };
+        let exit_code = result.as_ref().err().map_or(0, Error::exit_code);
+
+        print_results(&session, result, true, true);
+
+        exit(exit_code);
+    }
+
+    verify_dependencies(&session)?;
+
+    // Signals which arrive from here on are only handled once the menu is up, so that what has been
+    // created in the meantime is cleaned up as well.
+    let mut signals = Signals::new([SIGTERM, SIGHUP]).map_err(Error::from)?;
+
+    // Resources have to be detached from the start to outlive kube-minion.
+    if signal_policy == SignalPolicy::Detach {
+        session.set_detach_child_processes(true);
+    }
+
+    // The cluster settings given on the command line take precedence over those of the
+    // initialization file, also when it is reloaded.
+    let initial_settings = session.settings();
+
+    let init_file_path = if args.value_of("apply").unwrap_or_default() {
+        let init_file_path = get_init_file_path(
+            &session,
+            args.value_of::<String>("initialization-file-path").ok(),
+        );
+
+        if let Some(init_file_path) = &init_file_path {
+            let results = apply_init_file(&session, init_file_path)?;
+            print_results(&session, Ok(results), true, true);
+        }
+
+        init_file_path
+    } else {
+        run_init_file(
+            &session,
+            args.value_of::<String>("initialization-file-path").ok(),
+        )?
+    };
 
-    verify_dependencies()?;
+    // An initialization file can set the kubectl context, so it is selected by processing the file.
+    if init_file_path.is_none() {
+        select_kube_context(&session)?;
+        print_message(&session, &connect_kube_api(&session));
+    }
 
-    let init_file_path = run_init_file(args.value_of::<String>("initialization-file-path").ok())?;
+    // The menu checks on the dashboard load balancer whenever it is refreshed.
+    if let Some(kube_api) = session.kube_api() {
+        kube_api.watch_services();
+    }
 
-    match create_kubernetes_dashboard_load_balancer() {
-        results @ Ok(_) => print_results(results, true, true),
+    match create_kubernetes_dashboard_load_balancer(&session) {
+        results @ Ok(_) => print_results(&session, results, true, true),
         Err(error) => return Err(error),
     };
 
-    match create_minikube_tunnel() {
-        results @ Ok(_) => print_results(results, true, true),
+    match create_minikube_tunnel(&session) {
+        results @ Ok(_) => print_results(&session, results, true, true),
         Err(error) => return Err(error),
     };
 
     unsafe {
         signal_hook::low_level::register(signal_hook::consts::SIGINT, || {
             println!(
-                "\nSIGINT received. Please, use option 16 or option 17 to exit the application."
+                "\nSIGINT received. Please, use the clean up and exit option or the exit without \
+                cleaning up option to exit the application."
             );
         })
-        .map_err(|x| x.to_string())?
+        .map_err(Error::from)?
+    };
+
+    let supervisor = Supervisor::start(session.clone());
+
+    let init_file_watcher = match &init_file_path {
+        Some(init_file_path) => {
+            match InitFileWatcher::start(session.clone(), init_file_path, initial_settings) {
+                Ok(init_file_watcher) => Some(init_file_watcher),
+                Err(error) => {
+                    print_message(
+                        &session,
+                        &format!("Changes to {init_file_path} will not be reloaded: {error}"),
+                    );
+                    None
+                }
+            }
+        }
+        None => None,
     };
 
+    {
+        let session = session.clone();
+        let supervisor = supervisor.clone();
+        let init_file_watcher = init_file_watcher.clone();
+        let init_file_path = init_file_path.clone();
+
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                terminate(
+                    &session,
+                    &supervisor,
+                    init_file_watcher.as_deref(),
+                    signal_policy,
+                    init_file_path.as_deref(),
+                    signal,
+                );
+            }
+        });
+    }
+
     let mut exit = false;
 
     #[allow(clippy::invalid_regex)] // clippy bug?
@@ -76,7 +316,38 @@ fn main() -> Result<(), String> {
             break;
         }
 
-        let options = build_options(init_file_path.as_ref())?;
+        let restarts = supervisor.take_events();
+
+        if !restarts.is_empty() {
+            print_results(
+                &session,
+                Ok(CommandResultType::PrintableResults(
+                    Some(String::from("Supervisor:")),
+                    restarts,
+                )),
+                true,
+                true,
+            );
+        }
+
+        let changes = init_file_watcher
+            .as_ref()
+            .map(|x| x.take_events())
+            .unwrap_or_default();
+
+        if !changes.is_empty() {
+            print_results(
+                &session,
+                Ok(CommandResultType::PrintableResults(
+                    Some(String::from("Initialization file:")),
+                    changes,
+                )),
+                true,
+                true,
+            );
+        }
+
+        let options = build_options(&session, init_file_path.as_ref())?;
 
         println!("Options:");
         println!("\t0. Refresh options");
@@ -113,10 +384,17 @@ fn main() -> Result<(), String> {
 
         let mut option_index = String::new();
         {
-            stdin()
-                .lock()
-                .read_line(&mut option_index)
-                .map_err(|x| x.to_string())?;
+            // The terminal has been closed, which is handled as a SIGHUP.
+            if !matches!(stdin().lock().read_line(&mut option_index), Ok(n) if n > 0) {
+                terminate(
+                    &session,
+                    &supervisor,
+                    init_file_watcher.as_deref(),
+                    signal_policy,
+                    init_file_path.as_deref(),
+                    SIGHUP,
+                );
+            }
         }
 
         let option_index = match option_index.trim().parse::<usize>() {
@@ -142,8 +420,75 @@ fn main() -> Result<(), String> {
 
         exit = *exit_after;
 
-        print_results(func(), true, true);
+        print_results(&session, func(&session), true, true);
     }
 
     Ok(())
 }
+
+/// Applies the signal policy and exits with the conventional status for the signal.
+fn terminate(
+    session: &Session,
+    supervisor: &Supervisor,
+    init_file_watcher: Option<&InitFileWatcher>,
+    signal_policy: SignalPolicy,
+    init_file_path: Option<&str>,
+    signal: i32,
+) -> ! {
+    // A second caller waits here until the first one exits.
+    let _termination = TERMINATION.lock();
+
+    if signal == SIGHUP {
+        silence_output();
+    }
+
+    supervisor.stop();
+    if let Some(init_file_watcher) = init_file_watcher {
+        init_file_watcher.stop();
+    }
+
+    print_results(
+        session,
+        clean_up_on_signal(session, signal_policy, init_file_path),
+        true,
+        true,
+    );
+
+    exit(128 + signal);
+}
+
+/// Points stdout and stderr to `/dev/null`, since printing to a closed terminal fails.
+fn silence_output() {
+    if let Ok(dev_null) = File::options().write(true).open("/dev/null") {
+        unsafe {
+            libc::dup2(dev_null.as_raw_fd(), libc::STDOUT_FILENO);
+            libc::dup2(dev_null.as_raw_fd(), libc::STDERR_FILENO);
+        }
+    }
+}
+
+/// Splits the command line arguments into the global options and the arguments of a subcommand,
+/// if one has been provided.
+/// The subcommand is the first free argument which is not the value of a global option.
+fn split_subcommand_args(raw_args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut expects_value = false;
+
+    // The first argument is the program's name.
+    for (i, arg) in raw_args.iter().enumerate().skip(1) {
+        if expects_value {
+            expects_value = false;
+        } else if arg.starts_with('-') {
+            // Values can also be attached, eg, --dashboard-port=51515 or -p51515.
+            expects_value = !FLAGS.contains(&arg.as_str())
+                && !arg.contains('=')
+                && (arg.starts_with("--") || arg.len() == 2);
+        } else if SUBCOMMANDS.contains(&arg.as_str()) {
+            let mut raw_args = raw_args;
+            let subcommand_args = raw_args.split_off(i);
+
+            return (raw_args, subcommand_args);
+        }
+    }
+
+    (raw_args, Vec::new())
+}
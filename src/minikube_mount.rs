@@ -1,14 +1,18 @@
-use regex::Regex;
-use std::{fs, thread, time::Duration};
-
-use sysinfo::{ProcessExt, SystemExt};
+use std::fs;
 
+use crate::process_log::log_path;
+use crate::registry::{
+    fetch_registered_processes, kill_registered_process, register_process, RegistryEntry,
+};
 use crate::{
-    get_sys_info, merge_if_ok, parse_num, parse_string, print_results, start_and_wait_process,
-    CommandExecutionResult, CommandResultType::*, OptionFunc,
+    is_minikube_profile_process, minikube_args, parse_num, parse_string,
+    spawn_long_running_process, CommandExecutionResult, CommandResultType::*, Error, OptionFunc,
+    Resource, Session,
 };
 
-pub fn build_create_minikube_mount_option() -> Result<(String, OptionFunc, bool), String> {
+pub const MINIKUBE_MOUNT_KIND: &str = "minikubeMount";
+
+pub fn build_create_minikube_mount_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Create minikube mount"),
         Box::new(create_minikube_mount_guided),
@@ -16,7 +20,7 @@ pub fn build_create_minikube_mount_option() -> Result<(String, OptionFunc, bool)
     ))
 }
 
-pub fn build_fetch_minikube_mounts_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_fetch_minikube_mounts_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("List minikube mounts"),
         Box::new(fetch_minikube_mounts),
@@ -24,7 +28,7 @@ pub fn build_fetch_minikube_mounts_option() -> Result<(String, OptionFunc, bool)
     ))
 }
 
-pub fn build_delete_minikube_mount_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_minikube_mount_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete minikube mount"),
         Box::new(delete_minikube_mount_guided),
@@ -32,7 +36,7 @@ pub fn build_delete_minikube_mount_option() -> Result<(String, OptionFunc, bool)
     ))
 }
 
-pub fn build_delete_all_minikube_mounts_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_all_minikube_mounts_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete all minikube mounts"),
         Box::new(delete_all_minikube_mounts),
@@ -40,99 +44,105 @@ pub fn build_delete_all_minikube_mounts_option() -> Result<(String, OptionFunc,
     ))
 }
 
-pub fn create_minikube_mount(host_path: &str, minikube_path: &str) -> CommandExecutionResult {
-    if !fs::metadata(host_path).map_err(|x| x.to_string())?.is_dir() {
-        return Err(format!("{host_path} is not a valid host directory path"));
+pub fn create_minikube_mount(
+    session: &Session,
+    host_path: &str,
+    minikube_path: &str,
+) -> CommandExecutionResult {
+    if !fs::metadata(host_path).is_ok_and(|x| x.is_dir()) {
+        return Err(Error::Input(format!(
+            "{host_path} is not a valid host directory path"
+        )));
     }
 
-    if check_minikube_mount(host_path, minikube_path).is_some() {
-        return Ok(PrintableResults(None, vec![
-            format!("Minikube mount from host path {host_path} to minikube path {minikube_path} already exists")
+    if let Some(entry) = check_minikube_mount(session, host_path, minikube_path)? {
+        return Ok(Resources(None, vec![
+            describe_minikube_mount(&entry, format!("Minikube mount from host path {host_path} to minikube path {minikube_path} already exists"), "running")
         ]));
     }
 
-    {
-        let host_path = String::from(host_path);
-        let minikube_path = String::from(minikube_path);
-        thread::spawn(move || {
-            print_results(
-                start_and_wait_process(
-                    "minikube",
-                    &["mount", &format!("{host_path}:{minikube_path}")],
-                    Some(format!(
-                    "Failed to stop minikube mount from host path {host_path} to minikube path \
-                    {minikube_path}"
-                )),
-                ),
-                false,
-                true,
-            );
-        });
-    }
-
-    {
-        let mut cnt = 0;
-
-        while let None = check_minikube_mount(host_path, minikube_path) && cnt < 5 {
-            cnt += 1;
-            thread::sleep(Duration::from_secs(1));
-        }
+    let args = minikube_args(session, &["mount", &format!("{host_path}:{minikube_path}")]);
+    let profile = session.minikube_profile();
+
+    let pid = spawn_long_running_process(
+        session,
+        "minikube",
+        &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+        match &profile {
+            Some(profile) => log_path(
+                session,
+                &["minikube-mount", profile, host_path, minikube_path],
+            ),
+            None => log_path(session, &["minikube-mount", host_path, minikube_path]),
+        },
+        Some(format!(
+            "Failed to start minikube mount from host path {host_path} to minikube path \
+            {minikube_path}"
+        )),
+    )?;
 
-        if cnt == 5 {
-            return Err(String::from(
-                "Failed to verify if minikube mount has been created",
-            ));
-        }
+    let mut spec = serde_json::Map::new();
+    spec.insert(String::from("hostPath"), host_path.into());
+    spec.insert(String::from("minikubePath"), minikube_path.into());
+    if let Some(profile) = profile {
+        spec.insert(String::from("profile"), profile.into());
     }
 
-    Ok(PrintableResults(
+    let entry =
+        register_process(session, MINIKUBE_MOUNT_KIND, pid, spec).map_err(|_| Error::Spawn {
+            command: String::from("minikube"),
+            message: String::from("Failed to verify if minikube mount has been created"),
+        })?;
+
+    Ok(Resources(
         None,
-        vec![format!(
-            "Created minikube mount from host path {host_path} to minikube path {minikube_path}"
+        vec![describe_minikube_mount(
+            &entry,
+            format!(
+                "Created minikube mount from host path {host_path} to minikube path {minikube_path}"
+            ),
+            "created",
         )],
     ))
 }
 
-pub fn delete_minikube_mount(host_path: &str, minikube_path: &str) -> CommandExecutionResult {
-    let mut results: Vec<String> = Vec::new();
-
-    if let Some(pid) = check_minikube_mount(host_path, minikube_path) &&
-            let Some(process) = get_sys_info().process(pid) {
-            if process.kill_with(sysinfo::Signal::Interrupt).is_some() {
-                results.push(format!("Stopped minikube mount from host path {host_path} to minikube path {minikube_path}"));
-            } else {
-                results.push(format!("Failed to stop minikube mount from host path {host_path} to minikube path {minikube_path}"));
-            }
-        }
+pub fn delete_minikube_mount(
+    session: &Session,
+    host_path: &str,
+    minikube_path: &str,
+) -> CommandExecutionResult {
+    let results = check_minikube_mount(session, host_path, minikube_path)?
+        .iter()
+        .map(|x| delete_minikube_mount_entry(session, x))
+        .collect();
 
-    Ok(PrintableResults(None, results))
+    Ok(Resources(None, results))
 }
 
-pub fn delete_all_minikube_mounts() -> CommandExecutionResult {
-    let minikube_mounts = match fetch_minikube_mounts()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
-    };
-
-    let mut results: Vec<String> = Vec::new();
-
-    for minikube_mount in &minikube_mounts {
-        let (host_path, minikube_path) = parse_minikube_mount(minikube_mount);
-
-        merge_if_ok(&mut results, || {
-            delete_minikube_mount(&host_path, &minikube_path)
-        })?;
-    }
+pub fn delete_all_minikube_mounts(session: &Session) -> CommandExecutionResult {
+    let results = fetch_minikube_mount_processes(session)?
+        .iter()
+        .map(|x| delete_minikube_mount_entry(session, x))
+        .collect();
 
-    Ok(PrintableResults(None, results))
+    Ok(Resources(None, results))
 }
 
-fn fetch_minikube_mounts() -> CommandExecutionResult {
-    let sys_info = get_sys_info();
-    let minikube_mounts: Vec<String> = sys_info
-        .processes_by_name("minikube")
-        .map(|x| x.cmd().join(" "))
-        .filter(|x| x.contains("mount"))
+pub fn fetch_minikube_mounts(session: &Session) -> CommandExecutionResult {
+    let minikube_mounts: Vec<Resource> = fetch_minikube_mount_processes(session)?
+        .iter()
+        .map(|x| {
+            describe_minikube_mount(
+                x,
+                format!(
+                    "Host path {} mounted on minikube path {} (pid {})",
+                    x.spec_str("hostPath"),
+                    x.spec_str("minikubePath"),
+                    x.pid,
+                ),
+                "running",
+            )
+        })
         .collect();
 
     let title = if minikube_mounts.is_empty() {
@@ -141,10 +151,18 @@ fn fetch_minikube_mounts() -> CommandExecutionResult {
         Some(String::from("Minikube mounts:"))
     };
 
-    Ok(PrintableResults(title, minikube_mounts))
+    Ok(Resources(title, minikube_mounts))
 }
 
-fn create_minikube_mount_guided() -> CommandExecutionResult {
+/// The minikube mounts of the session's minikube profile, leaving alone those of other profiles.
+pub fn fetch_minikube_mount_processes(session: &Session) -> Result<Vec<RegistryEntry>, Error> {
+    Ok(fetch_registered_processes(session, MINIKUBE_MOUNT_KIND)?
+        .into_iter()
+        .filter(|x| is_minikube_profile_process(session, x))
+        .collect())
+}
+
+fn create_minikube_mount_guided(session: &Session) -> CommandExecutionResult {
     let host_path = parse_string(
         "Host path: ",
         None,
@@ -161,21 +179,22 @@ fn create_minikube_mount_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    create_minikube_mount(&host_path, &minikube_path)
+    create_minikube_mount(session, &host_path, &minikube_path)
 }
 
-fn check_minikube_mount(host_path: &str, minikube_path: &str) -> Option<sysinfo::Pid> {
-    get_sys_info()
-        .processes_by_name("minikube")
+fn check_minikube_mount(
+    session: &Session,
+    host_path: &str,
+    minikube_path: &str,
+) -> Result<Option<RegistryEntry>, Error> {
+    Ok(fetch_minikube_mount_processes(session)?
+        .into_iter()
         .find(|x| {
-            let cmd = x.cmd().join(" ");
-
-            cmd.contains("mount") && cmd.contains(&format!("{host_path}:{minikube_path}"))
-        })
-        .map(|x| x.pid())
+            x.spec_str("hostPath") == host_path && x.spec_str("minikubePath") == minikube_path
+        }))
 }
 
-fn delete_minikube_mount_guided() -> CommandExecutionResult {
+fn delete_minikube_mount_guided(session: &Session) -> CommandExecutionResult {
     let index: usize = parse_num(
         "Index: ",
         None,
@@ -184,42 +203,156 @@ fn delete_minikube_mount_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    delete_minikube_mount_by_index(index - 1)
+    delete_minikube_mount_by_index(session, index - 1)
 }
 
-fn delete_minikube_mount_by_index(index: usize) -> CommandExecutionResult {
-    let minikube_mounts = match fetch_minikube_mounts()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
-    };
+pub fn delete_minikube_mount_by_index(session: &Session, index: usize) -> CommandExecutionResult {
+    let minikube_mounts = fetch_minikube_mount_processes(session)?;
 
     if index >= minikube_mounts.len() {
-        return Err(format!(
+        return Err(Error::NotFound(format!(
             "Index {index} does not correspond to a minikube mount"
-        ));
+        )));
     }
 
-    let (host_path, minikube_path) = parse_minikube_mount(&minikube_mounts[index]);
-
-    let mut results: Vec<String> = Vec::new();
+    Ok(Resources(
+        None,
+        vec![delete_minikube_mount_entry(
+            session,
+            &minikube_mounts[index],
+        )],
+    ))
+}
 
-    if let Some(pid) = check_minikube_mount(&host_path, &minikube_path) &&
-        let Some(process) = get_sys_info().process(pid) &&
-        process.kill_with(sysinfo::Signal::Interrupt).is_some() {
-        results.push(format!("Stopped minikube mount from host path {host_path} to minikube path {minikube_path}"));
+pub fn delete_minikube_mount_entry(session: &Session, entry: &RegistryEntry) -> Resource {
+    let host_path = entry.spec_str("hostPath");
+    let minikube_path = entry.spec_str("minikubePath");
+
+    match kill_registered_process(session, entry, sysinfo::Signal::Interrupt) {
+        Ok(_) => describe_minikube_mount(
+            entry,
+            format!(
+                "Stopped minikube mount from host path {host_path} to minikube path {minikube_path}"
+            ),
+            "deleted",
+        ),
+        Err(error) => describe_minikube_mount(
+            entry,
+            format!(
+                "Failed to stop minikube mount from host path {host_path} to minikube path \
+                {minikube_path}: {error}"
+            ),
+            "running",
+        ),
     }
+}
 
-    // start_and_wait_process("kubectl", &["-n", &namespace, "delete", "svc", &name], None)
-    Ok(PrintableResults(None, results))
+fn describe_minikube_mount(entry: &RegistryEntry, description: String, state: &str) -> Resource {
+    Resource::new("minikubeMount", description)
+        .field("hostPath", entry.spec_str("hostPath"))
+        .field("minikubePath", entry.spec_str("minikubePath"))
+        .field(
+            "profile",
+            entry.spec.get("profile").and_then(|x| x.as_str()),
+        )
+        .field("pid", entry.pid)
+        .field("state", state)
 }
 
-#[allow(clippy::invalid_regex)] // clippy bug?
-fn parse_minikube_mount(spec: &str) -> (String, String) {
-    let re = Regex::new(r".+?\s+(?<host_path>[^:]+):(?<minikube_path>[^:]+)$").unwrap();
-    let captures = re.captures(spec).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{resource_fields, TestSession};
+
+    #[test]
+    fn create_minikube_mount_starts_and_registers_the_mount() {
+        let session = TestSession::new();
+        let host_path = env!("CARGO_MANIFEST_DIR");
+
+        let result = create_minikube_mount(&session, host_path, "/src");
+
+        assert_eq!(
+            resource_fields(result, &["hostPath", "minikubePath", "state"]),
+            vec![vec![host_path, "/src", "created"]]
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec![format!("minikube mount {host_path}:/src")]
+        );
+    }
+
+    #[test]
+    fn create_minikube_mount_does_not_duplicate_an_existing_mount() {
+        let session = TestSession::new();
+        let host_path = env!("CARGO_MANIFEST_DIR");
+        create_minikube_mount(&session, host_path, "/src").unwrap();
+
+        let result = create_minikube_mount(&session, host_path, "/src");
 
-    let host_path: String = captures.name("host_path").unwrap().as_str().to_string();
-    let minikube_path: String = captures.name("minikube_path").unwrap().as_str().to_string();
+        assert_eq!(resource_fields(result, &["state"]), vec![vec!["running"]]);
+        assert_eq!(session.runner.running().len(), 1);
+    }
+
+    #[test]
+    fn create_minikube_mount_requires_a_host_directory() {
+        let session = TestSession::new();
+        let host_path = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
 
-    (host_path, minikube_path)
+        assert!(matches!(
+            create_minikube_mount(&session, host_path, "/src"),
+            Err(Error::Input(_))
+        ));
+        assert!(session.runner.calls().is_empty());
+    }
+
+    #[test]
+    fn minikube_mounts_of_other_profiles_are_left_alone() {
+        let session = TestSession::new();
+        let host_path = env!("CARGO_MANIFEST_DIR");
+        session.set_minikube_profile(Some(String::from("stable")));
+        create_minikube_mount(&session, host_path, "/src").unwrap();
+
+        session.set_minikube_profile(Some(String::from("experimental")));
+        let result = create_minikube_mount(&session, host_path, "/src");
+        assert_eq!(
+            resource_fields(result, &["profile", "state"]),
+            vec![vec!["experimental", "created"]]
+        );
+
+        delete_all_minikube_mounts(&session).unwrap();
+
+        assert_eq!(
+            session.runner.running(),
+            vec![format!("minikube -p stable mount {host_path}:/src")]
+        );
+    }
+
+    #[test]
+    fn delete_minikube_mounts() {
+        let session = TestSession::new();
+        let host_path = env!("CARGO_MANIFEST_DIR");
+        create_minikube_mount(&session, host_path, "/src").unwrap();
+        create_minikube_mount(&session, host_path, "/other").unwrap();
+        create_minikube_mount(&session, host_path, "/third").unwrap();
+
+        let result = delete_minikube_mount_by_index(&session, 0);
+        assert_eq!(
+            resource_fields(result, &["minikubePath", "state"]),
+            vec![vec!["/src", "deleted"]]
+        );
+
+        let result = delete_minikube_mount(&session, host_path, "/third");
+        assert_eq!(
+            resource_fields(result, &["minikubePath"]),
+            vec![vec!["/third"]]
+        );
+
+        assert_eq!(
+            resource_fields(fetch_minikube_mounts(&session), &["minikubePath"]),
+            vec![vec!["/other"]]
+        );
+
+        delete_all_minikube_mounts(&session).unwrap();
+        assert!(session.runner.running().is_empty());
+    }
 }
@@ -1,54 +1,54 @@
-use std::{thread, time::Duration};
-
-use sysinfo::{ProcessExt, SystemExt};
-
+use crate::process_log::log_path;
+use crate::registry::{
+    fetch_registered_processes, kill_registered_process, register_process, RegistryEntry,
+};
 use crate::{
-    get_sys_info, kill_process, merge_if_ok, parse_string, print_results, start_and_wait_process,
-    CommandExecutionResult, CommandResultType::*, OptionFunc,
+    is_minikube_profile_process, merge_if_ok, minikube_args, parse_string,
+    spawn_long_running_process, CommandExecutionResult, CommandResultType::*, Error, OptionFunc,
+    Resource, Session,
 };
 
-static mut BIND_ADDRESS: Option<String> = None;
+pub const MINIKUBE_TUNNEL_KIND: &str = "minikubeTunnel";
 
-pub fn create_minikube_tunnel() -> CommandExecutionResult {
-    if let Ok(true) = check_minikube_tunnel() {
-        toggle_minikube_tunnel(true)?;
-    }
+pub fn create_minikube_tunnel(session: &Session) -> CommandExecutionResult {
+    create_minikube_tunnel_with(session, &session.bind_address())
+}
 
-    toggle_minikube_tunnel(false)?;
+/// Starts the minikube tunnel bound to the given address, rather than to the session's bind
+/// address, eg, to restart a tunnel bound to the address it was bound to.
+pub fn create_minikube_tunnel_with(
+    session: &Session,
+    bind_address: &str,
+) -> CommandExecutionResult {
+    if let Ok(true) = check_minikube_tunnel(session) {
+        toggle_minikube_tunnel(session, true)?;
+    }
 
-    Ok(PrintableResults(
-        None,
-        vec![format!(
-            "The minikube tunnel has been started and bound to {}",
-            get_bind_address()
-        )],
-    ))
+    start_minikube_tunnel(session, bind_address)
 }
 
-pub fn stop_minikube_tunnel() -> CommandExecutionResult {
-    if let Ok(true) = check_minikube_tunnel() {
-        toggle_minikube_tunnel(true)
+pub fn stop_minikube_tunnel(session: &Session) -> CommandExecutionResult {
+    if let Ok(true) = check_minikube_tunnel(session) {
+        toggle_minikube_tunnel(session, true)
     } else {
         Ok(PrintableResults(None, Vec::new()))
     }
 }
 
-pub fn build_minikube_tunnel_option() -> Result<(String, OptionFunc, bool), String> {
-    match check_minikube_tunnel() {
-        Ok(running) => {
-            let next_state = if running { "Stop" } else { "Start" };
+pub fn build_minikube_tunnel_option(
+    session: &Session,
+) -> Result<(String, OptionFunc, bool), Error> {
+    let running = check_minikube_tunnel(session)?;
+    let next_state = if running { "Stop" } else { "Start" };
 
-            Ok((
-                format!("{next_state} minikube tunnel"),
-                Box::new(move || toggle_minikube_tunnel(running)),
-                false,
-            ))
-        }
-        Err(error) => Err(format!("Error in build_minikube_tunnel_option: {error}")),
-    }
+    Ok((
+        format!("{next_state} minikube tunnel"),
+        Box::new(move |session| toggle_minikube_tunnel(session, running)),
+        false,
+    ))
 }
 
-pub fn build_set_bind_address_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_set_bind_address_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Set the minikube tunnel bind address"),
         Box::new(set_bind_address_guided),
@@ -56,110 +56,136 @@ pub fn build_set_bind_address_option() -> Result<(String, OptionFunc, bool), Str
     ))
 }
 
-pub fn set_bind_address(bind_address: String) -> CommandExecutionResult {
-    if get_bind_address() == bind_address {
+pub fn set_bind_address(session: &Session, bind_address: String) -> CommandExecutionResult {
+    if session.bind_address() == bind_address {
         return Ok(PrintableResults(None, Vec::new()));
     }
 
-    unsafe {
-        BIND_ADDRESS.replace(bind_address);
-    }
+    session.set_bind_address(bind_address);
 
     let mut results: Vec<String> = Vec::new();
 
     results.push(format!(
         "The minikube tunnel bind address has been set to {}",
-        unsafe { BIND_ADDRESS.as_ref().unwrap() }
+        session.bind_address()
     ));
 
-    if let Ok(true) = check_minikube_tunnel() {
-        let _ = merge_if_ok(&mut results, create_minikube_tunnel);
+    if let Ok(true) = check_minikube_tunnel(session) {
+        let _ = merge_if_ok(&mut results, || create_minikube_tunnel(session));
     }
 
     Ok(PrintableResults(None, results))
 }
 
-fn check_minikube_tunnel() -> Result<bool, String> {
-    Ok(get_sys_info()
-        .processes_by_name("minikube")
-        .any(|x| x.cmd().join(" ").contains("tunnel")))
+pub fn check_minikube_tunnel(session: &Session) -> Result<bool, Error> {
+    Ok(!fetch_minikube_tunnel_processes(session)?.is_empty())
 }
 
-fn toggle_minikube_tunnel(running: bool) -> CommandExecutionResult {
+/// The minikube tunnels of the session's minikube profile, leaving alone those of other profiles.
+fn fetch_minikube_tunnel_processes(session: &Session) -> Result<Vec<RegistryEntry>, Error> {
+    Ok(fetch_registered_processes(session, MINIKUBE_TUNNEL_KIND)?
+        .into_iter()
+        .filter(|x| is_minikube_profile_process(session, x))
+        .collect())
+}
+
+fn toggle_minikube_tunnel(session: &Session, running: bool) -> CommandExecutionResult {
     if running {
-        if let error @ Err(_) = kill_process("minikube", vec!["tunnel"]) {
-            return error;
+        for entry in fetch_minikube_tunnel_processes(session)? {
+            kill_registered_process(session, &entry, sysinfo::Signal::Interrupt)?;
         }
 
-        clear_minikube_ssh_tunnels()?;
+        clear_minikube_ssh_tunnels(session)?;
 
-        Ok(PrintableResults(
+        Ok(Resources(
             None,
-            vec![String::from("The minikube tunnel has been stopped")],
+            vec![describe_minikube_tunnel(
+                session,
+                &session.bind_address(),
+                String::from("The minikube tunnel has been stopped"),
+                "stopped",
+            )],
         ))
     } else {
-        clear_minikube_ssh_tunnels()?;
-
-        thread::spawn(move || {
-            print_results(
-                start_and_wait_process(
-                    "minikube",
-                    &["tunnel", "-c", "--bind-address", &get_bind_address()],
-                    Some(String::from("Failed to start the minikube tunnel")),
-                ),
-                false,
-                true,
-            );
-        });
+        start_minikube_tunnel(session, &session.bind_address())
+    }
+}
 
-        {
-            let mut cnt = 0;
-
-            while !check_minikube_tunnel()? && cnt < 5 {
-                cnt += 1;
-                thread::sleep(Duration::from_secs(1));
-            }
-
-            if cnt == 5 {
-                return Err(String::from(
-                    "Failed to verify if minikube tunnel has been started",
-                ));
-            }
-        }
+fn start_minikube_tunnel(session: &Session, bind_address: &str) -> CommandExecutionResult {
+    clear_minikube_ssh_tunnels(session)?;
+
+    let args = minikube_args(session, &["tunnel", "-c", "--bind-address", bind_address]);
+    let profile = session.minikube_profile();
+
+    let pid = spawn_long_running_process(
+        session,
+        "minikube",
+        &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+        match &profile {
+            Some(profile) => log_path(session, &["minikube-tunnel", profile]),
+            None => log_path(session, &["minikube-tunnel"]),
+        },
+        Some(String::from("Failed to start the minikube tunnel")),
+    )?;
 
-        Ok(PrintableResults(
-            None,
-            vec![format!(
-                "The minikube tunnel has been started and bound to {}",
-                get_bind_address()
-            )],
-        ))
+    let mut spec = serde_json::Map::new();
+    spec.insert(String::from("bindAddress"), bind_address.into());
+    if let Some(profile) = profile {
+        spec.insert(String::from("profile"), profile.into());
     }
-}
 
-fn clear_minikube_ssh_tunnels() -> Result<(), String> {
-    let sys_info = get_sys_info();
-    let ssh_processes = sys_info.processes_by_name("ssh").filter(|x| {
-        let cmd = x.cmd().join(" ");
+    register_process(session, MINIKUBE_TUNNEL_KIND, pid, spec).map_err(|_| Error::Spawn {
+        command: String::from("minikube"),
+        message: String::from("Failed to verify if minikube tunnel has been started"),
+    })?;
+
+    Ok(Resources(
+        None,
+        vec![describe_minikube_tunnel(
+            session,
+            bind_address,
+            format!("The minikube tunnel has been started and bound to {bind_address}"),
+            "started",
+        )],
+    ))
+}
 
-        cmd.contains("docker@127.0.0.1")
-            && cmd.contains("minikube/id_rsa")
-            && cmd.contains("-L 127.0.0.1:")
-    });
+/// Kills the ssh processes that forward the ports of a minikube tunnel of the session's minikube
+/// profile, which are identified by the profile's machine key.
+fn clear_minikube_ssh_tunnels(session: &Session) -> Result<(), Error> {
+    let machine_key = format!(
+        "/machines/{}/id_rsa",
+        session
+            .minikube_profile()
+            .unwrap_or_else(|| String::from("minikube"))
+    );
+
+    let ssh_processes = session
+        .runner()
+        .find_processes("ssh")
+        .into_iter()
+        .filter(|x| {
+            x.command_line.contains("docker@127.0.0.1")
+                && x.command_line.contains(&machine_key)
+                && x.command_line.contains("-L 127.0.0.1:")
+        });
 
     for ssh_process in ssh_processes {
-        if !ssh_process.kill() {
-            return Err(format!(
+        if !session
+            .runner()
+            .kill(ssh_process.pid, sysinfo::Signal::Kill)
+        {
+            return Err(Error::Io(format!(
                 "Failed to kill minikube ssh tunnel with process id {pid}",
-                pid = ssh_process.pid(),
-            ));
+                pid = ssh_process.pid,
+            )));
         }
     }
 
     Ok(())
 }
 
-fn set_bind_address_guided() -> CommandExecutionResult {
+fn set_bind_address_guided(session: &Session) -> CommandExecutionResult {
     let bind_address = parse_string(
         "Bind address: ",
         None,
@@ -168,9 +194,108 @@ fn set_bind_address_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    set_bind_address(bind_address)
+    set_bind_address(session, bind_address)
+}
+
+fn describe_minikube_tunnel(
+    session: &Session,
+    bind_address: &str,
+    description: String,
+    state: &str,
+) -> Resource {
+    Resource::new("minikubeTunnel", description)
+        .field("bindAddress", bind_address)
+        .field("profile", session.minikube_profile())
+        .field("state", state)
 }
 
-fn get_bind_address() -> String {
-    String::from(unsafe { BIND_ADDRESS.as_ref().unwrap_or(&String::from("127.0.0.1")) })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{resource_fields, TestSession};
+
+    #[test]
+    fn create_minikube_tunnel_replaces_stale_ssh_tunnels() {
+        let session = TestSession::new();
+        session.runner.add_process(
+            "ssh",
+            "ssh -o UserKnownHostsFile=/dev/null -N docker@127.0.0.1 -p 32771 \
+            -i /home/user/.minikube/machines/minikube/id_rsa -L 127.0.0.1:80:10.0.0.1:80",
+        );
+        session.runner.add_process("ssh", "ssh user@example.com");
+        session.set_bind_address(String::from("0.0.0.0"));
+
+        let result = create_minikube_tunnel(&session);
+
+        assert_eq!(
+            resource_fields(result, &["bindAddress", "state"]),
+            vec![vec!["0.0.0.0", "started"]]
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                "minikube tunnel -c --bind-address 0.0.0.0",
+                "ssh user@example.com",
+            ]
+        );
+        assert_eq!(check_minikube_tunnel(&session), Ok(true));
+    }
+
+    #[test]
+    fn set_bind_address_restarts_a_running_tunnel() {
+        let session = TestSession::new();
+        create_minikube_tunnel(&session).unwrap();
+
+        set_bind_address(&session, String::from("0.0.0.0")).unwrap();
+
+        assert_eq!(
+            session.runner.running(),
+            vec!["minikube tunnel -c --bind-address 0.0.0.0"]
+        );
+    }
+
+    #[test]
+    fn minikube_tunnels_of_other_profiles_are_left_alone() {
+        let session = TestSession::new();
+        session.set_minikube_profile(Some(String::from("stable")));
+        create_minikube_tunnel(&session).unwrap();
+        session.runner.add_process(
+            "ssh",
+            "ssh -N docker@127.0.0.1 -p 32771 \
+            -i /home/user/.minikube/machines/stable/id_rsa -L 127.0.0.1:80:10.0.0.1:80",
+        );
+
+        session.set_minikube_profile(Some(String::from("experimental")));
+        assert_eq!(check_minikube_tunnel(&session), Ok(false));
+
+        let result = create_minikube_tunnel(&session);
+        assert_eq!(
+            resource_fields(result, &["profile", "state"]),
+            vec![vec!["experimental", "started"]]
+        );
+
+        stop_minikube_tunnel(&session).unwrap();
+
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                "minikube -p stable tunnel -c --bind-address 127.0.0.1",
+                "ssh -N docker@127.0.0.1 -p 32771 \
+                -i /home/user/.minikube/machines/stable/id_rsa -L 127.0.0.1:80:10.0.0.1:80",
+            ]
+        );
+        session.set_minikube_profile(Some(String::from("stable")));
+        assert_eq!(check_minikube_tunnel(&session), Ok(true));
+    }
+
+    #[test]
+    fn stop_minikube_tunnel_kills_the_tunnel() {
+        let session = TestSession::new();
+        create_minikube_tunnel(&session).unwrap();
+
+        stop_minikube_tunnel(&session).unwrap();
+
+        assert!(session.runner.running().is_empty());
+        assert_eq!(check_minikube_tunnel(&session), Ok(false));
+    }
 }
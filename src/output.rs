@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use serde_json::{json, Map, Value};
+
+use crate::{process_exited_with_success, CommandExecutionResult, CommandResultType::*, Session};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            output_format => Err(format!(
+                "Unsupported output format {output_format} (expected text or json)"
+            )),
+        }
+    }
+}
+
+/// A resource managed by `kube-minion`, as returned by the operations that create, list or delete
+/// resources.
+/// The description is what gets printed in text mode, whereas the kind and the fields make up the
+/// resource's document in JSON mode.
+pub struct Resource {
+    pub kind: &'static str,
+    pub fields: Map<String, Value>,
+    pub description: String,
+}
+
+impl Resource {
+    pub fn new(kind: &'static str, description: String) -> Self {
+        Resource {
+            kind,
+            fields: Map::new(),
+            description,
+        }
+    }
+
+    pub fn field(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.fields.insert(String::from(key), value.into());
+
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut document = Map::new();
+
+        document.insert(String::from("kind"), Value::from(self.kind));
+        document.extend(self.fields.clone());
+
+        Value::Object(document)
+    }
+}
+
+/// Prints an informational message which is not part of an operation's results.
+/// In JSON mode, such messages are printed to stderr so that stdout only contains JSON documents.
+pub fn print_message(session: &Session, message: &str) {
+    match session.output_format() {
+        OutputFormat::Text => println!("{message}"),
+        OutputFormat::Json => eprintln!("{message}"),
+    }
+}
+
+/// Renders the result of an operation as a single line JSON document.
+pub fn render_json(result: CommandExecutionResult) -> String {
+    let document = match result {
+        Ok(ChildProcess(_)) => {
+            let (success, stdout, stderr) = process_exited_with_success(result);
+
+            json!({
+                "status": if success { "ok" } else { "error" },
+                "stdout": stdout.map(|x| x.trim().to_string()),
+                "stderr": stderr.map(|x| x.trim().to_string()),
+            })
+        }
+        Ok(PrintableResults(title, messages)) => json!({
+            "status": "ok",
+            "title": title,
+            "messages": messages,
+        }),
+        Ok(Resources(title, resources)) => json!({
+            "status": "ok",
+            "title": title,
+            "resources": resources.iter().map(Resource::to_json).collect::<Vec<Value>>(),
+        }),
+        Err(error) => json!({
+            "status": "error",
+            "error": error.to_string(),
+            "errorKind": error.kind(),
+        }),
+    };
+
+    document.to_string()
+}
@@ -0,0 +1,486 @@
+use std::env;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::health::{probe, Health, HealthCheck};
+use crate::process_log::log_path;
+use crate::registry::{
+    fetch_registered_processes, kill_registered_process, register_process, RegistryEntry,
+};
+use crate::subcommand::parse_index;
+use crate::{
+    kubectl_args, parse_num, parse_string, spawn_long_running_process, CommandExecutionResult,
+    CommandResultType::*, Error, OptionFunc, Resource, Session,
+};
+
+pub const PORT_FORWARD_KIND: &str = "portForward";
+
+/// How long `kubectl port-forward` has to keep running for a reconnecting port forward to be
+/// considered started, which matches how long a spawned process is given to fail on startup.
+const STARTUP_PERIOD: Duration = Duration::from_secs(1);
+
+/// How long a reconnecting port forward waits before running `kubectl port-forward` again, eg,
+/// while the pods that replace the one it forwarded to are not ready yet.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How often a reconnecting port forward checks whether `kubectl port-forward` has exited or it
+/// has been asked to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn build_create_port_forward_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((
+        String::from("Create port forward"),
+        Box::new(create_port_forward_guided),
+        false,
+    ))
+}
+
+pub fn build_fetch_port_forwards_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((
+        String::from("List port forwards"),
+        Box::new(fetch_port_forwards),
+        false,
+    ))
+}
+
+pub fn build_delete_port_forward_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((
+        String::from("Delete port forward"),
+        Box::new(delete_port_forward_guided),
+        false,
+    ))
+}
+
+pub fn build_delete_all_port_forwards_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((
+        String::from("Delete all port forwards"),
+        Box::new(delete_all_port_forwards),
+        false,
+    ))
+}
+
+/// Starts `kubectl port-forward` from a local port to a port of a pod, or of a pod picked by
+/// kubectl for a service or a deployment.
+/// When the pod goes away, kubectl exits and the supervisor starts it again, which picks one of
+/// the pods that have replaced it.
+/// A detached port forward may outlive the supervisor, so it is run by a `kube-minion reconnect`
+/// process of its own instead, which runs kubectl again whenever it exits.
+pub fn create_port_forward(
+    session: &Session,
+    namespace: &str,
+    resource_type: &str,
+    name: &str,
+    local_port: u16,
+    remote_port: u16,
+) -> CommandExecutionResult {
+    if let Some(entry) = check_port_forward(session, namespace, resource_type, name, local_port)? {
+        return Ok(Resources(
+            None,
+            vec![describe_port_forward(
+                &entry,
+                format!(
+                    "Port forward from local port {local_port} to {namespace}/{resource_type}/{name} \
+                    already exists"
+                ),
+                "running",
+            )],
+        ));
+    }
+
+    let args = kubectl_args(
+        session,
+        &[
+            "-n",
+            namespace,
+            "port-forward",
+            &format!("{resource_type}/{name}"),
+            &format!("{local_port}:{remote_port}"),
+        ],
+    );
+
+    let (command, args) = if session.detach_child_processes() {
+        (
+            env::current_exe()?.to_string_lossy().to_string(),
+            [vec![String::from("reconnect")], args].concat(),
+        )
+    } else {
+        (String::from("kubectl"), args)
+    };
+
+    let pid = spawn_long_running_process(
+        session,
+        &command,
+        &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+        log_path(
+            session,
+            &[
+                "port-forward",
+                namespace,
+                resource_type,
+                name,
+                &local_port.to_string(),
+                &remote_port.to_string(),
+            ],
+        ),
+        Some(format!(
+            "Failed to start port forward from local port {local_port} to \
+            {namespace}/{resource_type}/{name}:{remote_port}"
+        )),
+    )?;
+
+    let mut spec = serde_json::Map::new();
+    spec.insert(String::from("namespace"), namespace.into());
+    spec.insert(String::from("resourceType"), resource_type.into());
+    spec.insert(String::from("name"), name.into());
+    spec.insert(String::from("localPort"), local_port.into());
+    spec.insert(String::from("remotePort"), remote_port.into());
+
+    let entry =
+        register_process(session, PORT_FORWARD_KIND, pid, spec).map_err(|_| Error::Spawn {
+            command,
+            message: String::from("Failed to verify if the port forward has been started"),
+        })?;
+
+    Ok(Resources(
+        None,
+        vec![describe_port_forward(
+            &entry,
+            format!(
+                "Started port forward from local port {local_port} to \
+                {namespace}/{resource_type}/{name}:{remote_port}"
+            ),
+            "started",
+        )],
+    ))
+}
+
+/// Runs `kubectl port-forward` with the given arguments, and again whenever it exits, until
+/// SIGINT or SIGTERM is received, which is how a detached port forward reconnects when its pod is
+/// replaced.
+/// Only returns early when kubectl exits right after being started for the first time, eg,
+/// because the resource to forward to does not exist, so that the port forward fails to start.
+pub fn run_reconnecting_port_forward(args: &[String]) -> Result<(), Error> {
+    let stopped = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, stopped.clone())?;
+    }
+
+    let mut started = false;
+
+    while !stopped.load(Ordering::SeqCst) {
+        let start_time = Instant::now();
+        let mut child = Command::new("kubectl").args(args).spawn()?;
+
+        let status = loop {
+            if stopped.load(Ordering::SeqCst) {
+                let _ = child.kill();
+                child.wait()?;
+
+                return Ok(());
+            }
+
+            match child.try_wait()? {
+                Some(status) => break status,
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        };
+
+        if !started && start_time.elapsed() < STARTUP_PERIOD {
+            return Err(Error::Spawn {
+                command: String::from("kubectl"),
+                message: format!(
+                    "kubectl port-forward exited right after being started ({status})"
+                ),
+            });
+        }
+        started = true;
+
+        eprintln!("kubectl port-forward exited ({status}), reconnecting");
+        thread::sleep(RECONNECT_DELAY);
+    }
+
+    Ok(())
+}
+
+pub fn delete_port_forward(
+    session: &Session,
+    namespace: &str,
+    resource_type: &str,
+    name: &str,
+    local_port: u16,
+) -> CommandExecutionResult {
+    let results = check_port_forward(session, namespace, resource_type, name, local_port)?
+        .iter()
+        .map(|x| delete_port_forward_entry(session, x))
+        .collect();
+
+    Ok(Resources(None, results))
+}
+
+pub fn delete_all_port_forwards(session: &Session) -> CommandExecutionResult {
+    let results = fetch_registered_processes(session, PORT_FORWARD_KIND)?
+        .iter()
+        .map(|x| delete_port_forward_entry(session, x))
+        .collect();
+
+    Ok(Resources(None, results))
+}
+
+pub fn fetch_port_forwards(session: &Session) -> CommandExecutionResult {
+    let port_forwards: Vec<Resource> = fetch_registered_processes(session, PORT_FORWARD_KIND)?
+        .iter()
+        .map(|x| {
+            probe_port_forward(x).annotate(describe_port_forward(
+                x,
+                format!(
+                    "Local port {} forwarded to {}/{}/{}:{} (pid {})",
+                    x.spec_u16("localPort"),
+                    x.spec_str("namespace"),
+                    x.spec_str("resourceType"),
+                    x.spec_str("name"),
+                    x.spec_u16("remotePort"),
+                    x.pid,
+                ),
+                "running",
+            ))
+        })
+        .collect();
+
+    let title = if port_forwards.is_empty() {
+        None
+    } else {
+        Some(String::from("Port forwards:"))
+    };
+
+    Ok(Resources(title, port_forwards))
+}
+
+pub fn delete_port_forward_by_index(session: &Session, index: usize) -> CommandExecutionResult {
+    let port_forwards = fetch_registered_processes(session, PORT_FORWARD_KIND)?;
+
+    if index >= port_forwards.len() {
+        return Err(Error::NotFound(format!(
+            "Index {index} does not correspond to a port forward"
+        )));
+    }
+
+    Ok(Resources(
+        None,
+        vec![delete_port_forward_entry(session, &port_forwards[index])],
+    ))
+}
+
+fn create_port_forward_guided(session: &Session) -> CommandExecutionResult {
+    let namespace = parse_string(
+        "Namespace (leave empty for default namespace): ",
+        Some(String::from("default")),
+        None,
+    )?;
+
+    let resource_type = parse_string(
+        "Resource type (either pod, svc or deployment / leave empty for svc): ",
+        Some(String::from("svc")),
+        None,
+    )?;
+
+    let name = parse_string(
+        "Name: ",
+        None,
+        Some(format!("The name of the {resource_type} is required")),
+    )?;
+
+    let local_port: u16 = parse_num(
+        "Local port: ",
+        None,
+        Some(format!(
+            "A local port is required to create a port forward to {resource_type}/{name}"
+        )),
+    )?;
+
+    let remote_port: u16 = parse_num(
+        "Remote port (leave empty to use the same as the local port): ",
+        Some(local_port),
+        None,
+    )?;
+
+    create_port_forward(
+        session,
+        &namespace,
+        &resource_type,
+        &name,
+        local_port,
+        remote_port,
+    )
+}
+
+fn delete_port_forward_guided(session: &Session) -> CommandExecutionResult {
+    let index: usize = parse_num(
+        "Index: ",
+        None,
+        Some(String::from(
+            "An index is required to delete a port forward",
+        )),
+    )?;
+
+    delete_port_forward_by_index(session, parse_index(index)?)
+}
+
+fn check_port_forward(
+    session: &Session,
+    namespace: &str,
+    resource_type: &str,
+    name: &str,
+    local_port: u16,
+) -> Result<Option<RegistryEntry>, Error> {
+    Ok(fetch_registered_processes(session, PORT_FORWARD_KIND)?
+        .into_iter()
+        .find(|x| {
+            x.spec_str("namespace") == namespace
+                && x.spec_str("resourceType") == resource_type
+                && x.spec_str("name") == name
+                && x.spec_u16("localPort") == local_port
+        }))
+}
+
+pub fn delete_port_forward_entry(session: &Session, entry: &RegistryEntry) -> Resource {
+    let local_port = entry.spec_u16("localPort");
+    let target = format!(
+        "{}/{}/{}",
+        entry.spec_str("namespace"),
+        entry.spec_str("resourceType"),
+        entry.spec_str("name")
+    );
+
+    match kill_registered_process(session, entry, sysinfo::Signal::Interrupt) {
+        Ok(_) => describe_port_forward(
+            entry,
+            format!("Stopped port forward from local port {local_port} to {target}"),
+            "deleted",
+        ),
+        Err(error) => describe_port_forward(
+            entry,
+            format!(
+                "Failed to stop port forward from local port {local_port} to {target}: {error}"
+            ),
+            "running",
+        ),
+    }
+}
+
+/// Probes the local port of a port forward, which kubectl binds on the loopback interface.
+fn probe_port_forward(entry: &RegistryEntry) -> Health {
+    probe("127.0.0.1", entry.spec_u16("localPort"), &HealthCheck::Tcp)
+}
+
+fn describe_port_forward(entry: &RegistryEntry, description: String, state: &str) -> Resource {
+    Resource::new("portForward", description)
+        .field("namespace", entry.spec_str("namespace"))
+        .field("resourceType", entry.spec_str("resourceType"))
+        .field("name", entry.spec_str("name"))
+        .field("localPort", entry.spec_u16("localPort"))
+        .field("remotePort", entry.spec_u16("remotePort"))
+        .field("pid", entry.pid)
+        .field("state", state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{resource_fields, TestSession};
+    use crate::Supervisor;
+
+    #[test]
+    fn create_port_forward_starts_and_registers_kubectl() {
+        let session = TestSession::new();
+        session.set_kube_context(Some(String::from("minikube")));
+
+        let result = create_port_forward(&session, "apps", "svc", "web", 8080, 80);
+
+        assert_eq!(
+            resource_fields(result, &["namespace", "name", "localPort", "state"]),
+            vec![vec!["apps", "web", "8080", "started"]]
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec!["kubectl --context minikube -n apps port-forward svc/web 8080:80"]
+        );
+    }
+
+    #[test]
+    fn detached_port_forwards_are_run_by_a_reconnecting_process() {
+        let session = TestSession::new();
+        session.set_detach_child_processes(true);
+
+        create_port_forward(&session, "default", "svc", "web", 8080, 80).unwrap();
+
+        assert_eq!(
+            session.runner.running(),
+            vec![format!(
+                "{} reconnect -n default port-forward svc/web 8080:80",
+                env::current_exe().unwrap().to_string_lossy()
+            )]
+        );
+    }
+
+    #[test]
+    fn create_port_forward_does_not_duplicate_an_existing_port_forward() {
+        let session = TestSession::new();
+        create_port_forward(&session, "default", "svc", "web", 8080, 80).unwrap();
+
+        let result = create_port_forward(&session, "default", "svc", "web", 8080, 80);
+
+        assert_eq!(resource_fields(result, &["state"]), vec![vec!["running"]]);
+        assert_eq!(session.runner.running().len(), 1);
+    }
+
+    #[test]
+    fn delete_port_forwards() {
+        let session = TestSession::new();
+        create_port_forward(&session, "default", "svc", "web", 8080, 80).unwrap();
+        create_port_forward(&session, "default", "pod", "db-0", 5432, 5432).unwrap();
+        create_port_forward(&session, "default", "deployment", "api", 9090, 80).unwrap();
+
+        let result = delete_port_forward_by_index(&session, 0);
+        assert_eq!(
+            resource_fields(result, &["name", "state"]),
+            vec![vec!["web", "deleted"]]
+        );
+
+        let result = delete_port_forward(&session, "default", "deployment", "api", 9090);
+        assert_eq!(resource_fields(result, &["name"]), vec![vec!["api"]]);
+
+        assert_eq!(
+            resource_fields(fetch_port_forwards(&session), &["name"]),
+            vec![vec!["db-0"]]
+        );
+
+        delete_all_port_forwards(&session).unwrap();
+        assert!(session.runner.running().is_empty());
+    }
+
+    #[test]
+    fn port_forwards_are_restarted_when_their_pod_goes_away() {
+        let session = TestSession::new();
+        create_port_forward(&session, "default", "svc", "web", 8080, 80).unwrap();
+
+        // kubectl exits once it loses the connection to the pod it forwards to.
+        let entry = fetch_registered_processes(&session, PORT_FORWARD_KIND).unwrap()[0].clone();
+        session.runner().kill(entry.pid, sysinfo::Signal::Kill);
+
+        Supervisor::new().check(&session);
+
+        assert_eq!(
+            session.runner.running(),
+            vec!["kubectl -n default port-forward svc/web 8080:80"]
+        );
+        assert_eq!(
+            resource_fields(fetch_port_forwards(&session), &["name"]),
+            vec![vec!["web"]]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::subcommand::parse_index;
+use crate::{
+    parse_num, CommandExecutionResult, CommandResultType::*, Error, OptionFunc, Resource, Session,
+};
+
+/// The size beyond which a log file is rotated.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// How many rotated log files are kept next to the current one, eg, `socat.log.1` and
+/// `socat.log.2`.
+const ROTATED_LOG_FILES: usize = 2;
+
+pub fn build_fetch_logs_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((String::from("List logs"), Box::new(fetch_logs), false))
+}
+
+pub fn build_show_log_option() -> Result<(String, OptionFunc, bool), Error> {
+    Ok((String::from("Show log"), Box::new(show_log_guided), false))
+}
+
+/// A log file that the output of a long-running child process is appended to, which is rotated
+/// once it grows beyond [`MAX_LOG_SIZE`].
+/// A detached child process writes to the file directly, bypassing the rotation, so its log file
+/// is only rotated when the process is started, or restarted, and can grow beyond
+/// [`MAX_LOG_SIZE`] in the meantime.
+pub struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingLog {
+    /// Opens a log file for appending, creating its directory if needed, and rotates it if it has
+    /// already grown too large.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        let mut log = RotatingLog {
+            path: path.to_path_buf(),
+            file,
+            size,
+        };
+
+        if log.size >= MAX_LOG_SIZE {
+            log.rotate()?;
+        }
+
+        Ok(log)
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+
+        if self.size > 0 && self.size + len > MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+
+        Ok(())
+    }
+
+    /// Writes a line on behalf of `kube-minion`, as opposed to the child process, prefixed with the
+    /// current time.
+    pub fn write_event(&mut self, event: &str) -> io::Result<()> {
+        self.write_line(&format!("{} kube-minion: {event}", timestamp()))
+    }
+
+    /// Returns a handle to the log file, to which a child process can write directly.
+    pub fn file(&self) -> io::Result<File> {
+        self.file.try_clone()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..ROTATED_LOG_FILES).rev() {
+            let rotated = rotated_log_path(&self.path, i);
+
+            if rotated.exists() {
+                fs::rename(rotated, rotated_log_path(&self.path, i + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, rotated_log_path(&self.path, 1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+/// The path of the log file of a resource, eg, `socat-tunnel-tcp-8080-localhost-80.log`.
+/// Resources with the same specification share a log file, so that the log of a resource that is
+/// restarted continues where it left off.
+pub fn log_path(session: &Session, parts: &[&str]) -> PathBuf {
+    get_logs_dir(session).join(format!("{}.log", file_name(parts)))
+}
+
+/// Joins the parts that identify a resource into a file name, replacing the characters that are
+/// not safe in one.
+pub fn file_name(parts: &[&str]) -> String {
+    parts
+        .join("-")
+        .chars()
+        .map(|x| {
+            if x.is_ascii_alphanumeric() || x == '-' || x == '.' {
+                x
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn fetch_logs(session: &Session) -> CommandExecutionResult {
+    let logs: Vec<Resource> = list_log_paths(session)?
+        .iter()
+        .map(|x| {
+            let name = x
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let size = log_size(x);
+
+            Resource::new("log", format!("{name} ({size} bytes)"))
+                .field("name", name)
+                .field("path", x.to_string_lossy())
+                .field("size", size)
+        })
+        .collect();
+
+    let title = if logs.is_empty() {
+        None
+    } else {
+        Some(String::from("Logs:"))
+    };
+
+    Ok(Resources(title, logs))
+}
+
+/// Returns the last `lines` lines of a listed log, or all of it, including its rotated files, when
+/// no number of lines is given.
+pub fn show_log(session: &Session, index: usize, lines: Option<usize>) -> CommandExecutionResult {
+    let log_paths = list_log_paths(session)?;
+
+    let Some(log_path) = log_paths.get(index) else {
+        return Err(Error::NotFound(format!(
+            "Index {index} does not correspond to a log"
+        )));
+    };
+
+    let mut log_lines: Vec<String> = Vec::new();
+
+    for i in (0..=ROTATED_LOG_FILES).rev() {
+        let path = match i {
+            0 => log_path.clone(),
+            i => rotated_log_path(log_path, i),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => log_lines.extend(contents.lines().map(String::from)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    if let Some(lines) = lines {
+        log_lines.drain(..log_lines.len().saturating_sub(lines));
+    }
+
+    Ok(PrintableResults(None, log_lines))
+}
+
+/// The current UTC time, eg, `2023-05-01T12:30:00Z`.
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+
+    // Converts the days since the epoch to a civil date, as per Howard Hinnant's `civil_from_days`.
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses a UTC timestamp as formatted by [`timestamp`], or by Kubernetes, into seconds since the
+/// epoch.
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|x| x.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|x| x.parse().ok())
+        .collect::<Option<_>>()?;
+    let (&[year, month, day], &[hours, minutes, seconds]) = (&date[..], &time[..]) else {
+        return None;
+    };
+
+    // Converts a civil date to days since the epoch, as per Howard Hinnant's `days_from_civil`.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146097 + day_of_era - 719468).ok()?;
+
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+fn show_log_guided(session: &Session) -> CommandExecutionResult {
+    let index: usize = parse_num(
+        "Index: ",
+        None,
+        Some(String::from("An index is required to show a log")),
+    )?;
+
+    let lines: usize = parse_num(
+        "Number of lines (leave empty for the whole log): ",
+        Some(0),
+        None,
+    )?;
+
+    show_log(
+        session,
+        parse_index(index)?,
+        if lines == 0 { None } else { Some(lines) },
+    )
+}
+
+fn get_logs_dir(session: &Session) -> PathBuf {
+    session.state_dir().join("logs")
+}
+
+/// The current log files, sorted by name, without their rotated files.
+fn list_log_paths(session: &Session) -> Result<Vec<PathBuf>, Error> {
+    let mut log_paths: Vec<PathBuf> = match fs::read_dir(get_logs_dir(session)) {
+        Ok(entries) => entries
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .filter(|x| x.extension().is_some_and(|x| x == "log"))
+            .collect(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(error.into()),
+    };
+
+    log_paths.sort();
+
+    Ok(log_paths)
+}
+
+fn rotated_log_path(path: &Path, i: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{i}", path.display()))
+}
+
+fn log_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|x| x.len()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthCheck;
+    use crate::socat_tunnel::{create_socat_tunnel, TunnelEngine};
+    use crate::test_support::{resource_fields, TestSession};
+
+    #[test]
+    fn long_running_processes_log_to_a_file_per_resource() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8081,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resource_fields(fetch_logs(&session), &["name"]),
+            vec![
+                vec!["socat-tunnel-tcp-8080-localhost-80.log"],
+                vec!["socat-tunnel-tcp-8081-localhost-80.log"],
+            ]
+        );
+
+        let Ok(PrintableResults(None, lines)) = show_log(&session, 1, None) else {
+            panic!("The log could not be shown");
+        };
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with(
+            "kube-minion: starting socat -lpkube-minion-socat \
+            tcp-listen:8081,fork,reuseaddr tcp:localhost:80"
+        ));
+    }
+
+    #[test]
+    fn show_log_includes_rotated_files_and_can_tail() {
+        let session = TestSession::new();
+        let path = log_path(&session, &["test"]);
+        let mut log = RotatingLog::open(&path).unwrap();
+        let line = "x".repeat(1023);
+
+        for _ in 0..1024 {
+            log.write_line(&line).unwrap();
+        }
+        log.write_line("last").unwrap();
+
+        assert!(rotated_log_path(&path, 1).exists());
+        assert_eq!(log_size(&path), 5);
+
+        let Ok(PrintableResults(_, lines)) = show_log(&session, 0, None) else {
+            panic!("The log could not be shown");
+        };
+        assert_eq!(lines.len(), 1025);
+
+        let Ok(PrintableResults(_, lines)) = show_log(&session, 0, Some(2)) else {
+            panic!("The log could not be shown");
+        };
+        assert_eq!(lines, vec![line, String::from("last")]);
+    }
+
+    #[test]
+    fn the_log_of_a_detached_process_is_rotated_when_it_is_started() {
+        let session = TestSession::new();
+        session.set_detach_child_processes(true);
+        let path = log_path(
+            &session,
+            &["socat-tunnel", "tcp", "8080", "localhost", "80"],
+        );
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "x".repeat(MAX_LOG_SIZE as usize)).unwrap();
+
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        assert_eq!(log_size(&rotated_log_path(&path, 1)), MAX_LOG_SIZE);
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("kube-minion: starting socat"));
+    }
+
+    #[test]
+    fn parse_timestamp_is_the_inverse_of_timestamp() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        assert!(parse_timestamp(&timestamp()).is_some_and(|x| x.abs_diff(now) <= 1));
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29T12:30:05Z"), Some(1709209805));
+        assert_eq!(parse_timestamp("2024-02-29"), None);
+    }
+
+    #[test]
+    fn show_log_fails_for_an_unknown_index() {
+        let session = TestSession::new();
+
+        assert!(matches!(
+            show_log(&session, 0, None),
+            Err(Error::NotFound(_))
+        ));
+    }
+}
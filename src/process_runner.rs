@@ -0,0 +1,565 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::{thread, time::Duration};
+
+use sysinfo::{
+    Pid, PidExt, ProcessExt, ProcessRefreshKind, ProcessStatus, RefreshKind, Signal, System,
+    SystemExt,
+};
+
+use crate::RotatingLog;
+
+/// How many of the last lines of a child process' stderr are passed on when it exits.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// The captured output of a command that has run to completion.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcessOutput {
+    /// The exit code, which is `None` when the process has been terminated by a signal.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ProcessOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// A running process, as found by [`ProcessRunner::find_processes`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub command_line: String,
+}
+
+pub type OnExit = Box<dyn FnOnce(io::Result<ProcessOutput>) + Send>;
+
+/// Runs external commands and looks up processes on behalf of `kube-minion`, so that its operations
+/// can be exercised against a [`FakeProcessRunner`] instead of real `kubectl`, `minikube` and
+/// `socat` processes.
+pub trait ProcessRunner: Send + Sync {
+    /// Runs a command to completion and captures its output.
+    fn run(&self, command: &str, args: &[&str]) -> io::Result<ProcessOutput>;
+
+    /// Starts a command which keeps running until it is killed and returns its process id.
+    /// The command's stdout and stderr are written to `log` and `on_exit` is called with its exit
+    /// code and the last lines of its stderr when it exits.
+    /// A detached command does not share any standard stream or signal with `kube-minion`.
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+        detach: bool,
+        log: RotatingLog,
+        on_exit: OnExit,
+    ) -> io::Result<u32>;
+
+    /// Returns the start time of a running process, or `None` if no such process is running.
+    fn process_start_time(&self, pid: u32) -> Option<u64>;
+
+    /// Sends a signal to a process and returns whether it has been delivered.
+    fn kill(&self, pid: u32, signal: Signal) -> bool;
+
+    /// Returns the running processes with the given name.
+    fn find_processes(&self, name: &str) -> Vec<ProcessInfo>;
+}
+
+/// Runs commands as actual child processes.
+#[derive(Default)]
+pub struct SystemProcessRunner;
+
+impl ProcessRunner for SystemProcessRunner {
+    fn run(&self, command: &str, args: &[&str]) -> io::Result<ProcessOutput> {
+        let output = Command::new(command).args(args).output()?;
+
+        Ok(ProcessOutput {
+            code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    /// Returns one second after starting the command, so that a command which fails on startup
+    /// (eg, because its listening port is already in use) has exited by the time the caller checks
+    /// on it.
+    /// A detached command writes to its log file directly, since it may outlive `kube-minion`, and
+    /// runs in a process group of its own, so that it does not receive the signals sent to
+    /// `kube-minion`'s terminal, eg, SIGINT or SIGHUP, whereas the output of any other command is
+    /// piped through `kube-minion`, so that its log can be rotated while it runs.
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+        detach: bool,
+        log: RotatingLog,
+        on_exit: OnExit,
+    ) -> io::Result<u32> {
+        let (stdout, stderr) = if detach {
+            (Stdio::from(log.file()?), Stdio::from(log.file()?))
+        } else {
+            (Stdio::piped(), Stdio::piped())
+        };
+
+        let mut command = Command::new(command);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr);
+
+        if detach {
+            command.process_group(0);
+        }
+
+        let mut child = command.spawn()?;
+
+        let pid = child.id();
+        let log = Arc::new(Mutex::new(log));
+
+        let stdout_reader = child.stdout.take().map(|x| {
+            let log = log.clone();
+
+            thread::spawn(move || log_lines(x, &log, 0))
+        });
+        let stderr_reader = child.stderr.take().map(|x| {
+            let log = log.clone();
+
+            thread::spawn(move || log_lines(x, &log, STDERR_TAIL_LINES))
+        });
+
+        thread::spawn(move || {
+            let status = child.wait();
+
+            if let Some(stdout_reader) = stdout_reader {
+                let _ = stdout_reader.join();
+            }
+            let stderr = stderr_reader
+                .and_then(|x| x.join().ok())
+                .unwrap_or_default();
+
+            on_exit(status.map(|status| {
+                let _ = log
+                    .lock()
+                    .unwrap()
+                    .write_event(&format!("exited ({status})"));
+
+                ProcessOutput {
+                    code: status.code(),
+                    stdout: String::new(),
+                    stderr,
+                }
+            }))
+        });
+
+        thread::sleep(Duration::from_secs(1));
+
+        Ok(pid)
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let mut sys_info = System::new();
+        let pid = Pid::from_u32(pid);
+
+        if !sys_info.refresh_process_specifics(pid, ProcessRefreshKind::new()) {
+            return None;
+        }
+
+        sys_info
+            .process(pid)
+            .filter(|x| x.status() != ProcessStatus::Zombie)
+            .map(|x| x.start_time())
+    }
+
+    fn kill(&self, pid: u32, signal: Signal) -> bool {
+        let mut sys_info = System::new();
+        let pid = Pid::from_u32(pid);
+        sys_info.refresh_process_specifics(pid, ProcessRefreshKind::new());
+
+        match sys_info.process(pid) {
+            Some(process) => process.kill_with(signal).unwrap_or_else(|| process.kill()),
+            None => true,
+        }
+    }
+
+    fn find_processes(&self, name: &str) -> Vec<ProcessInfo> {
+        let sys_info = System::new_with_specifics(
+            RefreshKind::new().with_processes(ProcessRefreshKind::everything()),
+        );
+
+        sys_info
+            .processes_by_name(name)
+            .map(|x| ProcessInfo {
+                pid: x.pid().as_u32(),
+                command_line: x.cmd().join(" "),
+            })
+            .collect()
+    }
+}
+
+/// A [`ProcessRunner`] which only runs the commands that read state, eg, `kubectl get`, so that a
+/// dry run finds the resources that an operation would affect, and prints the command lines of all
+/// other commands, and the processes it would kill, instead of running or killing them.
+/// The processes it pretends to have spawned are kept track of, so that they can be registered,
+/// whereas the processes it pretends to have killed are reported as no longer running.
+pub struct DryRunProcessRunner {
+    runner: Arc<dyn ProcessRunner>,
+    state: Mutex<DryRunState>,
+}
+
+#[derive(Default)]
+struct DryRunState {
+    actions: Vec<String>,
+    spawned: HashSet<u32>,
+    killed: HashSet<u32>,
+    last_pid: u32,
+}
+
+impl DryRunProcessRunner {
+    pub fn new(runner: Arc<dyn ProcessRunner>) -> Self {
+        DryRunProcessRunner {
+            runner,
+            state: Mutex::new(DryRunState {
+                // Above the highest process id that Linux assigns, so that no running process is
+                // mistaken for one that has only been pretended to be spawned.
+                last_pid: u32::MAX,
+                ..DryRunState::default()
+            }),
+        }
+    }
+
+    /// The command lines which would have been run, in order.
+    pub fn actions(&self) -> Vec<String> {
+        self.state.lock().unwrap().actions.clone()
+    }
+
+    fn record(&self, action: String) {
+        eprintln!("Dry run: {action}");
+
+        self.state.lock().unwrap().actions.push(action);
+    }
+}
+
+impl ProcessRunner for DryRunProcessRunner {
+    fn run(&self, command: &str, args: &[&str]) -> io::Result<ProcessOutput> {
+        if is_read_only(command, args) {
+            return self.runner.run(command, args);
+        }
+
+        self.record(format!("{command} {}", args.join(" ")));
+
+        Ok(ProcessOutput {
+            code: Some(0),
+            ..ProcessOutput::default()
+        })
+    }
+
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+        _detach: bool,
+        _log: RotatingLog,
+        _on_exit: OnExit,
+    ) -> io::Result<u32> {
+        self.record(format!("{command} {}", args.join(" ")));
+
+        let mut state = self.state.lock().unwrap();
+
+        state.last_pid -= 1;
+        let pid = state.last_pid;
+        state.spawned.insert(pid);
+
+        Ok(pid)
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+
+        if state.killed.contains(&pid) {
+            None
+        } else if state.spawned.contains(&pid) {
+            Some(u64::from(pid))
+        } else {
+            drop(state);
+
+            self.runner.process_start_time(pid)
+        }
+    }
+
+    fn kill(&self, pid: u32, signal: Signal) -> bool {
+        self.record(format!("kill -{} {pid}", signal_name(signal)));
+
+        self.state.lock().unwrap().killed.insert(pid);
+
+        true
+    }
+
+    fn find_processes(&self, name: &str) -> Vec<ProcessInfo> {
+        let killed = self.state.lock().unwrap().killed.clone();
+
+        self.runner
+            .find_processes(name)
+            .into_iter()
+            .filter(|x| !killed.contains(&x.pid))
+            .collect()
+    }
+}
+
+/// Whether a command only reads state, ie, it prints a version, or it is `kubectl get`,
+/// `kubectl config` or `minikube profile`, `status` or `ip`.
+/// The options which precede the verb, eg, `--context`, `-n` or `-p`, all take a value.
+fn is_read_only(command: &str, args: &[&str]) -> bool {
+    let mut args = args.iter().copied();
+
+    let verb = loop {
+        match args.next() {
+            Some(arg) if arg.starts_with('-') && arg != "-V" => {
+                args.next();
+            }
+            arg => break arg.unwrap_or_default(),
+        }
+    };
+
+    matches!(
+        (command, verb),
+        (_, "-V" | "version")
+            | ("kubectl", "get" | "config")
+            | ("minikube", "profile" | "status" | "ip")
+    )
+}
+
+/// The name of a signal as `kill` takes it, eg, INT for [`Signal::Interrupt`].
+fn signal_name(signal: Signal) -> String {
+    match signal {
+        Signal::Hangup => String::from("HUP"),
+        Signal::Interrupt => String::from("INT"),
+        Signal::Kill => String::from("KILL"),
+        Signal::Term => String::from("TERM"),
+        signal => format!("{signal:?}").to_uppercase(),
+    }
+}
+
+/// Writes the lines read from a child process' stream to its log and returns the last `tail` of
+/// them.
+fn log_lines(stream: impl Read, log: &Mutex<RotatingLog>, tail: usize) -> String {
+    let mut last_lines: VecDeque<String> = VecDeque::new();
+
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let _ = log.lock().unwrap().write_line(&line);
+
+        if tail > 0 {
+            if last_lines.len() == tail {
+                last_lines.pop_front();
+            }
+            last_lines.push_back(line);
+        }
+    }
+
+    Vec::from(last_lines).join("\n")
+}
+
+/// A [`ProcessRunner`] which does not start any process.
+/// It records the command lines it is asked to run, answers them with canned outputs and keeps
+/// track of the processes it pretends to have spawned, so that they can be listed and killed.
+#[derive(Default)]
+pub struct FakeProcessRunner {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    calls: Vec<String>,
+    outputs: Vec<(String, ProcessOutput)>,
+    missing_commands: Vec<String>,
+    exiting_spawns: Vec<String>,
+    processes: HashMap<u32, (String, String)>,
+    last_pid: u32,
+}
+
+impl FakeProcessRunner {
+    pub fn new() -> Self {
+        FakeProcessRunner::default()
+    }
+
+    /// Answers the commands whose command line starts with `prefix` with the given output.
+    /// The most recently added answer takes precedence and commands without an answer succeed
+    /// with no output.
+    pub fn respond(&self, prefix: &str, output: ProcessOutput) {
+        let mut state = self.state.lock().unwrap();
+
+        state.outputs.insert(0, (String::from(prefix), output));
+    }
+
+    /// Answers the commands whose command line starts with `prefix` with the given stdout.
+    pub fn respond_ok(&self, prefix: &str, stdout: &str) {
+        self.respond(
+            prefix,
+            ProcessOutput {
+                code: Some(0),
+                stdout: String::from(stdout),
+                stderr: String::new(),
+            },
+        );
+    }
+
+    /// Makes the commands whose command line starts with `prefix` exit with status 1 and the
+    /// given stderr.
+    pub fn respond_err(&self, prefix: &str, stderr: &str) {
+        self.respond(
+            prefix,
+            ProcessOutput {
+                code: Some(1),
+                stdout: String::new(),
+                stderr: String::from(stderr),
+            },
+        );
+    }
+
+    /// Makes the given command fail as if it were not installed.
+    pub fn remove_command(&self, command: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.missing_commands.push(String::from(command));
+    }
+
+    /// Makes the spawned commands whose command line starts with `prefix` exit right away.
+    pub fn exit_on_spawn(&self, prefix: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.exiting_spawns.push(String::from(prefix));
+    }
+
+    /// Adds a running process which has not been spawned through the runner and returns its
+    /// process id.
+    pub fn add_process(&self, name: &str, command_line: &str) -> u32 {
+        let mut state = self.state.lock().unwrap();
+
+        state.last_pid += 1;
+        let pid = state.last_pid;
+        state
+            .processes
+            .insert(pid, (String::from(name), String::from(command_line)));
+
+        pid
+    }
+
+    /// The command lines of all the commands which have been run or spawned, in order.
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// The command lines of the processes which are still running, sorted.
+    pub fn running(&self) -> Vec<String> {
+        let mut running: Vec<String> = self
+            .state
+            .lock()
+            .unwrap()
+            .processes
+            .values()
+            .map(|(_, command_line)| command_line.clone())
+            .collect();
+
+        running.sort();
+
+        running
+    }
+
+    fn record(&self, command: &str, args: &[&str]) -> io::Result<String> {
+        let mut state = self.state.lock().unwrap();
+
+        let command_line = [command]
+            .iter()
+            .chain(args.iter())
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        state.calls.push(command_line.clone());
+
+        if state.missing_commands.iter().any(|x| x == command) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{command} not found"),
+            ));
+        }
+
+        Ok(command_line)
+    }
+}
+
+impl ProcessRunner for FakeProcessRunner {
+    fn run(&self, command: &str, args: &[&str]) -> io::Result<ProcessOutput> {
+        let command_line = self.record(command, args)?;
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .outputs
+            .iter()
+            .find(|(prefix, _)| command_line.starts_with(prefix))
+            .map(|(_, output)| output.clone())
+            .unwrap_or(ProcessOutput {
+                code: Some(0),
+                ..ProcessOutput::default()
+            }))
+    }
+
+    fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+        _detach: bool,
+        _log: RotatingLog,
+        _on_exit: OnExit,
+    ) -> io::Result<u32> {
+        let command_line = self.record(command, args)?;
+        let mut state = self.state.lock().unwrap();
+
+        state.last_pid += 1;
+        let pid = state.last_pid;
+
+        if !state
+            .exiting_spawns
+            .iter()
+            .any(|x| command_line.starts_with(x))
+        {
+            state
+                .processes
+                .insert(pid, (String::from(command), command_line));
+        }
+
+        Ok(pid)
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+
+        state.processes.get(&pid).map(|_| u64::from(pid))
+    }
+
+    fn kill(&self, pid: u32, _signal: Signal) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        state.processes.remove(&pid);
+
+        true
+    }
+
+    fn find_processes(&self, name: &str) -> Vec<ProcessInfo> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .processes
+            .iter()
+            .filter(|(_, (process_name, _))| process_name == name)
+            .map(|(pid, (_, command_line))| ProcessInfo {
+                pid: *pid,
+                command_line: command_line.clone(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,57 @@
+use std::str::FromStr;
+
+/// The transport protocol of a port of a load balancer or of a socat tunnel, which `kube-minion`
+/// spells in lower case, like socat does, and Kubernetes in upper case.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+    Sctp,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+            Protocol::Sctp => "sctp",
+        }
+    }
+
+    /// The name of the protocol in the ports of a Kubernetes service.
+    pub fn kubernetes_name(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+            Protocol::Sctp => "SCTP",
+        }
+    }
+
+    /// Parses the protocol of a socat tunnel, which cannot be SCTP, since neither socat nor the
+    /// built-in forwarder can forward it.
+    pub fn parse_tunnel_protocol(s: &str) -> Result<Self, String> {
+        match s.parse() {
+            Ok(Protocol::Sctp) | Err(_) => Err(format!(
+                "Unsupported tunnel protocol {} (expected tcp or udp)",
+                s.trim().to_lowercase()
+            )),
+            Ok(protocol) => Ok(protocol),
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "tcp" => Ok(Protocol::Tcp),
+            "udp" => Ok(Protocol::Udp),
+            "sctp" => Ok(Protocol::Sctp),
+            protocol => Err(format!(
+                "Unsupported protocol {protocol} (expected tcp, udp or sctp)"
+            )),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::{thread, time::Duration};
+
+use serde_json::{json, Map, Value};
+
+use crate::{Error, Session};
+
+/// A long-running process that has been started by `kube-minion`, as recorded in the registry file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistryEntry {
+    pub kind: String,
+    pub pid: u32,
+    pub start_time: u64,
+    /// The id of the `kube-minion` session that started the process.
+    pub session: String,
+    /// The process id and the start time of the `kube-minion` process that owns the session, which
+    /// tell whether the session is still running.
+    pub session_pid: u32,
+    pub session_start_time: Option<u64>,
+    pub spec: Map<String, Value>,
+}
+
+impl RegistryEntry {
+    pub fn spec_str(&self, key: &str) -> String {
+        self.spec
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    pub fn spec_u16(&self, key: &str) -> u16 {
+        self.spec
+            .get(key)
+            .and_then(Value::as_u64)
+            .unwrap_or_default() as u16
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "kind": self.kind,
+            "pid": self.pid,
+            "startTime": self.start_time,
+            "session": self.session,
+            "sessionPid": self.session_pid,
+            "sessionStartTime": self.session_start_time,
+            "spec": self.spec,
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        // The entries of earlier versions are recorded with the process id as the session id and no
+        // start time, so their sessions are no longer taken to be running.
+        let session = match json.get("session")? {
+            Value::String(session) => session.clone(),
+            session => session.as_u64()?.to_string(),
+        };
+        let session_pid = match json.get("sessionPid") {
+            Some(session_pid) => session_pid.as_u64()? as u32,
+            None => session.parse().ok()?,
+        };
+
+        Some(RegistryEntry {
+            kind: json.get("kind")?.as_str()?.to_string(),
+            pid: json.get("pid")?.as_u64()? as u32,
+            start_time: json.get("startTime")?.as_u64()?,
+            session,
+            session_pid,
+            session_start_time: json.get("sessionStartTime").and_then(Value::as_u64),
+            spec: json.get("spec")?.as_object()?.to_owned(),
+        })
+    }
+}
+
+/// Records a process that has just been started, so that it can be listed and deleted later on.
+pub fn register_process(
+    session: &Session,
+    kind: &str,
+    pid: u32,
+    spec: Map<String, Value>,
+) -> Result<RegistryEntry, Error> {
+    let start_time = session
+        .runner()
+        .process_start_time(pid)
+        .ok_or_else(|| Error::Spawn {
+            command: String::from(kind),
+            message: format!("The {kind} process with process id {pid} is not running"),
+        })?;
+
+    let entry = RegistryEntry {
+        kind: String::from(kind),
+        pid,
+        start_time,
+        session: session.id().to_string(),
+        session_pid: session.owner_pid(),
+        session_start_time: session.owner_start_time(),
+        spec,
+    };
+
+    with_registry(session, |entries| entries.push(entry.clone()))?;
+
+    Ok(entry)
+}
+
+pub fn unregister_process(session: &Session, entry: &RegistryEntry) -> Result<(), Error> {
+    with_registry(session, |entries| entries.retain(|x| x != entry))
+}
+
+/// Returns the registered processes of the given kind which are still running.
+/// Entries whose process has exited are removed from the registry, unless the session that
+/// started them is still running, so that its supervisor gets to restart them.
+pub fn fetch_registered_processes(
+    session: &Session,
+    kind: &str,
+) -> Result<Vec<RegistryEntry>, Error> {
+    with_registry(session, |entries| {
+        entries.retain(|x| is_process_alive(session, x) || is_session_alive(session, x));
+
+        entries
+            .iter()
+            .filter(|x| x.kind == kind && is_process_alive(session, x))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Removes the processes started by this session which have exited from the registry and
+/// returns them.
+pub fn take_exited_processes(session: &Session) -> Result<Vec<RegistryEntry>, Error> {
+    with_registry(session, |entries| {
+        let (exited, running): (Vec<RegistryEntry>, Vec<RegistryEntry>) = entries
+            .drain(..)
+            .partition(|x| x.session == session.id() && !is_process_alive(session, x));
+
+        *entries = running;
+
+        exited
+    })
+}
+
+/// Kills a registered process, waits for it to exit and removes it from the registry.
+/// The entry is removed before the process is killed, so that the process is not mistaken for
+/// one that has exited unexpectedly, and it is restored if the process cannot be killed.
+pub fn kill_registered_process(
+    session: &Session,
+    entry: &RegistryEntry,
+    signal: sysinfo::Signal,
+) -> Result<(), Error> {
+    unregister_process(session, entry)?;
+
+    if is_process_alive(session, entry) {
+        if !session.runner().kill(entry.pid, signal) {
+            with_registry(session, |entries| entries.push(entry.clone()))?;
+
+            return Err(Error::Io(format!(
+                "Failed to kill {} with process id {}",
+                entry.kind, entry.pid
+            )));
+        }
+
+        let mut cnt = 0;
+
+        while is_process_alive(session, entry) && cnt < 50 {
+            cnt += 1;
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        if is_process_alive(session, entry) {
+            with_registry(session, |entries| entries.push(entry.clone()))?;
+
+            return Err(Error::Timeout(format!(
+                "{} with process id {} did not exit in time",
+                entry.kind, entry.pid
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// A registered process is considered alive only if a process with the same id and start time
+/// exists, so that a recycled process id is not mistaken for the registered process.
+pub fn is_process_alive(session: &Session, entry: &RegistryEntry) -> bool {
+    session.runner().process_start_time(entry.pid) == Some(entry.start_time)
+}
+
+/// Whether the `kube-minion` session that started a registered process is still running, which is
+/// only known when the process that owns the session, with the same id and start time, exists.
+fn is_session_alive(session: &Session, entry: &RegistryEntry) -> bool {
+    entry.session == session.id()
+        || entry.session_start_time.is_some_and(|start_time| {
+            session.runner().process_start_time(entry.session_pid) == Some(start_time)
+        })
+}
+
+/// Reads the registry, lets `f` modify it and writes it back, while holding an exclusive lock on
+/// the registry file, so that concurrent `kube-minion` instances do not overwrite each other's
+/// entries.
+fn with_registry<T>(
+    session: &Session,
+    f: impl FnOnce(&mut Vec<RegistryEntry>) -> T,
+) -> Result<T, Error> {
+    let registry_path = session.state_dir().join("registry.json");
+
+    if let Some(parent) = registry_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file: File = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&registry_path)
+        .map_err(|x| Error::Io(format!("Failed to open {}: {x}", registry_path.display())))?;
+
+    file.lock()?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let mut entries: Vec<RegistryEntry> = serde_json::from_str::<Value>(&content)
+        .ok()
+        .and_then(|x| x.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(RegistryEntry::from_json)
+        .collect();
+
+    let result = f(&mut entries);
+
+    // A dry run leaves the registry as it is, since it does not start or kill any process.
+    if session.dry_run() {
+        return Ok(result);
+    }
+
+    let content = Value::Array(entries.iter().map(RegistryEntry::to_json).collect());
+
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(content.to_string().as_bytes())?;
+
+    Ok(result)
+}
@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::{env, process};
+
+use crate::{KubeApi, OutputFormat, ProcessRunner, SystemProcessRunner, TunnelEngine};
+
+/// Tells apart the sessions of a single `kube-minion` process, eg, of tests that run side by side.
+static NEXT_SESSION_SERIAL: AtomicU32 = AtomicU32::new(0);
+
+/// The runtime settings of a `kube-minion` session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// The port on which to expose the Kubernetes dashboard load balancer service.
+    pub dashboard_port: u16,
+    /// The host at the receiving end of socat tunnels, when none is specified.
+    pub default_connect_host: String,
+    /// The address on which to bind the minikube tunnel.
+    pub bind_address: String,
+    /// The engine of socat tunnels, when none is specified.
+    pub default_tunnel_engine: TunnelEngine,
+    pub output_format: OutputFormat,
+    /// Whether long-running child processes must be detached from `kube-minion`'s standard
+    /// streams, so that they can outlive it.
+    pub detach_child_processes: bool,
+    /// The directory in which the resource registry is kept.
+    pub state_dir: PathBuf,
+    /// The minikube profile to operate on, or `None` for minikube's active profile.
+    pub minikube_profile: Option<String>,
+    /// The kubectl context to operate on, which is pinned to the current context when none is
+    /// given, so that switching contexts in another shell does not affect `kube-minion`.
+    pub kube_context: Option<String>,
+    /// The kubeconfig file that kubectl reads, or `None` for kubectl's default.
+    pub kubeconfig: Option<String>,
+    /// Whether to operate on a kubectl context that does not belong to the minikube profile.
+    pub allow_foreign_kube_context: bool,
+    /// Whether to run kubectl for every Kubernetes operation, rather than talking to the
+    /// Kubernetes API server directly.
+    pub use_kubectl: bool,
+    /// Whether to print the commands that change anything instead of running them, in which case
+    /// the session's runner is a [`DryRunProcessRunner`](crate::DryRunProcessRunner).
+    pub dry_run: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            dashboard_port: 51515,
+            default_connect_host: String::from("localhost"),
+            bind_address: String::from("127.0.0.1"),
+            default_tunnel_engine: TunnelEngine::Socat,
+            output_format: OutputFormat::Text,
+            detach_child_processes: false,
+            state_dir: get_default_state_dir(),
+            minikube_profile: None,
+            kube_context: None,
+            kubeconfig: None,
+            allow_foreign_kube_context: false,
+            use_kubectl: false,
+            dry_run: false,
+        }
+    }
+}
+
+/// Holds the settings that every operation runs with, along with the process runner through which
+/// external commands are executed and the client of the Kubernetes API, if the session talks to it
+/// directly.
+/// A session can be shared between threads and its settings can be snapshotted and restored.
+pub struct Session {
+    id: String,
+    /// The start time of the `kube-minion` process that owns the session, so that the session is
+    /// not mistaken for a running one once its process id has been recycled.
+    owner_start_time: Option<u64>,
+    settings: RwLock<Settings>,
+    runner: Arc<dyn ProcessRunner>,
+    kube_api: RwLock<Option<Arc<KubeApi>>>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new(Settings::default())
+    }
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> Self {
+        Session::with_runner(settings, Arc::new(SystemProcessRunner))
+    }
+
+    pub fn with_runner(settings: Settings, runner: Arc<dyn ProcessRunner>) -> Self {
+        let pid = process::id();
+
+        Session {
+            id: format!(
+                "{pid}-{}",
+                NEXT_SESSION_SERIAL.fetch_add(1, Ordering::SeqCst)
+            ),
+            owner_start_time: runner.process_start_time(pid),
+            settings: RwLock::new(settings),
+            runner,
+            kube_api: RwLock::new(None),
+        }
+    }
+
+    pub fn runner(&self) -> &dyn ProcessRunner {
+        self.runner.as_ref()
+    }
+
+    /// The client of the Kubernetes API, or `None` when kubectl has to be run instead.
+    pub fn kube_api(&self) -> Option<Arc<KubeApi>> {
+        self.kube_api.read().unwrap().clone()
+    }
+
+    pub fn set_kube_api(&self, kube_api: Option<Arc<KubeApi>>) {
+        *self.kube_api.write().unwrap() = kube_api;
+    }
+
+    /// The id with which the resources started in this session are recorded in the registry, which
+    /// is unique among the sessions of the `kube-minion` process that owns it.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The process id of the `kube-minion` process that owns the session.
+    pub fn owner_pid(&self) -> u32 {
+        process::id()
+    }
+
+    pub fn owner_start_time(&self) -> Option<u64> {
+        self.owner_start_time
+    }
+
+    /// Returns a snapshot of the session's current settings.
+    pub fn settings(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replaces the session's settings, eg, with a previously taken snapshot.
+    pub fn restore(&self, settings: Settings) {
+        *self.settings.write().unwrap() = settings;
+    }
+
+    pub fn dashboard_port(&self) -> u16 {
+        self.settings.read().unwrap().dashboard_port
+    }
+
+    pub fn set_dashboard_port(&self, dashboard_port: u16) {
+        self.settings.write().unwrap().dashboard_port = dashboard_port;
+    }
+
+    pub fn default_connect_host(&self) -> String {
+        self.settings.read().unwrap().default_connect_host.clone()
+    }
+
+    pub fn set_default_connect_host(&self, default_connect_host: String) {
+        self.settings.write().unwrap().default_connect_host = default_connect_host;
+    }
+
+    pub fn bind_address(&self) -> String {
+        self.settings.read().unwrap().bind_address.clone()
+    }
+
+    pub fn set_bind_address(&self, bind_address: String) {
+        self.settings.write().unwrap().bind_address = bind_address;
+    }
+
+    pub fn default_tunnel_engine(&self) -> TunnelEngine {
+        self.settings.read().unwrap().default_tunnel_engine
+    }
+
+    pub fn set_default_tunnel_engine(&self, default_tunnel_engine: TunnelEngine) {
+        self.settings.write().unwrap().default_tunnel_engine = default_tunnel_engine;
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.settings.read().unwrap().output_format
+    }
+
+    pub fn set_output_format(&self, output_format: OutputFormat) {
+        self.settings.write().unwrap().output_format = output_format;
+    }
+
+    pub fn detach_child_processes(&self) -> bool {
+        self.settings.read().unwrap().detach_child_processes
+    }
+
+    pub fn set_detach_child_processes(&self, detach_child_processes: bool) {
+        self.settings.write().unwrap().detach_child_processes = detach_child_processes;
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.settings.read().unwrap().state_dir.clone()
+    }
+
+    pub fn minikube_profile(&self) -> Option<String> {
+        self.settings.read().unwrap().minikube_profile.clone()
+    }
+
+    pub fn set_minikube_profile(&self, minikube_profile: Option<String>) {
+        self.settings.write().unwrap().minikube_profile = minikube_profile;
+    }
+
+    pub fn kube_context(&self) -> Option<String> {
+        self.settings.read().unwrap().kube_context.clone()
+    }
+
+    pub fn set_kube_context(&self, kube_context: Option<String>) {
+        self.settings.write().unwrap().kube_context = kube_context;
+    }
+
+    pub fn kubeconfig(&self) -> Option<String> {
+        self.settings.read().unwrap().kubeconfig.clone()
+    }
+
+    pub fn set_kubeconfig(&self, kubeconfig: Option<String>) {
+        self.settings.write().unwrap().kubeconfig = kubeconfig;
+    }
+
+    pub fn allow_foreign_kube_context(&self) -> bool {
+        self.settings.read().unwrap().allow_foreign_kube_context
+    }
+
+    pub fn use_kubectl(&self) -> bool {
+        self.settings.read().unwrap().use_kubectl
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.settings.read().unwrap().dry_run
+    }
+}
+
+/// `$XDG_STATE_HOME/kube-minion/`, falling back to `$HOME/.local/state/kube-minion/`.
+fn get_default_state_dir() -> PathBuf {
+    let state_home = match env::var("XDG_STATE_HOME") {
+        Ok(state_home) if !state_home.is_empty() => PathBuf::from(state_home),
+        _ => PathBuf::from(env::var("HOME").unwrap_or_default())
+            .join(".local")
+            .join("state"),
+    };
+
+    state_home.join("kube-minion")
+}
@@ -1,16 +1,63 @@
-use regex::Regex;
-use std::{thread, time::Duration};
-
-use sysinfo::{ProcessExt, SystemExt};
-
+use std::env;
+use std::str::FromStr;
+
+use crate::forwarder::{forwarder_stats_path, read_forwarder_stats};
+use crate::health::{probe, Health, HealthCheck};
+use crate::process_log::log_path;
+use crate::protocol::Protocol;
+use crate::registry::{
+    fetch_registered_processes, kill_registered_process, register_process, RegistryEntry,
+};
 use crate::{
-    get_sys_info, merge_if_ok, parse_num, parse_string, print_results, start_and_wait_process,
-    CommandExecutionResult, CommandResultType::*, OptionFunc,
+    parse_num, parse_string, spawn_long_running_process, CommandExecutionResult,
+    CommandResultType::*, Error, OptionFunc, Resource, Session,
 };
 
-static mut DEFAULT_CONNECT_HOST: Option<String> = None;
+pub const SOCAT_TUNNEL_KIND: &str = "socatTunnel";
+
+/// What forwards the traffic of a socat tunnel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TunnelEngine {
+    /// A `socat` process.
+    Socat,
+    /// A `kube-minion forward` process, which runs the built-in forwarder and keeps statistics of
+    /// the forwarded traffic.
+    Builtin,
+}
+
+impl TunnelEngine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TunnelEngine::Socat => "socat",
+            TunnelEngine::Builtin => "builtin",
+        }
+    }
+
+    /// Reads the engine from the specification of a registered process, which is `socat` for the
+    /// tunnels registered before there was a choice.
+    pub fn from_spec(entry: &RegistryEntry) -> Self {
+        entry
+            .spec_str("engine")
+            .parse()
+            .unwrap_or(TunnelEngine::Socat)
+    }
+}
+
+impl FromStr for TunnelEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "socat" => Ok(TunnelEngine::Socat),
+            "builtin" => Ok(TunnelEngine::Builtin),
+            engine => Err(format!(
+                "Unsupported tunnel engine {engine} (expected socat or builtin)"
+            )),
+        }
+    }
+}
 
-pub fn build_create_socat_tunnel_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_create_socat_tunnel_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Create socat tunnel"),
         Box::new(create_socat_tunnel_guided),
@@ -18,7 +65,7 @@ pub fn build_create_socat_tunnel_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_fetch_socat_tunnels_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_fetch_socat_tunnels_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("List socat tunnels"),
         Box::new(fetch_socat_tunnels),
@@ -26,7 +73,7 @@ pub fn build_fetch_socat_tunnels_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_delete_socat_tunnel_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_socat_tunnel_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete socat tunnel"),
         Box::new(delete_socat_tunnel_guided),
@@ -34,7 +81,7 @@ pub fn build_delete_socat_tunnel_option() -> Result<(String, OptionFunc, bool),
     ))
 }
 
-pub fn build_delete_all_socat_tunnels_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_delete_all_socat_tunnels_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Delete all socat tunnels"),
         Box::new(delete_all_socat_tunnels),
@@ -42,7 +89,7 @@ pub fn build_delete_all_socat_tunnels_option() -> Result<(String, OptionFunc, bo
     ))
 }
 
-pub fn build_set_default_connect_host_option() -> Result<(String, OptionFunc, bool), String> {
+pub fn build_set_default_connect_host_option() -> Result<(String, OptionFunc, bool), Error> {
     Ok((
         String::from("Set socat default connect host"),
         Box::new(set_default_connect_host_guided),
@@ -51,125 +98,153 @@ pub fn build_set_default_connect_host_option() -> Result<(String, OptionFunc, bo
 }
 
 pub fn create_socat_tunnel(
+    session: &Session,
     protocol: &str,
     listening_port: u16,
     connect_host: &str,
     connect_port: u16,
+    health_check: &HealthCheck,
+    engine: TunnelEngine,
 ) -> CommandExecutionResult {
-    {
-        let protocol = String::from(protocol);
-
-        let connect_host = match connect_host {
-            "" => get_default_connect_host(),
-            connect_host => String::from(connect_host),
-        };
-
-        thread::spawn(move || {
-            print_results(
-                start_and_wait_process(
-                    "socat",
-                    &[
-                        "-lpkube-minion-socat",
-                        &format!("{protocol}-listen:{listening_port},fork,reuseaddr"),
-                        &format!("{protocol}:{connect_host}:{connect_port}"),
-                    ],
-                    Some(format!(
-                    "Failed to start socat tunnel listening on port {listening_port}/{protocol} \
-                    and connecting to {connect_host}:{connect_port}"
-                )),
-                ),
-                false,
-                true,
-            );
-        });
-    }
+    let connect_host = match connect_host {
+        "" => session.default_connect_host(),
+        connect_host => String::from(connect_host),
+    };
 
-    {
-        let sys_info = get_sys_info();
-        let mut cnt = 0;
+    let listening_port_str = listening_port.to_string();
+    let connect_port_str = connect_port.to_string();
+    let parts = [
+        "socat-tunnel",
+        protocol,
+        &listening_port_str,
+        &connect_host,
+        &connect_port_str,
+    ];
+
+    let (command, args) = match engine {
+        TunnelEngine::Socat => (
+            String::from("socat"),
+            vec![
+                String::from("-lpkube-minion-socat"),
+                format!("{protocol}-listen:{listening_port},fork,reuseaddr"),
+                format!("{protocol}:{connect_host}:{connect_port}"),
+            ],
+        ),
+        TunnelEngine::Builtin => (
+            env::current_exe()?.to_string_lossy().to_string(),
+            vec![
+                String::from("forward"),
+                String::from(protocol),
+                listening_port_str.clone(),
+                connect_host.clone(),
+                connect_port_str.clone(),
+                String::from("--stats"),
+                forwarder_stats_path(session, &parts)
+                    .to_string_lossy()
+                    .to_string(),
+            ],
+        ),
+    };
 
-        while let None = check_socat_tunnel(&sys_info, listening_port, connect_host, connect_port) && cnt < 5 {
-            cnt += 1;
-            thread::sleep(Duration::from_secs(1));
-        }
+    let pid = spawn_long_running_process(
+        session,
+        &command,
+        &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+        log_path(session, &parts),
+        Some(format!(
+            "Failed to start socat tunnel listening on port {listening_port}/{protocol} \
+            and connecting to {connect_host}:{connect_port}"
+        )),
+    )?;
 
-        if cnt == 5 {
-            return Err(String::from(
-                "Failed to verify if socat tunnel has been started",
-            ));
-        }
-    }
+    let mut spec = serde_json::Map::new();
+    spec.insert(String::from("protocol"), protocol.into());
+    spec.insert(String::from("listeningPort"), listening_port.into());
+    spec.insert(String::from("connectHost"), connect_host.clone().into());
+    spec.insert(String::from("connectPort"), connect_port.into());
+    spec.insert(String::from("engine"), engine.name().into());
+    health_check.to_spec(&mut spec);
+
+    let entry =
+        register_process(session, SOCAT_TUNNEL_KIND, pid, spec).map_err(|_| Error::Spawn {
+            command,
+            message: String::from("Failed to verify if socat tunnel has been started"),
+        })?;
 
-    Ok(PrintableResults(None, vec![
-        format!("Started socat tunnel listening on port {listening_port} and connecting to {connect_host}:{connect_port}")
-    ]))
+    Ok(Resources(
+        None,
+        vec![probe_socat_tunnel(&entry).annotate(describe_socat_tunnel(
+            &entry,
+            format!(
+                "Started socat tunnel listening on port {listening_port} \
+                and connecting to {connect_host}:{connect_port}"
+            ),
+            "started",
+        ))],
+    ))
 }
 
 pub fn delete_socat_tunnel(
+    session: &Session,
     listening_port: u16,
     connect_host: &str,
     connect_port: u16,
 ) -> CommandExecutionResult {
     let connect_host = match connect_host {
-        "" => get_default_connect_host(),
+        "" => session.default_connect_host(),
         connect_host => String::from(connect_host),
     };
 
-    let sys_info = get_sys_info();
-
-    let mut results: Vec<String> = Vec::new();
-
-    if let Some(pid) = check_socat_tunnel(&sys_info, listening_port, &connect_host, connect_port) &&
-            let Some(process) = get_sys_info().process(pid) {
-            if process.kill() {
-                results.push(format!(
-                    "Stopped socat tunnel listening on port {listening_port} \
-                    and connecting to {connect_host}:{connect_port}", ));
-            } else {
-                results.push(format!(
-                    "Failed to stop socat tunnel listening on port {listening_port} \
-                    and connecting to {connect_host}:{connect_port}", ));
-            }
-        }
+    let results = fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?
+        .iter()
+        .filter(|x| {
+            x.spec_u16("listeningPort") == listening_port
+                && x.spec_str("connectHost") == connect_host
+                && x.spec_u16("connectPort") == connect_port
+        })
+        .map(|x| delete_socat_tunnel_entry(session, x))
+        .collect();
 
-    Ok(PrintableResults(None, results))
+    Ok(Resources(None, results))
 }
 
-pub fn set_default_connect_host(connect_host: String) -> String {
-    unsafe {
-        DEFAULT_CONNECT_HOST.replace(connect_host);
-    }
+pub fn set_default_connect_host(session: &Session, connect_host: String) -> String {
+    session.set_default_connect_host(connect_host);
 
-    format!("Socat default connect host has been set to {}", unsafe {
-        DEFAULT_CONNECT_HOST.as_ref().unwrap()
-    })
+    format!(
+        "Socat default connect host has been set to {}",
+        session.default_connect_host()
+    )
 }
 
-pub fn delete_all_socat_tunnels() -> CommandExecutionResult {
-    let socat_tunnels = match fetch_socat_tunnels()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
-    };
-
-    let mut results: Vec<String> = Vec::new();
-
-    for socat_tunnel in &socat_tunnels {
-        let (listening_port, connect_host, connect_port) = parse_socat_tunnel(socat_tunnel);
-
-        merge_if_ok(&mut results, || {
-            delete_socat_tunnel(listening_port, &connect_host, connect_port)
-        })?;
-    }
+pub fn delete_all_socat_tunnels(session: &Session) -> CommandExecutionResult {
+    let results = fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?
+        .iter()
+        .map(|x| delete_socat_tunnel_entry(session, x))
+        .collect();
 
-    Ok(PrintableResults(None, results))
+    Ok(Resources(None, results))
 }
 
-fn fetch_socat_tunnels() -> CommandExecutionResult {
-    let sys_info = get_sys_info();
-    let socat_tunnels: Vec<String> = sys_info
-        .processes_by_name("socat")
-        .map(|x| x.cmd().join(" "))
-        .filter(|x| x.contains("-lpkube-minion-socat"))
+pub fn fetch_socat_tunnels(session: &Session) -> CommandExecutionResult {
+    let socat_tunnels: Vec<Resource> = fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?
+        .iter()
+        .map(|x| {
+            let socat_tunnel = describe_socat_tunnel(
+                x,
+                format!(
+                    "{} tunnel listening on port {} and connecting to {}:{} (pid {})",
+                    x.spec_str("protocol"),
+                    x.spec_u16("listeningPort"),
+                    x.spec_str("connectHost"),
+                    x.spec_u16("connectPort"),
+                    x.pid,
+                ),
+                "running",
+            );
+
+            probe_socat_tunnel(x).annotate(add_forwarder_stats(session, x, socat_tunnel))
+        })
         .collect();
 
     let title = if socat_tunnels.is_empty() {
@@ -178,15 +253,17 @@ fn fetch_socat_tunnels() -> CommandExecutionResult {
         Some(String::from("Socat tunnels:"))
     };
 
-    Ok(PrintableResults(title, socat_tunnels))
+    Ok(Resources(title, socat_tunnels))
 }
 
-fn create_socat_tunnel_guided() -> CommandExecutionResult {
-    let protocol = parse_string(
+fn create_socat_tunnel_guided(session: &Session) -> CommandExecutionResult {
+    let protocol = Protocol::parse_tunnel_protocol(&parse_string(
         "Protocol (either tcp or udp / leave empty for tcp): ",
         Some(String::from("tcp")),
         None,
-    )?;
+    )?)
+    .map_err(Error::Input)?
+    .name();
 
     let listening_port: u16 = parse_num(
         "Listening port: ",
@@ -199,9 +276,9 @@ fn create_socat_tunnel_guided() -> CommandExecutionResult {
     let connect_host = parse_string(
         &format!(
             "Connect host (leave empty for {}): ",
-            get_default_connect_host()
+            session.default_connect_host()
         ),
-        Some(get_default_connect_host()),
+        Some(session.default_connect_host()),
         None,
     )?;
 
@@ -213,28 +290,48 @@ fn create_socat_tunnel_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    create_socat_tunnel(&protocol, listening_port, &connect_host, connect_port)
-}
+    let health_check_path = parse_string(
+        "HTTP health check path (leave empty to only check that the port accepts connections): ",
+        Some(String::new()),
+        None,
+    )?;
 
-fn check_socat_tunnel(
-    sys_info: &sysinfo::System,
-    listening_port: u16,
-    connect_host: &str,
-    connect_port: u16,
-) -> Option<sysinfo::Pid> {
-    sys_info
-        .processes_by_name("socat")
-        .find(|x| {
-            let cmd = x.cmd().join(" ");
-
-            cmd.contains("-lpkube-minion-socat")
-                && cmd.contains(&format!("-listen:{listening_port}"))
-                && cmd.contains(&format!(":{connect_host}:{connect_port}"))
-        })
-        .map(|x| x.pid())
+    let engine = parse_string(
+        &format!(
+            "Engine (either socat or builtin / leave empty for {}): ",
+            session.default_tunnel_engine().name()
+        ),
+        Some(String::from(session.default_tunnel_engine().name())),
+        None,
+    )?
+    .parse::<TunnelEngine>()
+    .map_err(Error::Input)?;
+
+    let health_check = if health_check_path.is_empty() {
+        HealthCheck::Tcp
+    } else {
+        HealthCheck::Http {
+            path: health_check_path,
+            expected_status: parse_num(
+                "Expected HTTP status (leave empty for 200): ",
+                Some(200),
+                None,
+            )?,
+        }
+    };
+
+    create_socat_tunnel(
+        session,
+        protocol,
+        listening_port,
+        &connect_host,
+        connect_port,
+        &health_check,
+        engine,
+    )
 }
 
-fn delete_socat_tunnel_guided() -> CommandExecutionResult {
+fn delete_socat_tunnel_guided(session: &Session) -> CommandExecutionResult {
     let index: usize = parse_num(
         "Index: ",
         None,
@@ -243,66 +340,115 @@ fn delete_socat_tunnel_guided() -> CommandExecutionResult {
         )),
     )?;
 
-    delete_socat_tunnel_by_index(index - 1)
+    delete_socat_tunnel_by_index(session, index - 1)
 }
 
-fn delete_socat_tunnel_by_index(index: usize) -> CommandExecutionResult {
-    let socat_tunnels = match fetch_socat_tunnels()? {
-        ChildProcess(_) => unreachable!(),
-        PrintableResults(_, results) => results,
-    };
+pub fn delete_socat_tunnel_by_index(session: &Session, index: usize) -> CommandExecutionResult {
+    let socat_tunnels = fetch_registered_processes(session, SOCAT_TUNNEL_KIND)?;
 
     if index >= socat_tunnels.len() {
-        return Err(format!(
+        return Err(Error::NotFound(format!(
             "Index {index} does not correspond to a socat tunnel"
-        ));
+        )));
     }
 
-    let (listening_port, connect_host, connect_port) = parse_socat_tunnel(&socat_tunnels[index]);
-
-    let sys_info = get_sys_info();
+    Ok(Resources(
+        None,
+        vec![delete_socat_tunnel_entry(session, &socat_tunnels[index])],
+    ))
+}
 
-    let mut results: Vec<String> = Vec::new();
+pub fn delete_socat_tunnel_entry(session: &Session, entry: &RegistryEntry) -> Resource {
+    let listening_port = entry.spec_u16("listeningPort");
+    let connect_host = entry.spec_str("connectHost");
+    let connect_port = entry.spec_u16("connectPort");
+
+    match kill_registered_process(session, entry, sysinfo::Signal::Kill) {
+        Ok(_) => describe_socat_tunnel(
+            entry,
+            format!(
+                "Stopped socat tunnel listening on port {listening_port} \
+                and connecting to {connect_host}:{connect_port}"
+            ),
+            "deleted",
+        ),
+        Err(error) => describe_socat_tunnel(
+            entry,
+            format!(
+                "Failed to stop socat tunnel listening on port {listening_port} \
+                and connecting to {connect_host}:{connect_port}: {error}"
+            ),
+            "running",
+        ),
+    }
+}
 
-    if let Some(pid) = check_socat_tunnel(&sys_info, listening_port, &connect_host, connect_port) &&
-        let Some(process) = get_sys_info().process(pid) &&
-        process.kill() {
-        results.push(format!(
-            "Stopped socat tunnel listening on port {listening_port} \
-            and connecting to {connect_host}:{connect_port}", ));
+/// Probes the listening port of a tunnel, which is only possible for tcp tunnels.
+fn probe_socat_tunnel(entry: &RegistryEntry) -> Health {
+    if entry.spec_str("protocol") != "tcp" {
+        return Health::Unknown(String::from("only tcp tunnels can be probed"));
     }
 
-    // start_and_wait_process("kubectl", &["-n", &namespace, "delete", "svc", &name], None)
-    Ok(PrintableResults(None, results))
+    probe(
+        "127.0.0.1",
+        entry.spec_u16("listeningPort"),
+        &HealthCheck::from_spec(&entry.spec),
+    )
 }
 
-#[allow(clippy::invalid_regex)] // clippy bug?
-fn parse_socat_tunnel(spec: &str) -> (u16, String, u16) {
-    let re = Regex::new(
-        r".+?-listen:(?<listening_port>[0-9]+).+?:(?<connect_host>.+):(?<connect_port>[0-9]+)$",
-    )
-    .unwrap();
+/// Adds the statistics of a built-in forwarder to the description and the fields of its tunnel.
+fn add_forwarder_stats(session: &Session, entry: &RegistryEntry, resource: Resource) -> Resource {
+    if TunnelEngine::from_spec(entry) != TunnelEngine::Builtin {
+        return resource;
+    }
+
+    let stats_path = forwarder_stats_path(
+        session,
+        &[
+            "socat-tunnel",
+            &entry.spec_str("protocol"),
+            &entry.spec_u16("listeningPort").to_string(),
+            &entry.spec_str("connectHost"),
+            &entry.spec_u16("connectPort").to_string(),
+        ],
+    );
+
+    let Some(stats) = read_forwarder_stats(&stats_path) else {
+        return resource;
+    };
 
-    let captures = re.captures(spec).unwrap();
+    let stat = |key: &str| stats.get(key).and_then(|x| x.as_u64()).unwrap_or_default();
 
-    let listening_port: u16 = captures
-        .name("listening_port")
-        .unwrap()
-        .as_str()
-        .parse()
-        .unwrap();
-    let connect_host: String = captures.name("connect_host").unwrap().as_str().to_string();
-    let connect_port: u16 = captures
-        .name("connect_port")
-        .unwrap()
-        .as_str()
-        .parse()
-        .unwrap();
+    let mut resource = resource
+        .field("connections", stat("connections"))
+        .field("activeConnections", stat("activeConnections"))
+        .field("bytesSent", stat("bytesSent"))
+        .field("bytesReceived", stat("bytesReceived"));
+
+    resource.description = format!(
+        "{}, {} connections ({} active), {} bytes sent, {} bytes received",
+        resource.description,
+        stat("connections"),
+        stat("activeConnections"),
+        stat("bytesSent"),
+        stat("bytesReceived"),
+    );
 
-    (listening_port, connect_host, connect_port)
+    resource
 }
 
-fn set_default_connect_host_guided() -> CommandExecutionResult {
+fn describe_socat_tunnel(entry: &RegistryEntry, description: String, state: &str) -> Resource {
+    Resource::new("socatTunnel", description)
+        .field("engine", TunnelEngine::from_spec(entry).name())
+        .field("protocol", entry.spec_str("protocol"))
+        .field("listeningPort", entry.spec_u16("listeningPort"))
+        .field("connectHost", entry.spec_str("connectHost"))
+        .field("connectPort", entry.spec_u16("connectPort"))
+        .field("pid", entry.pid)
+        .field("state", state)
+}
+
+fn set_default_connect_host_guided(session: &Session) -> CommandExecutionResult {
     let connect_host = parse_string(
         "Default connect host: ",
         None,
@@ -313,14 +459,228 @@ fn set_default_connect_host_guided() -> CommandExecutionResult {
 
     Ok(PrintableResults(
         None,
-        vec![set_default_connect_host(connect_host)],
+        vec![set_default_connect_host(session, connect_host)],
     ))
 }
 
-fn get_default_connect_host() -> String {
-    String::from(unsafe {
-        DEFAULT_CONNECT_HOST
-            .as_ref()
-            .unwrap_or(&String::from("localhost"))
-    })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{resource_fields, TestSession};
+    use std::fs;
+
+    #[test]
+    fn create_socat_tunnel_starts_and_registers_socat() {
+        let session = TestSession::new();
+
+        let result = create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "example.com",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        );
+
+        assert_eq!(
+            resource_fields(
+                result,
+                &["listeningPort", "connectHost", "connectPort", "state"]
+            ),
+            vec![vec!["8080", "example.com", "80", "started"]]
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec!["socat -lpkube-minion-socat tcp-listen:8080,fork,reuseaddr tcp:example.com:80"]
+        );
+    }
+
+    #[test]
+    fn create_socat_tunnel_uses_the_default_connect_host() {
+        let session = TestSession::new();
+        set_default_connect_host(&session, String::from("10.0.0.1"));
+
+        let result = create_socat_tunnel(
+            &session,
+            "udp",
+            5353,
+            "",
+            53,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        );
+
+        assert_eq!(
+            resource_fields(result, &["protocol", "connectHost"]),
+            vec![vec!["udp", "10.0.0.1"]]
+        );
+    }
+
+    #[test]
+    fn builtin_tunnels_run_the_forwarder_and_report_its_stats() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Builtin,
+        )
+        .unwrap();
+
+        let stats_path = forwarder_stats_path(
+            &session,
+            &["socat-tunnel", "tcp", "8080", "localhost", "80"],
+        );
+        assert!(session.runner.running()[0].ends_with(&format!(
+            " forward tcp 8080 localhost 80 --stats {}",
+            stats_path.display()
+        )));
+
+        fs::create_dir_all(stats_path.parent().unwrap()).unwrap();
+        fs::write(
+            &stats_path,
+            r#"{"connections": 3, "activeConnections": 1, "bytesSent": 120, "bytesReceived": 450}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resource_fields(
+                fetch_socat_tunnels(&session),
+                &["engine", "connections", "bytesSent", "bytesReceived"]
+            ),
+            vec![vec!["builtin", "3", "120", "450"]]
+        );
+    }
+
+    #[test]
+    fn create_socat_tunnel_fails_when_socat_exits_right_away() {
+        let session = TestSession::new();
+        session.runner.exit_on_spawn("socat");
+
+        assert!(matches!(
+            create_socat_tunnel(
+                &session,
+                "tcp",
+                8080,
+                "localhost",
+                80,
+                &HealthCheck::Tcp,
+                TunnelEngine::Socat
+            ),
+            Err(Error::Spawn { .. })
+        ));
+        assert!(fetch_registered_processes(&session, SOCAT_TUNNEL_KIND)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn fetch_socat_tunnels_lists_running_tunnels_only() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8443,
+            "localhost",
+            443,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        let entry = fetch_registered_processes(&session, SOCAT_TUNNEL_KIND).unwrap()[0].clone();
+        session.runner().kill(entry.pid, sysinfo::Signal::Kill);
+
+        assert_eq!(
+            resource_fields(fetch_socat_tunnels(&session), &["listeningPort"]),
+            vec![vec!["8443"]]
+        );
+    }
+
+    #[test]
+    fn delete_socat_tunnel_by_index_stops_the_listed_tunnel() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8443,
+            "localhost",
+            443,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        let result = delete_socat_tunnel_by_index(&session, 1);
+
+        assert_eq!(
+            resource_fields(result, &["listeningPort", "state"]),
+            vec![vec!["8443", "deleted"]]
+        );
+        assert_eq!(
+            session.runner.running(),
+            vec!["socat -lpkube-minion-socat tcp-listen:8080,fork,reuseaddr tcp:localhost:80"]
+        );
+        assert!(matches!(
+            delete_socat_tunnel_by_index(&session, 1),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn delete_socat_tunnel_matches_the_tunnel_specification() {
+        let session = TestSession::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8081,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        let result = delete_socat_tunnel(&session, 8081, "", 80);
+
+        assert_eq!(
+            resource_fields(result, &["listeningPort"]),
+            vec![vec!["8081"]]
+        );
+        assert_eq!(session.runner.running().len(), 1);
+    }
 }
@@ -0,0 +1,691 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use getopts::{Matches, Options};
+
+use crate::apply::apply_init_file;
+use crate::clean_up_and_exit::clean_up;
+use crate::export::{export_init_file, DEFAULT_EXPORT_PATH};
+use crate::forwarder::run_forwarder;
+use crate::health::HealthCheck;
+use crate::init_file::get_init_file_path;
+use crate::load_balancer::{
+    create_load_balancer, delete_all_load_balancers, delete_load_balancer,
+    delete_load_balancer_by_index, fetch_load_balancers, parse_key_value, PortMapping,
+    ServiceMetadata, TargetPort,
+};
+use crate::minikube_mount::{
+    create_minikube_mount, delete_all_minikube_mounts, delete_minikube_mount_by_index,
+    fetch_minikube_mounts,
+};
+use crate::minikube_tunnel::{
+    check_minikube_tunnel, create_minikube_tunnel, set_bind_address, stop_minikube_tunnel,
+};
+use crate::port_forward::{
+    create_port_forward, delete_all_port_forwards, delete_port_forward_by_index,
+    fetch_port_forwards, run_reconnecting_port_forward,
+};
+use crate::process_log::{fetch_logs, show_log};
+use crate::protocol::Protocol;
+use crate::socat_tunnel::{
+    create_socat_tunnel, delete_all_socat_tunnels, delete_socat_tunnel_by_index,
+    fetch_socat_tunnels, TunnelEngine,
+};
+use crate::validate::validate_init_file;
+use crate::{
+    process_exited_with_success, CommandExecutionResult, CommandResultType::*, Error, Session,
+};
+
+/// The names of the subcommands that can be used instead of the interactive options menu.
+pub const SUBCOMMANDS: [&str; 12] = [
+    "lb",
+    "socat",
+    "port-forward",
+    "mount",
+    "tunnel",
+    "logs",
+    "clean-up",
+    "apply",
+    "export",
+    "validate",
+    "forward",
+    "reconnect",
+];
+
+pub enum Subcommand {
+    CreateLoadBalancer {
+        namespace: String,
+        resource_type: String,
+        name: String,
+        ports: Vec<PortMapping>,
+        metadata: ServiceMetadata,
+    },
+    ListLoadBalancers,
+    DeleteLoadBalancerByIndex(usize),
+    DeleteLoadBalancer {
+        namespace: String,
+        name: String,
+    },
+    DeleteAllLoadBalancers,
+    CreateSocatTunnel {
+        protocol: String,
+        listening_port: u16,
+        connect_host: String,
+        connect_port: u16,
+        health_check: HealthCheck,
+        /// The engine of the tunnel, or `None` for the session's default engine.
+        engine: Option<TunnelEngine>,
+    },
+    ListSocatTunnels,
+    DeleteSocatTunnelByIndex(usize),
+    DeleteAllSocatTunnels,
+    CreatePortForward {
+        namespace: String,
+        resource_type: String,
+        name: String,
+        local_port: u16,
+        remote_port: u16,
+    },
+    ListPortForwards,
+    DeletePortForwardByIndex(usize),
+    DeleteAllPortForwards,
+    CreateMinikubeMount {
+        host_path: String,
+        minikube_path: String,
+    },
+    ListMinikubeMounts,
+    DeleteMinikubeMountByIndex(usize),
+    DeleteAllMinikubeMounts,
+    StartMinikubeTunnel {
+        bind_address: Option<String>,
+    },
+    StopMinikubeTunnel,
+    ListLogs,
+    ShowLog {
+        index: usize,
+        lines: Option<usize>,
+    },
+    CleanUp,
+    /// Applies an initialization file, or the one that would be processed at start-up.
+    Apply {
+        init_file_path: Option<String>,
+    },
+    /// Exports the current resources as an initialization file.
+    Export {
+        path: String,
+        overwrite: bool,
+    },
+    /// Checks an initialization file, or the one that would be processed at start-up.
+    Validate {
+        init_file_path: Option<String>,
+    },
+    /// Runs the built-in forwarder of a socat tunnel, which is how `kube-minion` starts such a
+    /// tunnel in a process of its own.
+    Forward {
+        protocol: String,
+        listening_port: u16,
+        connect_host: String,
+        connect_port: u16,
+        stats_path: PathBuf,
+    },
+    /// Runs `kubectl port-forward` with the given arguments again whenever it exits, which is how
+    /// `kube-minion` starts a detached port forward in a process of its own.
+    Reconnect {
+        args: Vec<String>,
+    },
+}
+
+pub fn subcommand_usage() -> String {
+    String::from(
+        "Subcommands:
+    lb create [-n NAMESPACE] [-t RESOURCE_TYPE] [-P PROTOCOL] [--service-name SERVICE_NAME]
+        [-l KEY=VALUE]... [--annotation KEY=VALUE]... NAME PORT [TARGET_PORT]
+    lb create [-n NAMESPACE] [-t RESOURCE_TYPE] [-P PROTOCOL] [--service-name SERVICE_NAME]
+        [-l KEY=VALUE]... [--annotation KEY=VALUE]...
+        --port [NAME=]PORT[:TARGET_PORT][/PROTOCOL]... NAME
+    lb list
+    lb delete INDEX
+    lb delete [-n NAMESPACE] SERVICE_NAME
+    lb delete-all
+    socat create [-P PROTOCOL] [-c CONNECT_HOST] [-e ENGINE]
+        [--http-check PATH [--expected-status STATUS]] LISTENING_PORT CONNECT_PORT
+    socat list
+    socat delete INDEX
+    socat delete-all
+    port-forward create [-n NAMESPACE] [-t RESOURCE_TYPE] NAME LOCAL_PORT [REMOTE_PORT]
+    port-forward list
+    port-forward delete INDEX
+    port-forward delete-all
+    mount create HOST_PATH MINIKUBE_PATH
+    mount list
+    mount delete INDEX
+    mount delete-all
+    tunnel start [-b BIND_ADDRESS]
+    tunnel stop
+    logs list
+    logs show [-n LINES] INDEX
+    clean-up
+    apply [INIT_FILE]
+    export [--force] [PATH]
+    validate [INIT_FILE]",
+    )
+}
+
+/// Parses the arguments that follow the subcommand name, with `args[0]` being the subcommand name.
+pub fn parse_subcommand(args: &[String]) -> Result<Subcommand, Error> {
+    let (subcommand, rest) = match args.split_first() {
+        Some((subcommand, rest)) => (subcommand.as_str(), rest),
+        None => return Err(Error::Input(String::from("No subcommand provided"))),
+    };
+
+    let action = rest.first().map(String::as_str).unwrap_or_default();
+    let rest = if rest.is_empty() { rest } else { &rest[1..] };
+
+    match (subcommand, action) {
+        ("lb", "create") => {
+            let mut options = Options::new();
+            options.optopt("n", "namespace", "", "NAMESPACE");
+            options.optopt("t", "resource-type", "", "RESOURCE_TYPE");
+            options.optopt("P", "protocol", "", "PROTOCOL");
+            options.optmulti("", "port", "", "[NAME=]PORT[:TARGET_PORT][/PROTOCOL]");
+            options.optopt("", "service-name", "", "SERVICE_NAME");
+            options.optmulti("l", "label", "", "KEY=VALUE");
+            options.optmulti("", "annotation", "", "KEY=VALUE");
+            let matches = options
+                .parse(rest)
+                .map_err(|x| Error::Input(x.to_string()))?;
+            let port_mappings = matches.opt_strs("port");
+            // The protocol of the ports whose protocol is not given.
+            let protocol = match matches.opt_str("protocol") {
+                Some(protocol) => protocol.parse::<Protocol>().map_err(Error::Input)?,
+                None => Protocol::Tcp,
+            };
+
+            let (matches, ports) = if port_mappings.is_empty() {
+                let matches = parse_matches(&options, rest, 2, 3)?;
+                let port = parse_port(&matches.free[1], "PORT")?;
+                let target_port = match matches.free.get(2) {
+                    Some(target_port) => target_port.parse().map_err(Error::Input)?,
+                    None => TargetPort::Number(port),
+                };
+
+                let ports = vec![PortMapping {
+                    name: None,
+                    port,
+                    target_port,
+                    protocol,
+                }];
+
+                (matches, ports)
+            } else {
+                let ports = port_mappings
+                    .iter()
+                    .map(|x| {
+                        let mut port = x.parse::<PortMapping>().map_err(Error::Input)?;
+                        if !x.contains('/') {
+                            port.protocol = protocol;
+                        }
+                        Ok(port)
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                (parse_matches(&options, rest, 1, 1)?, ports)
+            };
+
+            Ok(Subcommand::CreateLoadBalancer {
+                namespace: matches
+                    .opt_str("namespace")
+                    .unwrap_or(String::from("default")),
+                resource_type: matches
+                    .opt_str("resource-type")
+                    .unwrap_or(String::from("svc")),
+                name: matches.free[0].clone(),
+                ports,
+                metadata: ServiceMetadata {
+                    name: matches.opt_str("service-name"),
+                    labels: parse_key_values(&matches.opt_strs("label"))?,
+                    annotations: parse_key_values(&matches.opt_strs("annotation"))?,
+                },
+            })
+        }
+        ("lb", "list") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::ListLoadBalancers)
+        }
+        ("lb", "delete") => {
+            let mut options = Options::new();
+            options.optopt("n", "namespace", "", "NAMESPACE");
+            let matches = parse_matches(&options, rest, 1, 1)?;
+
+            match matches.free[0].parse::<usize>() {
+                Ok(index) => Ok(Subcommand::DeleteLoadBalancerByIndex(parse_index(index)?)),
+                Err(_) => Ok(Subcommand::DeleteLoadBalancer {
+                    namespace: matches
+                        .opt_str("namespace")
+                        .unwrap_or(String::from("default")),
+                    name: matches.free[0].clone(),
+                }),
+            }
+        }
+        ("lb", "delete-all") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::DeleteAllLoadBalancers)
+        }
+        ("socat", "create") => {
+            let mut options = Options::new();
+            options.optopt("P", "protocol", "", "PROTOCOL");
+            options.optopt("c", "connect-host", "", "CONNECT_HOST");
+            options.optopt("e", "engine", "", "ENGINE");
+            options.optopt("", "http-check", "", "PATH");
+            options.optopt("", "expected-status", "", "STATUS");
+            let matches = parse_matches(&options, rest, 2, 2)?;
+
+            let protocol = Protocol::parse_tunnel_protocol(
+                &matches.opt_str("protocol").unwrap_or(String::from("tcp")),
+            )
+            .map_err(Error::Input)?
+            .name()
+            .to_string();
+
+            let health_check = match matches.opt_str("http-check") {
+                Some(path) => HealthCheck::Http {
+                    path,
+                    expected_status: match matches.opt_str("expected-status") {
+                        Some(status) => status.parse::<u16>().map_err(|_| {
+                            Error::Input(format!("{status} is not a valid HTTP status"))
+                        })?,
+                        None => 200,
+                    },
+                },
+                None if matches.opt_present("expected-status") => {
+                    return Err(Error::Input(String::from(
+                        "--expected-status requires --http-check",
+                    )))
+                }
+                None => HealthCheck::Tcp,
+            };
+
+            let engine = match matches.opt_str("engine") {
+                Some(engine) => Some(engine.parse::<TunnelEngine>().map_err(Error::Input)?),
+                None => None,
+            };
+
+            Ok(Subcommand::CreateSocatTunnel {
+                protocol,
+                listening_port: parse_port(&matches.free[0], "LISTENING_PORT")?,
+                connect_host: matches.opt_str("connect-host").unwrap_or_default(),
+                connect_port: parse_port(&matches.free[1], "CONNECT_PORT")?,
+                health_check,
+                engine,
+            })
+        }
+        ("socat", "list") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::ListSocatTunnels)
+        }
+        ("socat", "delete") => {
+            let matches = parse_matches(&Options::new(), rest, 1, 1)?;
+
+            Ok(Subcommand::DeleteSocatTunnelByIndex(parse_index_str(
+                &matches.free[0],
+            )?))
+        }
+        ("socat", "delete-all") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::DeleteAllSocatTunnels)
+        }
+        ("port-forward", "create") => {
+            let mut options = Options::new();
+            options.optopt("n", "namespace", "", "NAMESPACE");
+            options.optopt("t", "resource-type", "", "RESOURCE_TYPE");
+            let matches = parse_matches(&options, rest, 2, 3)?;
+
+            let local_port = parse_port(&matches.free[1], "LOCAL_PORT")?;
+            let remote_port = match matches.free.get(2) {
+                Some(remote_port) => parse_port(remote_port, "REMOTE_PORT")?,
+                None => local_port,
+            };
+
+            Ok(Subcommand::CreatePortForward {
+                namespace: matches
+                    .opt_str("namespace")
+                    .unwrap_or(String::from("default")),
+                resource_type: matches
+                    .opt_str("resource-type")
+                    .unwrap_or(String::from("svc")),
+                name: matches.free[0].clone(),
+                local_port,
+                remote_port,
+            })
+        }
+        ("port-forward", "list") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::ListPortForwards)
+        }
+        ("port-forward", "delete") => {
+            let matches = parse_matches(&Options::new(), rest, 1, 1)?;
+
+            Ok(Subcommand::DeletePortForwardByIndex(parse_index_str(
+                &matches.free[0],
+            )?))
+        }
+        ("port-forward", "delete-all") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::DeleteAllPortForwards)
+        }
+        ("mount", "create") => {
+            let matches = parse_matches(&Options::new(), rest, 2, 2)?;
+
+            Ok(Subcommand::CreateMinikubeMount {
+                host_path: matches.free[0].clone(),
+                minikube_path: matches.free[1].clone(),
+            })
+        }
+        ("mount", "list") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::ListMinikubeMounts)
+        }
+        ("mount", "delete") => {
+            let matches = parse_matches(&Options::new(), rest, 1, 1)?;
+
+            Ok(Subcommand::DeleteMinikubeMountByIndex(parse_index_str(
+                &matches.free[0],
+            )?))
+        }
+        ("mount", "delete-all") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::DeleteAllMinikubeMounts)
+        }
+        ("tunnel", "start") => {
+            let mut options = Options::new();
+            options.optopt("b", "bind-address", "", "BIND_ADDRESS");
+            let matches = parse_matches(&options, rest, 0, 0)?;
+
+            Ok(Subcommand::StartMinikubeTunnel {
+                bind_address: matches.opt_str("bind-address"),
+            })
+        }
+        ("tunnel", "stop") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::StopMinikubeTunnel)
+        }
+        ("logs", "list") => {
+            parse_matches(&Options::new(), rest, 0, 0)?;
+
+            Ok(Subcommand::ListLogs)
+        }
+        ("logs", "show") => {
+            let mut options = Options::new();
+            options.optopt("n", "lines", "", "LINES");
+            let matches = parse_matches(&options, rest, 1, 1)?;
+
+            let lines = match matches.opt_str("lines") {
+                Some(lines) => Some(lines.parse::<usize>().map_err(|_| {
+                    Error::Input(format!("{lines} is not a valid number of lines"))
+                })?),
+                None => None,
+            };
+
+            Ok(Subcommand::ShowLog {
+                index: parse_index_str(&matches.free[0])?,
+                lines,
+            })
+        }
+        ("clean-up", _) => {
+            parse_matches(&Options::new(), &args[1..], 0, 0)?;
+
+            Ok(Subcommand::CleanUp)
+        }
+        ("apply", _) => {
+            let matches = parse_matches(&Options::new(), &args[1..], 0, 1)?;
+
+            Ok(Subcommand::Apply {
+                init_file_path: matches.free.first().cloned(),
+            })
+        }
+        ("export", _) => {
+            let mut options = Options::new();
+            options.optflag("", "force", "");
+            let matches = parse_matches(&options, &args[1..], 0, 1)?;
+
+            Ok(Subcommand::Export {
+                path: matches
+                    .free
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| String::from(DEFAULT_EXPORT_PATH)),
+                overwrite: matches.opt_present("force"),
+            })
+        }
+        ("validate", _) => {
+            let matches = parse_matches(&Options::new(), &args[1..], 0, 1)?;
+
+            Ok(Subcommand::Validate {
+                init_file_path: matches.free.first().cloned(),
+            })
+        }
+        ("forward", _) => {
+            let mut options = Options::new();
+            options.reqopt("", "stats", "", "PATH");
+            let matches = parse_matches(&options, &args[1..], 4, 4)?;
+
+            Ok(Subcommand::Forward {
+                protocol: matches.free[0].clone(),
+                listening_port: parse_port(&matches.free[1], "LISTENING_PORT")?,
+                connect_host: matches.free[2].clone(),
+                connect_port: parse_port(&matches.free[3], "CONNECT_PORT")?,
+                stats_path: PathBuf::from(matches.opt_str("stats").unwrap_or_default()),
+            })
+        }
+        ("reconnect", _) => Ok(Subcommand::Reconnect {
+            args: args[1..].to_vec(),
+        }),
+        (subcommand, "") if SUBCOMMANDS.contains(&subcommand) => Err(Error::Input(format!(
+            "No action provided for subcommand {subcommand}"
+        ))),
+        (subcommand, action) if SUBCOMMANDS.contains(&subcommand) => Err(Error::Input(format!(
+            "Unknown action {action} for subcommand {subcommand}"
+        ))),
+        (subcommand, _) => Err(Error::Input(format!("Unknown subcommand {subcommand}"))),
+    }
+}
+
+/// Executes a subcommand and converts any child process result into printable results.
+pub fn run_subcommand(session: &Session, subcommand: Subcommand) -> CommandExecutionResult {
+    // The processes started by a subcommand must outlive kube-minion.
+    session.set_detach_child_processes(true);
+
+    let result = match subcommand {
+        Subcommand::CreateLoadBalancer {
+            namespace,
+            resource_type,
+            name,
+            ports,
+            metadata,
+        } => create_load_balancer(
+            session,
+            &namespace,
+            &resource_type,
+            &name,
+            &ports,
+            &metadata,
+        ),
+        Subcommand::ListLoadBalancers => fetch_load_balancers(session),
+        Subcommand::DeleteLoadBalancerByIndex(index) => {
+            delete_load_balancer_by_index(session, index)
+        }
+        Subcommand::DeleteLoadBalancer { namespace, name } => {
+            delete_load_balancer(session, &namespace, &name)
+        }
+        Subcommand::DeleteAllLoadBalancers => delete_all_load_balancers(session),
+        Subcommand::CreateSocatTunnel {
+            protocol,
+            listening_port,
+            connect_host,
+            connect_port,
+            health_check,
+            engine,
+        } => create_socat_tunnel(
+            session,
+            &protocol,
+            listening_port,
+            &connect_host,
+            connect_port,
+            &health_check,
+            engine.unwrap_or(session.default_tunnel_engine()),
+        ),
+        Subcommand::ListSocatTunnels => fetch_socat_tunnels(session),
+        Subcommand::DeleteSocatTunnelByIndex(index) => delete_socat_tunnel_by_index(session, index),
+        Subcommand::DeleteAllSocatTunnels => delete_all_socat_tunnels(session),
+        Subcommand::CreatePortForward {
+            namespace,
+            resource_type,
+            name,
+            local_port,
+            remote_port,
+        } => create_port_forward(
+            session,
+            &namespace,
+            &resource_type,
+            &name,
+            local_port,
+            remote_port,
+        ),
+        Subcommand::ListPortForwards => fetch_port_forwards(session),
+        Subcommand::DeletePortForwardByIndex(index) => delete_port_forward_by_index(session, index),
+        Subcommand::DeleteAllPortForwards => delete_all_port_forwards(session),
+        Subcommand::CreateMinikubeMount {
+            host_path,
+            minikube_path,
+        } => create_minikube_mount(session, &host_path, &minikube_path),
+        Subcommand::ListMinikubeMounts => fetch_minikube_mounts(session),
+        Subcommand::DeleteMinikubeMountByIndex(index) => {
+            delete_minikube_mount_by_index(session, index)
+        }
+        Subcommand::DeleteAllMinikubeMounts => delete_all_minikube_mounts(session),
+        Subcommand::StartMinikubeTunnel { bind_address } => {
+            let result = match bind_address {
+                Some(bind_address) => set_bind_address(session, bind_address)?,
+                None => PrintableResults(None, Vec::new()),
+            };
+
+            if check_minikube_tunnel(session)? {
+                Ok(result)
+            } else {
+                create_minikube_tunnel(session)
+            }
+        }
+        Subcommand::StopMinikubeTunnel => stop_minikube_tunnel(session),
+        Subcommand::ListLogs => fetch_logs(session),
+        Subcommand::ShowLog { index, lines } => show_log(session, index, lines),
+        Subcommand::CleanUp => clean_up(session),
+        Subcommand::Apply { init_file_path } => match get_init_file_path(session, init_file_path) {
+            Some(init_file_path) => apply_init_file(session, &init_file_path),
+            None => Err(Error::NotFound(String::from(
+                "No initialization file has been found",
+            ))),
+        },
+        Subcommand::Validate { init_file_path } => {
+            match get_init_file_path(session, init_file_path) {
+                Some(init_file_path) => validate_init_file(&init_file_path),
+                None => Err(Error::NotFound(String::from(
+                    "No initialization file has been found",
+                ))),
+            }
+        }
+        Subcommand::Export { path, overwrite } => export_init_file(session, &path, overwrite),
+        Subcommand::Forward {
+            protocol,
+            listening_port,
+            connect_host,
+            connect_port,
+            stats_path,
+        } => run_forwarder(
+            &protocol,
+            listening_port,
+            &connect_host,
+            connect_port,
+            &stats_path,
+        )
+        .map(|_| PrintableResults(None, Vec::new())),
+        Subcommand::Reconnect { args } => {
+            run_reconnecting_port_forward(&args).map(|_| PrintableResults(None, Vec::new()))
+        }
+    };
+
+    match result {
+        Ok(ChildProcess(_)) => {
+            let (_, stdout, _) = process_exited_with_success(result);
+
+            Ok(PrintableResults(None, stdout.into_iter().collect()))
+        }
+        result => result,
+    }
+}
+
+fn parse_matches(
+    options: &Options,
+    args: &[String],
+    min_free: usize,
+    max_free: usize,
+) -> Result<Matches, Error> {
+    let matches = options
+        .parse(args)
+        .map_err(|x| Error::Input(x.to_string()))?;
+
+    if matches.free.len() < min_free {
+        return Err(Error::Input(String::from("Missing required arguments")));
+    }
+
+    if matches.free.len() > max_free {
+        return Err(Error::Input(format!(
+            "Unexpected arguments: {}",
+            matches.free[max_free..].join(" ")
+        )));
+    }
+
+    Ok(matches)
+}
+
+fn parse_key_values(values: &[String]) -> Result<BTreeMap<String, String>, Error> {
+    values
+        .iter()
+        .map(|x| parse_key_value(x).map_err(Error::Input))
+        .collect()
+}
+
+fn parse_port(value: &str, what: &str) -> Result<u16, Error> {
+    match value.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(Error::Input(format!(
+            "{what} must be between 1 and {}",
+            u16::MAX
+        ))),
+    }
+}
+
+/// Converts an index given by the user, which starts from 1, into one that starts from 0.
+pub fn parse_index(index: usize) -> Result<usize, Error> {
+    if index == 0 {
+        return Err(Error::Input(String::from("Indexes start from 1")));
+    }
+
+    Ok(index - 1)
+}
+
+fn parse_index_str(value: &str) -> Result<usize, Error> {
+    parse_index(
+        value
+            .parse::<usize>()
+            .map_err(|_| Error::Input(format!("Failed to parse {value} as an index")))?,
+    )
+}
@@ -0,0 +1,418 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::health::HealthCheck;
+use crate::registry::{fetch_registered_processes, take_exited_processes, RegistryEntry};
+use crate::{
+    create_minikube_mount, create_minikube_tunnel, create_minikube_tunnel_with,
+    create_port_forward, create_socat_tunnel, CommandExecutionResult, Error, Session, TunnelEngine,
+    MINIKUBE_MOUNT_KIND, MINIKUBE_TUNNEL_KIND, PORT_FORWARD_KIND, SOCAT_TUNNEL_KIND,
+};
+
+/// How often the supervisor checks on the resources of its session.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The longest time the supervisor waits before retrying a failed restart.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Restarts the socat tunnels, port forwards, minikube mounts and the minikube tunnel started in a
+/// session, when their process exits without having been deleted, eg, after the laptop has been
+/// asleep, minikube has been restarted or the pod of a port forward has been replaced.
+/// Failed restarts are retried with an exponential back-off and every restart is recorded as an
+/// event, to be reported to the user.
+#[derive(Default)]
+pub struct Supervisor {
+    state: Mutex<SupervisorState>,
+    stopped: AtomicBool,
+}
+
+#[derive(Default)]
+struct SupervisorState {
+    pending: Vec<PendingRestart>,
+    events: Vec<String>,
+}
+
+struct PendingRestart {
+    entry: RegistryEntry,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::default()
+    }
+
+    /// Starts a thread which checks on the session's resources for as long as `kube-minion` runs.
+    pub fn start(session: Arc<Session>) -> Arc<Supervisor> {
+        let supervisor = Arc::new(Supervisor::new());
+        let thread_supervisor = supervisor.clone();
+
+        thread::spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+
+            thread_supervisor.check(&session);
+        });
+
+        supervisor
+    }
+
+    /// Restarts the resources of the session whose process has exited, unless their back-off has
+    /// not elapsed yet.
+    pub fn check(&self, session: &Session) {
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let now = Instant::now();
+
+        let exited = match take_exited_processes(session) {
+            Ok(exited) => exited,
+            Err(error) => {
+                self.push_event(format!("Failed to check on the running resources: {error}"));
+                return;
+            }
+        };
+
+        let due: Vec<PendingRestart> = {
+            let mut state = self.state.lock().unwrap();
+
+            state
+                .pending
+                .extend(exited.into_iter().map(|entry| PendingRestart {
+                    entry,
+                    attempts: 0,
+                    next_attempt: now,
+                }));
+
+            let (due, pending) = state.pending.drain(..).partition(|x| x.next_attempt <= now);
+            state.pending = pending;
+
+            due
+        };
+
+        for mut restart in due {
+            let description = describe_entry(&restart.entry);
+
+            match restart_entry(session, &restart.entry) {
+                Ok(true) => self.push_event(format!("Restarted the {description}")),
+                Ok(false) => {}
+                Err(error) => {
+                    restart.attempts += 1;
+
+                    let backoff =
+                        Duration::from_secs(1 << restart.attempts.min(9)).min(MAX_BACKOFF);
+                    restart.next_attempt = Instant::now() + backoff;
+
+                    self.push_event(format!(
+                        "Failed to restart the {description}, retrying in {}s: {error}",
+                        backoff.as_secs()
+                    ));
+
+                    self.state.lock().unwrap().pending.push(restart);
+                }
+            }
+        }
+    }
+
+    /// Stops restarting resources, eg, before they are cleaned up.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns the events recorded since the last call.
+    pub fn take_events(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().events)
+    }
+
+    fn push_event(&self, event: String) {
+        self.state.lock().unwrap().events.push(event);
+    }
+}
+
+/// Restarts the resource of an exited process and returns whether it has been restarted, which
+/// is not the case when an equivalent resource has already been started in the meantime.
+fn restart_entry(session: &Session, entry: &RegistryEntry) -> Result<bool, Error> {
+    let already_running = fetch_registered_processes(session, &entry.kind)?
+        .iter()
+        .any(|x| {
+            x.spec == entry.spec
+                || x.kind == MINIKUBE_TUNNEL_KIND
+                    && x.spec_str("profile") == entry.spec_str("profile")
+        });
+
+    if already_running {
+        return Ok(false);
+    }
+
+    let result: CommandExecutionResult = match entry.kind.as_str() {
+        SOCAT_TUNNEL_KIND => create_socat_tunnel(
+            session,
+            &entry.spec_str("protocol"),
+            entry.spec_u16("listeningPort"),
+            &entry.spec_str("connectHost"),
+            entry.spec_u16("connectPort"),
+            &HealthCheck::from_spec(&entry.spec),
+            TunnelEngine::from_spec(entry),
+        ),
+        PORT_FORWARD_KIND => create_port_forward(
+            session,
+            &entry.spec_str("namespace"),
+            &entry.spec_str("resourceType"),
+            &entry.spec_str("name"),
+            entry.spec_u16("localPort"),
+            entry.spec_u16("remotePort"),
+        ),
+        MINIKUBE_MOUNT_KIND => create_minikube_mount(
+            session,
+            &entry.spec_str("hostPath"),
+            &entry.spec_str("minikubePath"),
+        ),
+        // The tunnel is bound to the address it was bound to, rather than to the session's.
+        MINIKUBE_TUNNEL_KIND => match entry.spec_str("bindAddress").as_str() {
+            "" => create_minikube_tunnel(session),
+            bind_address => create_minikube_tunnel_with(session, bind_address),
+        },
+        kind => Err(Error::Input(format!("Unknown resource kind {kind}"))),
+    };
+
+    result.map(|_| true)
+}
+
+fn describe_entry(entry: &RegistryEntry) -> String {
+    match entry.kind.as_str() {
+        SOCAT_TUNNEL_KIND => format!(
+            "socat tunnel listening on port {} and connecting to {}:{}",
+            entry.spec_u16("listeningPort"),
+            entry.spec_str("connectHost"),
+            entry.spec_u16("connectPort"),
+        ),
+        PORT_FORWARD_KIND => format!(
+            "port forward from local port {} to {}/{}/{}:{}",
+            entry.spec_u16("localPort"),
+            entry.spec_str("namespace"),
+            entry.spec_str("resourceType"),
+            entry.spec_str("name"),
+            entry.spec_u16("remotePort"),
+        ),
+        MINIKUBE_MOUNT_KIND => format!(
+            "minikube mount from host path {} to minikube path {}",
+            entry.spec_str("hostPath"),
+            entry.spec_str("minikubePath"),
+        ),
+        MINIKUBE_TUNNEL_KIND => {
+            format!("minikube tunnel bound to {}", entry.spec_str("bindAddress"))
+        }
+        kind => format!("{kind} with process id {}", entry.pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delete_all_socat_tunnels;
+    use crate::test_support::TestSession;
+
+    fn crash_all(session: &TestSession) {
+        for kind in [
+            SOCAT_TUNNEL_KIND,
+            PORT_FORWARD_KIND,
+            MINIKUBE_MOUNT_KIND,
+            MINIKUBE_TUNNEL_KIND,
+        ] {
+            for entry in fetch_registered_processes(session, kind).unwrap() {
+                session.runner().kill(entry.pid, sysinfo::Signal::Kill);
+            }
+        }
+    }
+
+    #[test]
+    fn check_restarts_exited_resources() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        create_minikube_tunnel(&session).unwrap();
+        crash_all(&session);
+
+        supervisor.check(&session);
+
+        assert_eq!(
+            session.runner.running(),
+            vec![
+                "minikube tunnel -c --bind-address 127.0.0.1",
+                "socat -lpkube-minion-socat tcp-listen:8080,fork,reuseaddr tcp:localhost:80",
+            ]
+        );
+        let mut events = supervisor.take_events();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                "Restarted the minikube tunnel bound to 127.0.0.1",
+                "Restarted the socat tunnel listening on port 8080 and connecting to localhost:80",
+            ]
+        );
+        assert!(supervisor.take_events().is_empty());
+    }
+
+    #[test]
+    fn check_restarts_the_minikube_tunnel_with_its_bind_address() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        session.set_bind_address(String::from("0.0.0.0"));
+        create_minikube_tunnel(&session).unwrap();
+        session.set_bind_address(String::from("127.0.0.1"));
+        crash_all(&session);
+
+        supervisor.check(&session);
+
+        assert_eq!(
+            session.runner.running(),
+            vec!["minikube tunnel -c --bind-address 0.0.0.0"]
+        );
+        assert_eq!(session.bind_address(), "127.0.0.1");
+    }
+
+    #[test]
+    fn check_leaves_the_resources_of_other_sessions_of_the_process_alone() {
+        let session = TestSession::new();
+        let other_session = Session::with_runner(session.settings(), session.runner.clone());
+        let supervisor = Supervisor::new();
+        create_minikube_tunnel(&session).unwrap();
+        crash_all(&session);
+
+        supervisor.check(&other_session);
+
+        assert!(session.runner.running().is_empty());
+
+        supervisor.check(&session);
+
+        assert_eq!(
+            session.runner.running(),
+            vec!["minikube tunnel -c --bind-address 127.0.0.1"]
+        );
+    }
+
+    #[test]
+    fn check_does_nothing_once_stopped() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        crash_all(&session);
+
+        supervisor.stop();
+        supervisor.check(&session);
+
+        assert!(session.runner.running().is_empty());
+    }
+
+    #[test]
+    fn check_does_not_restart_deleted_resources() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        delete_all_socat_tunnels(&session).unwrap();
+
+        supervisor.check(&session);
+
+        assert!(session.runner.running().is_empty());
+        assert!(supervisor.take_events().is_empty());
+    }
+
+    #[test]
+    fn check_does_not_restart_resources_started_again() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        crash_all(&session);
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+
+        supervisor.check(&session);
+
+        assert_eq!(session.runner.running().len(), 1);
+        assert!(supervisor.take_events().is_empty());
+    }
+
+    #[test]
+    fn check_backs_off_after_a_failed_restart() {
+        let session = TestSession::new();
+        let supervisor = Supervisor::new();
+        create_socat_tunnel(
+            &session,
+            "tcp",
+            8080,
+            "localhost",
+            80,
+            &HealthCheck::Tcp,
+            TunnelEngine::Socat,
+        )
+        .unwrap();
+        crash_all(&session);
+        session.runner.exit_on_spawn("socat");
+
+        supervisor.check(&session);
+        supervisor.check(&session);
+
+        let spawns = session
+            .runner
+            .calls()
+            .iter()
+            .filter(|x| x.starts_with("socat"))
+            .count();
+        assert_eq!(spawns, 2);
+        assert_eq!(
+            supervisor.take_events(),
+            vec![
+                "Failed to restart the socat tunnel listening on port 8080 and connecting to \
+                localhost:80, retrying in 2s: Failed to verify if socat tunnel has been started"
+            ]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{env, fs, process, thread};
+
+use crate::kube_api::connect_kube_api;
+use crate::{CommandExecutionResult, CommandResultType::*, FakeProcessRunner, Session, Settings};
+
+static NEXT_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A session backed by a [`FakeProcessRunner`] and a state directory of its own, which is removed
+/// when the session is dropped.
+/// The runner answers as if the current kubectl context belonged to the active minikube profile.
+/// The session runs kubectl, unless it is connected to a [`StubApiServer`].
+pub struct TestSession {
+    session: Session,
+    pub runner: Arc<FakeProcessRunner>,
+}
+
+impl TestSession {
+    pub fn new() -> Self {
+        let state_dir = env::temp_dir().join(format!(
+            "kube-minion-test-{}-{}",
+            process::id(),
+            NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let runner = Arc::new(FakeProcessRunner::new());
+        runner.respond_ok("kubectl config current-context", "minikube\n");
+        runner.respond_ok("minikube profile", "minikube\n");
+        let session = Session::with_runner(
+            Settings {
+                state_dir,
+                use_kubectl: true,
+                ..Settings::default()
+            },
+            runner.clone(),
+        );
+
+        TestSession { session, runner }
+    }
+
+    /// Connects the session to a stub API server through a kubeconfig file whose current context
+    /// is minikube.
+    pub fn connect_kube_api(&self, server: &StubApiServer) {
+        fs::create_dir_all(self.state_dir()).unwrap();
+
+        let kubeconfig = self.state_dir().join("kubeconfig");
+        fs::write(
+            &kubeconfig,
+            format!(
+                "current-context: minikube
+contexts:
+- name: minikube
+  context: {{cluster: minikube, user: minikube}}
+clusters:
+- name: minikube
+  cluster: {{server: '{}'}}
+users:
+- name: minikube
+  user: {{token: secret}}
+",
+                server.url
+            ),
+        )
+        .unwrap();
+
+        self.restore(Settings {
+            kubeconfig: Some(kubeconfig.to_string_lossy().to_string()),
+            use_kubectl: false,
+            ..self.settings()
+        });
+
+        connect_kube_api(self);
+    }
+}
+
+impl Deref for TestSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+impl Drop for TestSession {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.session.state_dir());
+    }
+}
+
+/// Returns, for each resource in a result, the values of the given fields as strings.
+pub fn resource_fields(result: CommandExecutionResult, fields: &[&str]) -> Vec<Vec<String>> {
+    match result {
+        Ok(Resources(_, resources)) => resources
+            .iter()
+            .map(|x| {
+                fields
+                    .iter()
+                    .map(|field| match x.fields.get(*field) {
+                        Some(serde_json::Value::String(value)) => value.clone(),
+                        Some(value) => value.to_string(),
+                        None => String::new(),
+                    })
+                    .collect()
+            })
+            .collect(),
+        Ok(_) => panic!("The result does not contain resources"),
+        Err(error) => panic!("The operation has failed: {error}"),
+    }
+}
+
+/// An HTTP server which stands in for the Kubernetes API server.
+/// It answers each request with the most recently registered response for a prefix of the request's
+/// method and path, or with a 404 Status object, and records the requests it receives.
+pub struct StubApiServer {
+    pub url: String,
+    responses: Arc<Mutex<Vec<(String, u16, String)>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubApiServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses: Arc<Mutex<Vec<(String, u16, String)>>> = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let responses = responses.clone();
+            let requests = requests.clone();
+
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let _ = serve_stub_request(stream, &responses, &requests);
+                }
+            });
+        }
+
+        StubApiServer {
+            url,
+            responses,
+            requests,
+        }
+    }
+
+    pub fn respond(&self, request: &str, status: u16, body: &str) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push((String::from(request), status, String::from(body)));
+    }
+
+    /// The requests received so far, as their method, path and query, followed by their body, if
+    /// any.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve_stub_request(
+    mut stream: TcpStream,
+    responses: &Mutex<Vec<(String, u16, String)>>,
+    requests: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+
+        let header = header.trim().to_lowercase();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("content-length:") {
+            content_length = value.trim().parse().unwrap_or_default();
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    // eg, GET /api/v1/services HTTP/1.1
+    let mut request = request_line
+        .split_whitespace()
+        .take(2)
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if !body.is_empty() {
+        request = format!("{request} {}", String::from_utf8_lossy(&body));
+    }
+    requests.lock().unwrap().push(request.clone());
+
+    let (status, body) = responses
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|(prefix, _, _)| request.starts_with(prefix.as_str()))
+        .map(|(_, status, body)| (*status, body.clone()))
+        .unwrap_or((
+            404,
+            String::from(r#"{"kind": "Status", "reason": "NotFound", "message": "not found"}"#),
+        ));
+
+    write!(
+        stream,
+        "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::Value;
+
+use crate::init_file::parse_init_config_json;
+use crate::load_balancer::{validate_port_name, validate_service_name};
+use crate::{CommandExecutionResult, CommandResultType::*};
+
+/// What the value of a key of the initialization file must be.
+enum Kind {
+    String,
+    /// An integer between the bounds, inclusive.
+    Integer(u64, u64),
+    /// One of the values, compared case-insensitively and ignoring surrounding whitespace.
+    OneOf(&'static [&'static str]),
+    Object(&'static [Field]),
+    /// An object of strings under any keys, eg, labels.
+    StringMap,
+    /// The name of a Kubernetes service.
+    ServiceName,
+    /// A port number or the name of a container port.
+    TargetPort,
+    /// The name of a port of a service.
+    PortName,
+    /// An array of at least as many objects with the given fields.
+    Array(&'static [Field], usize),
+}
+
+/// Whether a key of the initialization file must be given.
+enum Presence {
+    Optional,
+    Required,
+    /// Either the key or the other key must be given, but not both.
+    EitherOr(&'static str),
+    /// The key is optional, but cannot be given along with the other key, which supersedes it.
+    Excludes(&'static str),
+}
+
+struct Field {
+    key: &'static str,
+    kind: Kind,
+    presence: Presence,
+}
+
+const fn field(key: &'static str, kind: Kind, presence: Presence) -> Field {
+    Field {
+        key,
+        kind,
+        presence,
+    }
+}
+
+const PORT: Kind = Kind::Integer(1, u16::MAX as u64);
+
+const LOAD_BALANCER_PROTOCOL: Kind = Kind::OneOf(&["tcp", "udp", "sctp"]);
+
+const PORT_MAPPING_FIELDS: &[Field] = &[
+    field("name", Kind::PortName, Presence::Optional),
+    field("port", PORT, Presence::Required),
+    field("targetPort", Kind::TargetPort, Presence::Optional),
+    field("protocol", LOAD_BALANCER_PROTOCOL, Presence::Optional),
+];
+
+const LOAD_BALANCER_FIELDS: &[Field] = &[
+    field("namespace", Kind::String, Presence::Optional),
+    field("resourceType", Kind::String, Presence::Optional),
+    field("name", Kind::String, Presence::Required),
+    field("port", PORT, Presence::EitherOr("ports")),
+    field("targetPort", Kind::TargetPort, Presence::Excludes("ports")),
+    field(
+        "protocol",
+        LOAD_BALANCER_PROTOCOL,
+        Presence::Excludes("ports"),
+    ),
+    field("serviceName", Kind::ServiceName, Presence::Optional),
+    field("labels", Kind::StringMap, Presence::Optional),
+    field("annotations", Kind::StringMap, Presence::Optional),
+    field(
+        "ports",
+        Kind::Array(PORT_MAPPING_FIELDS, 1),
+        Presence::Optional,
+    ),
+];
+
+const HEALTH_CHECK_FIELDS: &[Field] = &[
+    field("path", Kind::String, Presence::Required),
+    field(
+        "expectedStatus",
+        Kind::Integer(100, 599),
+        Presence::Optional,
+    ),
+];
+
+const SOCAT_TUNNEL_FIELDS: &[Field] = &[
+    field("protocol", Kind::OneOf(&["tcp", "udp"]), Presence::Optional),
+    field("listeningPort", PORT, Presence::Required),
+    field("connectHost", Kind::String, Presence::Optional),
+    field("connectPort", PORT, Presence::Required),
+    field(
+        "engine",
+        Kind::OneOf(&["socat", "builtin"]),
+        Presence::Optional,
+    ),
+    field(
+        "healthCheck",
+        Kind::Object(HEALTH_CHECK_FIELDS),
+        Presence::Optional,
+    ),
+];
+
+const PORT_FORWARD_FIELDS: &[Field] = &[
+    field("namespace", Kind::String, Presence::Optional),
+    field("resourceType", Kind::String, Presence::Optional),
+    field("name", Kind::String, Presence::Required),
+    field("localPort", PORT, Presence::Required),
+    field("remotePort", PORT, Presence::Optional),
+];
+
+const MINIKUBE_MOUNT_FIELDS: &[Field] = &[
+    field("hostPath", Kind::String, Presence::Required),
+    field("minikubePath", Kind::String, Presence::Required),
+];
+
+/// The keys of the initialization file, as `kube-minion.schema.json` describes them.
+const INIT_FILE_FIELDS: &[Field] = &[
+    field("$schema", Kind::String, Presence::Optional),
+    field(
+        "loadBalancers",
+        Kind::Array(LOAD_BALANCER_FIELDS, 0),
+        Presence::Optional,
+    ),
+    field(
+        "socatTunnels",
+        Kind::Array(SOCAT_TUNNEL_FIELDS, 0),
+        Presence::Optional,
+    ),
+    field(
+        "portForwards",
+        Kind::Array(PORT_FORWARD_FIELDS, 0),
+        Presence::Optional,
+    ),
+    field(
+        "minikubeMounts",
+        Kind::Array(MINIKUBE_MOUNT_FIELDS, 0),
+        Presence::Optional,
+    ),
+    field("defaultSocatConnectHost", Kind::String, Presence::Optional),
+    field(
+        "minikubeTunnelBindAddress",
+        Kind::String,
+        Presence::Optional,
+    ),
+    field("minikubeProfile", Kind::String, Presence::Optional),
+    field("kubeContext", Kind::String, Presence::Optional),
+    field("kubeconfig", Kind::String, Presence::Optional),
+];
+
+/// A problem found in an initialization file, at the position of the offending key or value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Checks an initialization file and reports whether it is valid, listing all of its problems
+/// otherwise.
+pub fn validate_init_file(init_file_path: &str) -> CommandExecutionResult {
+    parse_init_config_json(init_file_path)?;
+
+    Ok(PrintableResults(
+        None,
+        vec![format!("{init_file_path} is valid")],
+    ))
+}
+
+/// Finds all the problems of a parsed initialization file, ie, unknown keys, missing required
+/// keys, values of the wrong type, out-of-range ports and unsupported values, eg, protocols.
+/// `content` is the file's content, with its comments replaced by whitespace, in which the problems
+/// are located.
+pub fn validate_init_config(content: &str, init_config: &Value) -> Vec<Problem> {
+    let mut problems: Vec<(String, String)> = Vec::new();
+
+    validate_object(init_config, INIT_FILE_FIELDS, "", &mut problems);
+
+    if problems.is_empty() {
+        return Vec::new();
+    }
+
+    let positions = locate(content);
+
+    let mut problems: Vec<Problem> = problems
+        .into_iter()
+        .map(|(pointer, message)| {
+            // A key that is not found, eg, because it contains an escape sequence, is reported at
+            // the closest enclosing value that is.
+            let mut pointer = pointer.as_str();
+            let (line, column) = loop {
+                match positions.get(pointer) {
+                    Some(position) => break *position,
+                    None if pointer.is_empty() => break (1, 1),
+                    None => pointer = &pointer[..pointer.rfind('/').unwrap_or_default()],
+                }
+            };
+
+            Problem {
+                line,
+                column,
+                message,
+            }
+        })
+        .collect();
+
+    // The keys of an object are checked in alphabetical order, rather than in the file's order.
+    problems.sort_by_key(|x| (x.line, x.column));
+
+    problems
+}
+
+/// Lists the problems of an initialization file, one per line, prefixed by their position.
+pub fn describe_problems(init_file_path: &str, problems: &[Problem]) -> String {
+    let problems: Vec<String> = problems
+        .iter()
+        .map(|x| format!("{init_file_path}:{}:{}: {}", x.line, x.column, x.message))
+        .collect();
+
+    format!(
+        "{init_file_path} is not a valid initialization file:\n{}",
+        problems.join("\n")
+    )
+}
+
+fn validate_object(
+    value: &Value,
+    fields: &[Field],
+    pointer: &str,
+    problems: &mut Vec<(String, String)>,
+) {
+    let Some(object) = value.as_object() else {
+        problems.push((pointer.to_string(), String::from("expected a JSON object")));
+        return;
+    };
+
+    for (key, value) in object {
+        let key_pointer = format!("{pointer}/{}", escape(key));
+
+        match fields.iter().find(|x| x.key == key) {
+            Some(field) => validate_value(value, field, &key_pointer, problems),
+            None => {
+                let suggestion = fields
+                    .iter()
+                    .find(|x| x.key.eq_ignore_ascii_case(key))
+                    .map(|x| format!(" (did you mean {}?)", x.key))
+                    .unwrap_or_default();
+
+                problems.push((key_pointer, format!("unknown key {key}{suggestion}")));
+            }
+        }
+    }
+
+    for field in fields {
+        let key = field.key;
+
+        let problem = match field.presence {
+            Presence::Required if !object.contains_key(key) => Some(format!("{key} is required")),
+            Presence::EitherOr(other) => {
+                match (object.contains_key(key), object.contains_key(other)) {
+                    (false, false) => Some(format!("either {key} or {other} is required")),
+                    (true, true) => Some(format!("{key} and {other} cannot both be given")),
+                    _ => None,
+                }
+            }
+            Presence::Excludes(other) if object.contains_key(key) && object.contains_key(other) => {
+                problems.push((
+                    format!("{pointer}/{}", escape(key)),
+                    format!("{key} cannot be given along with {other}"),
+                ));
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(problem) = problem {
+            problems.push((pointer.to_string(), problem));
+        }
+    }
+}
+
+fn validate_value(
+    value: &Value,
+    field: &Field,
+    pointer: &str,
+    problems: &mut Vec<(String, String)>,
+) {
+    let key = field.key;
+
+    let problem = match &field.kind {
+        Kind::String if !value.is_string() => Some(format!("{key} must be a string")),
+        Kind::Integer(min, max) => match value.as_u64() {
+            Some(x) if (*min..=*max).contains(&x) => None,
+            _ => Some(format!("{key} must be an integer between {min} and {max}")),
+        },
+        Kind::OneOf(values) => match value.as_str() {
+            Some(x) if values.contains(&x.trim().to_lowercase().as_str()) => None,
+            _ => Some(format!("{key} must be one of {}", values.join(", "))),
+        },
+        Kind::Object(fields) => {
+            validate_object(value, fields, pointer, problems);
+            None
+        }
+        Kind::StringMap => match value.as_object() {
+            Some(object) => {
+                for k in object.keys().filter(|k| !object[*k].is_string()) {
+                    problems.push((
+                        format!("{pointer}/{}", escape(k)),
+                        format!("{key}.{k} must be a string"),
+                    ));
+                }
+                None
+            }
+            None => Some(format!("{key} must be an object of strings")),
+        },
+        Kind::ServiceName => match value.as_str() {
+            Some(x) => validate_service_name(x).err(),
+            None => Some(format!("{key} must be a string")),
+        },
+        Kind::PortName => match value.as_str() {
+            Some(x) => validate_port_name(x).err(),
+            None => Some(format!("{key} must be a string")),
+        },
+        Kind::TargetPort => match value {
+            Value::String(x) => validate_port_name(x).err(),
+            _ => match value.as_u64() {
+                Some(x) if (1..=u16::MAX as u64).contains(&x) => None,
+                _ => Some(format!(
+                    "{key} must be an integer between 1 and {} or a port name",
+                    u16::MAX
+                )),
+            },
+        },
+        Kind::Array(_, min_items) if value.as_array().is_some_and(|x| x.len() < *min_items) => {
+            Some(format!("{key} requires at least {min_items} item(s)"))
+        }
+        Kind::Array(fields, _) => match value.as_array() {
+            Some(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    validate_object(item, fields, &format!("{pointer}/{i}"), problems);
+                }
+                None
+            }
+            None => Some(format!("{key} must be an array")),
+        },
+        Kind::String => None,
+    };
+
+    if let Some(problem) = problem {
+        problems.push((pointer.to_string(), problem));
+    }
+}
+
+/// Escapes a key for a JSON pointer, as RFC 6901 requires.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Maps the JSON pointer of every value of a valid JSON document to its 1-based line and column,
+/// which, for the value of an object's key, is the position of the key.
+fn locate(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut locator = Locator {
+        chars: content.chars().peekable(),
+        line: 1,
+        column: 1,
+        positions: HashMap::new(),
+    };
+
+    locator.value(String::new());
+
+    locator.positions
+}
+
+struct Locator<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl Locator<'_> {
+    fn value(&mut self, pointer: String) {
+        self.skip_whitespace();
+
+        let position = (self.line, self.column);
+
+        match self.chars.peek() {
+            Some('{') => {
+                self.next();
+
+                loop {
+                    self.skip_whitespace();
+
+                    match self.chars.peek() {
+                        Some('"') => {
+                            let key_position = (self.line, self.column);
+                            let key_pointer = format!("{pointer}/{}", escape(&self.string()));
+                            self.positions.insert(key_pointer.clone(), key_position);
+
+                            self.skip_whitespace();
+                            self.next(); // :
+                            self.value(key_pointer);
+                        }
+                        Some(',') => self.next(),
+                        _ => {
+                            self.next(); // }
+                            break;
+                        }
+                    }
+                }
+            }
+            Some('[') => {
+                self.next();
+
+                for i in 0.. {
+                    self.skip_whitespace();
+
+                    match self.chars.peek() {
+                        Some(']') | None => {
+                            self.next();
+                            break;
+                        }
+                        _ => self.value(format!("{pointer}/{i}")),
+                    }
+
+                    self.skip_whitespace();
+                    if self.chars.peek() == Some(&',') {
+                        self.next();
+                    }
+                }
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while self
+                    .chars
+                    .peek()
+                    .is_some_and(|x| !matches!(x, ',' | ']' | '}') && !x.is_whitespace())
+                {
+                    self.next();
+                }
+            }
+        }
+
+        self.positions.entry(pointer).or_insert(position);
+    }
+
+    /// Reads a string, keeping the characters of its escape sequences as they are.
+    fn string(&mut self) -> String {
+        let mut string = String::new();
+
+        self.next(); // "
+
+        while let Some(c) = self.chars.peek().copied() {
+            self.next();
+
+            match c {
+                '"' => break,
+                '\\' => {
+                    if let Some(c) = self.chars.peek().copied() {
+                        self.next();
+                        string.push(c);
+                    }
+                }
+                c => string.push(c),
+            }
+        }
+
+        string
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|x| x.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn next(&mut self) {
+        match self.chars.next() {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(content: &str) -> Vec<String> {
+        let init_config: Value = serde_json::from_str(content).unwrap();
+
+        validate_init_config(content, &init_config)
+            .into_iter()
+            .map(|x| format!("{}:{}: {}", x.line, x.column, x.message))
+            .collect()
+    }
+
+    #[test]
+    fn a_valid_init_file_has_no_problems() {
+        assert!(validate(
+            &std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/kube-minion.template.json"
+            ))
+            .unwrap()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn all_problems_are_reported_at_their_position() {
+        assert_eq!(
+            validate(
+                r#"{
+  "loadBalancers": [
+    {"name": "web", "port": 8080, "targetport": 80},
+    {"port": 70000}
+  ],
+  "socatTunnels": [
+    {"protocol": "sctp", "listeningPort": 5432, "connectPort": "5432",
+     "healthCheck": {"path": "/", "expectedStatus": 700}}
+  ],
+  "minikubeMounts": {},
+  "kubeContext": 1
+}"#
+            ),
+            vec![
+                "3:35: unknown key targetport (did you mean targetPort?)",
+                "4:5: name is required",
+                "4:6: port must be an integer between 1 and 65535",
+                "7:6: protocol must be one of tcp, udp",
+                "7:49: connectPort must be an integer between 1 and 65535",
+                "8:35: expectedStatus must be an integer between 100 and 599",
+                "10:3: minikubeMounts must be an array",
+                "11:3: kubeContext must be a string",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_port_keys_of_a_load_balancer_cannot_be_given_along_with_ports() {
+        assert_eq!(
+            validate(
+                r#"{
+  "loadBalancers": [
+    {"name": "web", "targetPort": 80, "protocol": "udp", "ports": [{"port": 8080}]}
+  ]
+}"#
+            ),
+            vec![
+                "3:21: targetPort cannot be given along with ports",
+                "3:39: protocol cannot be given along with ports",
+            ]
+        );
+    }
+
+    #[test]
+    fn port_names_and_target_ports_are_checked() {
+        assert_eq!(
+            validate(
+                r#"{
+  "loadBalancers": [
+    {"name": "web", "ports": [{"port": 8080, "targetPort": "http", "name": "web http"},
+                              {"port": 8081, "targetPort": "HTTP"},
+                              {"port": 8082, "targetPort": 0}]}
+  ]
+}"#
+            ),
+            vec![
+                "3:68: web http is not a valid port name (expected up to 15 lower case letters, \
+                digits and dashes, with at least one letter)",
+                "4:46: HTTP is not a valid port name (expected up to 15 lower case letters, \
+                digits and dashes, with at least one letter)",
+                "5:46: targetPort must be an integer between 1 and 65535 or a port name",
+            ]
+        );
+    }
+}